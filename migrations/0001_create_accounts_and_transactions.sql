@@ -0,0 +1,23 @@
+-- Money and timestamps are stored as text so SQLite never coerces them into
+-- floating point. The declared type tells the backend how to decode a column.
+CREATE TABLE accounts (
+    document_id TEXT PRIMARY KEY,
+    account_number TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    phone TEXT NOT NULL,
+    balance DECIMAL_TEXT NOT NULL,
+    created_at TIMESTAMP_TEXT NOT NULL,
+    updated_at TIMESTAMP_TEXT NOT NULL
+);
+
+CREATE TABLE transactions (
+    document_id TEXT PRIMARY KEY,
+    transaction_type TEXT NOT NULL,
+    amount DECIMAL_TEXT NOT NULL,
+    sender_account_number TEXT,
+    recipient_account_number TEXT NOT NULL,
+    created_at TIMESTAMP_TEXT NOT NULL
+);
+
+CREATE INDEX transactions_sender_idx ON transactions (sender_account_number);
+CREATE INDEX transactions_recipient_idx ON transactions (recipient_account_number);
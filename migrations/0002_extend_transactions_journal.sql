@@ -0,0 +1,8 @@
+ALTER TABLE transactions ADD COLUMN id TEXT;
+ALTER TABLE transactions ADD COLUMN sender_balance_before DECIMAL_TEXT;
+ALTER TABLE transactions ADD COLUMN sender_balance_after DECIMAL_TEXT;
+ALTER TABLE transactions ADD COLUMN recipient_balance_before DECIMAL_TEXT;
+ALTER TABLE transactions ADD COLUMN recipient_balance_after DECIMAL_TEXT;
+ALTER TABLE transactions ADD COLUMN status TEXT;
+
+CREATE UNIQUE INDEX transactions_id_idx ON transactions (id);
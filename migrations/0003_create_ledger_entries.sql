@@ -0,0 +1,12 @@
+CREATE TABLE ledger_entries (
+    document_id TEXT PRIMARY KEY,
+    id TEXT NOT NULL,
+    transaction_id TEXT NOT NULL,
+    debit_account_number TEXT NOT NULL,
+    credit_account_number TEXT NOT NULL,
+    amount DECIMAL_TEXT NOT NULL,
+    created_at TIMESTAMP_TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX ledger_entries_id_idx ON ledger_entries (id);
+CREATE INDEX ledger_entries_transaction_id_idx ON ledger_entries (transaction_id);
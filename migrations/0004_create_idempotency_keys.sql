@@ -0,0 +1,9 @@
+CREATE TABLE idempotency_keys (
+    document_id TEXT PRIMARY KEY,
+    scope TEXT NOT NULL,
+    idempotency_key TEXT NOT NULL,
+    request_fingerprint TEXT NOT NULL,
+    transaction_id TEXT NOT NULL,
+    created_at TIMESTAMP_TEXT NOT NULL,
+    UNIQUE (scope, idempotency_key)
+);
@@ -0,0 +1,3 @@
+-- Rows written before multi-currency support are in dollars.
+ALTER TABLE accounts ADD COLUMN currency TEXT NOT NULL DEFAULT 'USD';
+ALTER TABLE transactions ADD COLUMN currency TEXT NOT NULL DEFAULT 'USD';
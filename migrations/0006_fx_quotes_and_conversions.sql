@@ -0,0 +1,13 @@
+CREATE TABLE fx_quotes (
+    document_id TEXT PRIMARY KEY,
+    id TEXT NOT NULL UNIQUE,
+    from_currency TEXT NOT NULL,
+    to_currency TEXT NOT NULL,
+    rate DECIMAL_TEXT NOT NULL,
+    created_at TIMESTAMP_TEXT NOT NULL,
+    expires_at TIMESTAMP_TEXT NOT NULL
+);
+
+ALTER TABLE transactions ADD COLUMN fx_rate DECIMAL_TEXT;
+ALTER TABLE transactions ADD COLUMN converted_amount DECIMAL_TEXT;
+ALTER TABLE transactions ADD COLUMN converted_currency TEXT;
@@ -0,0 +1,2 @@
+ALTER TABLE accounts ADD COLUMN closure_reason TEXT;
+ALTER TABLE accounts ADD COLUMN closed_at TIMESTAMP_TEXT;
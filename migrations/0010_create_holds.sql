@@ -0,0 +1,15 @@
+CREATE TABLE holds (
+    document_id TEXT PRIMARY KEY,
+    id TEXT NOT NULL,
+    account_number TEXT NOT NULL,
+    amount DECIMAL_TEXT NOT NULL,
+    captured_amount DECIMAL_TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    status TEXT NOT NULL,
+    created_at TIMESTAMP_TEXT NOT NULL,
+    expires_at TIMESTAMP_TEXT NOT NULL,
+    updated_at TIMESTAMP_TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX holds_id_idx ON holds (id);
+CREATE INDEX holds_account_number_idx ON holds (account_number);
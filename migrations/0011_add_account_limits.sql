@@ -0,0 +1,4 @@
+ALTER TABLE accounts ADD COLUMN tier TEXT NOT NULL DEFAULT 'STANDARD';
+ALTER TABLE accounts ADD COLUMN max_transaction_amount DECIMAL_TEXT;
+ALTER TABLE accounts ADD COLUMN max_daily_debit_total DECIMAL_TEXT;
+ALTER TABLE accounts ADD COLUMN max_daily_transfer_count INTEGER;
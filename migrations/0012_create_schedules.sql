@@ -0,0 +1,37 @@
+CREATE TABLE schedules (
+    document_id TEXT PRIMARY KEY,
+    id TEXT NOT NULL,
+    sender_account_number TEXT NOT NULL,
+    recipient_account_number TEXT NOT NULL,
+    amount DECIMAL_TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    frequency TEXT NOT NULL,
+    cron TEXT,
+    start_at TIMESTAMP_TEXT NOT NULL,
+    end_at TIMESTAMP_TEXT,
+    status TEXT NOT NULL,
+    due_at TIMESTAMP_TEXT,
+    next_run_at TIMESTAMP_TEXT,
+    failed_attempts INTEGER NOT NULL,
+    last_error TEXT,
+    created_at TIMESTAMP_TEXT NOT NULL,
+    updated_at TIMESTAMP_TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX schedules_id_idx ON schedules (id);
+CREATE INDEX schedules_sender_account_number_idx ON schedules (sender_account_number);
+CREATE INDEX schedules_status_idx ON schedules (status);
+
+CREATE TABLE schedule_runs (
+    document_id TEXT PRIMARY KEY,
+    id TEXT NOT NULL,
+    schedule_id TEXT NOT NULL,
+    due_at TIMESTAMP_TEXT NOT NULL,
+    attempt INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    transaction_id TEXT,
+    error TEXT,
+    created_at TIMESTAMP_TEXT NOT NULL
+);
+
+CREATE INDEX schedule_runs_schedule_id_idx ON schedule_runs (schedule_id);
@@ -0,0 +1,2 @@
+CREATE INDEX ledger_entries_debit_account_number_idx ON ledger_entries (debit_account_number, created_at);
+CREATE INDEX ledger_entries_credit_account_number_idx ON ledger_entries (credit_account_number, created_at);
@@ -0,0 +1,11 @@
+CREATE TABLE api_keys (
+    document_id TEXT PRIMARY KEY,
+    id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    key_hash TEXT NOT NULL,
+    created_at TIMESTAMP_TEXT NOT NULL,
+    revoked_at TIMESTAMP_TEXT
+);
+
+CREATE UNIQUE INDEX api_keys_id_idx ON api_keys (id);
+CREATE UNIQUE INDEX api_keys_key_hash_idx ON api_keys (key_hash);
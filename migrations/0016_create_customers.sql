@@ -0,0 +1,19 @@
+CREATE TABLE customers (
+    document_id TEXT PRIMARY KEY,
+    id TEXT NOT NULL,
+    first_name TEXT NOT NULL,
+    last_name TEXT NOT NULL,
+    phone TEXT NOT NULL,
+    email TEXT,
+    address TEXT,
+    date_of_birth TEXT,
+    kyc_status TEXT NOT NULL,
+    created_at TIMESTAMP_TEXT NOT NULL,
+    updated_at TIMESTAMP_TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX customers_id_idx ON customers (id);
+
+ALTER TABLE accounts ADD COLUMN customer_id TEXT;
+
+CREATE INDEX accounts_customer_id_idx ON accounts (customer_id);
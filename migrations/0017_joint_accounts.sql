@@ -0,0 +1,43 @@
+ALTER TABLE accounts ADD COLUMN signing_rule TEXT NOT NULL DEFAULT 'ANY_ONE';
+
+CREATE TABLE account_owners (
+    document_id TEXT PRIMARY KEY,
+    account_number TEXT NOT NULL,
+    customer_id TEXT NOT NULL,
+    created_at TIMESTAMP_TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX account_owners_account_customer_idx ON account_owners (account_number, customer_id);
+CREATE INDEX account_owners_customer_id_idx ON account_owners (customer_id);
+
+CREATE TABLE pending_transactions (
+    document_id TEXT PRIMARY KEY,
+    id TEXT NOT NULL,
+    sender_account_number TEXT NOT NULL,
+    recipient_account_number TEXT NOT NULL,
+    amount DECIMAL_TEXT NOT NULL,
+    currency TEXT,
+    fx_quote_id TEXT,
+    idempotency_scope TEXT,
+    idempotency_key TEXT,
+    request_fingerprint TEXT NOT NULL,
+    status TEXT NOT NULL,
+    transaction_id TEXT,
+    failure_reason TEXT,
+    created_at TIMESTAMP_TEXT NOT NULL,
+    expires_at TIMESTAMP_TEXT NOT NULL,
+    updated_at TIMESTAMP_TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX pending_transactions_id_idx ON pending_transactions (id);
+CREATE INDEX pending_transactions_sender_idx ON pending_transactions (sender_account_number);
+CREATE INDEX pending_transactions_idempotency_key_idx ON pending_transactions (idempotency_scope, idempotency_key);
+
+CREATE TABLE transaction_approvals (
+    document_id TEXT PRIMARY KEY,
+    pending_transaction_id TEXT NOT NULL,
+    customer_id TEXT NOT NULL,
+    approved_at TIMESTAMP_TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX transaction_approvals_idx ON transaction_approvals (pending_transaction_id, customer_id);
@@ -0,0 +1,111 @@
+use crate::domain::AppState;
+use crate::service::{AuthService, ImportService};
+use std::fs::File;
+
+const USAGE: &str = "Usage: bank-service [import <accounts.csv> | api-key create <name> <teller|admin|auditor> | api-key list | api-key revoke <id>]";
+
+/// Runs a command given on the command line instead of the server and
+/// returns the exit code.
+pub async fn run(app_state: &AppState, args: &[String]) -> i32 {
+    match args {
+        [command, path] if command == "import" => import_accounts(app_state, path).await,
+        [command, args @ ..] if command == "api-key" => api_key(app_state, args).await,
+        _ => {
+            eprintln!("{}", USAGE);
+            2
+        }
+    }
+}
+
+/// Prints a line per row of the file and fails if any row did.
+async fn import_accounts(app_state: &AppState, path: &str) -> i32 {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("Unable to open {}: {}", path, e);
+            return 1;
+        }
+    };
+    let import_service = ImportService::new(
+        app_state.processor.clone(),
+        app_state.system_accounts.clone(),
+    );
+    let report = match import_service.import_accounts(file).await {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Import failed: {}", e.message());
+            return 1;
+        }
+    };
+    for result in &report.results {
+        match (&result.account_number, &result.error) {
+            (_, Some(error)) => println!("row {}: failed: {}", result.row, error.message),
+            (Some(account_number), None) => println!(
+                "row {}: opened {}{}",
+                result.row,
+                account_number,
+                result
+                    .transaction_id
+                    .as_ref()
+                    .map(|id| format!(", opening balance credited in {}", id))
+                    .unwrap_or_default()
+            ),
+            (None, None) => (),
+        }
+    }
+    println!("{} imported, {} failed", report.imported, report.failed);
+    if report.failed > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+/// Keys are printed once, when they are created.
+async fn api_key(app_state: &AppState, args: &[String]) -> i32 {
+    let auth_service = AuthService::new(app_state.processor.clone(), app_state.jwt.clone());
+    let result = match args {
+        [action, name, role] if action == "create" => match role.parse() {
+            Ok(role) => {
+                auth_service
+                    .create_api_key(name.clone(), role)
+                    .await
+                    .map(|(api_key, key)| {
+                        println!(
+                            "Created {} API key {} for {}",
+                            api_key.role, api_key.id, api_key.name
+                        );
+                        println!("{}", key);
+                    })
+            }
+            Err(e) => Err(e),
+        },
+        [action] if action == "list" => auth_service.find_api_keys().await.map(|api_keys| {
+            for api_key in api_keys {
+                let revoked = api_key
+                    .revoked_at
+                    .map(|at| format!(" revoked {}", at))
+                    .unwrap_or_default();
+                println!(
+                    "{} {} {} created {}{}",
+                    api_key.id, api_key.name, api_key.role, api_key.created_at, revoked
+                );
+            }
+        }),
+        [action, id] if action == "revoke" => auth_service
+            .revoke_api_key(id.clone())
+            .await
+            .map(|api_key| println!("Revoked API key {} of {}", api_key.id, api_key.name)),
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e.message());
+            1
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use super::{ledger, limits, Filter, LedgerTransaction};
+use crate::domain::{
+    CapturedHold, Currency, Hold, LimitPolicy, Posting, TransactionType, HOLDS_TABLE,
+};
+use crate::error::{AppError, ErrorType};
+use bigdecimal::BigDecimal;
+use ion_binary_rs::IonValue;
+use std::collections::HashMap;
+
+/// Loads the holds matching `filter`. Open holds past their expiry are marked
+/// expired on the way, which is how holds lapse without a background job.
+pub async fn find(tx: &mut dyn LedgerTransaction, filter: &Filter) -> Result<Vec<Hold>, AppError> {
+    let docs = tx.find(HOLDS_TABLE, filter).await?;
+    let mut holds = Hold::from_documents(docs)?;
+    for hold in holds.iter_mut() {
+        if hold.expire() {
+            save(tx, hold).await?;
+        }
+    }
+    Ok(holds)
+}
+
+/// Total each account still has reserved by open holds.
+pub fn held_by_account(holds: &[Hold]) -> HashMap<String, BigDecimal> {
+    let mut held: HashMap<String, BigDecimal> = HashMap::new();
+    for hold in holds.iter().filter(|hold| hold.status.is_open()) {
+        *held.entry(hold.account_number.clone()).or_default() += hold.remaining();
+    }
+    held
+}
+
+async fn find_hold(
+    tx: &mut dyn LedgerTransaction,
+    account_number: &str,
+    hold_id: &str,
+) -> Result<Hold, AppError> {
+    let filter = Filter::all()
+        .eq("id", IonValue::String(hold_id.to_string()))
+        .eq(
+            "account_number",
+            IonValue::String(account_number.to_string()),
+        );
+    match find(tx, &filter).await?.pop() {
+        Some(hold) => Ok(hold),
+        None => Err(AppError::from_type(ErrorType::HoldNotFound(
+            hold_id.to_string(),
+        ))),
+    }
+}
+
+async fn save(tx: &mut dyn LedgerTransaction, hold: &Hold) -> Result<(), AppError> {
+    let filter = Filter::all().eq("id", IonValue::String(hold.id.clone()));
+    let values = vec![
+        (
+            "captured_amount",
+            IonValue::Decimal(hold.captured_amount.clone()),
+        ),
+        ("status", IonValue::String(hold.status.to_string())),
+        ("updated_at", IonValue::DateTime(hold.updated_at)),
+    ];
+    tx.update(HOLDS_TABLE, &filter, values).await?;
+    Ok(())
+}
+
+/// Reserves `amount` of the account's available balance.
+pub async fn place(
+    tx: &mut dyn LedgerTransaction,
+    account_number: String,
+    amount: BigDecimal,
+    currency: Option<Currency>,
+    ttl: chrono::Duration,
+) -> Result<Hold, AppError> {
+    let account = ledger::find_account(tx, &account_number).await?;
+    account.ensure_can_send()?;
+    if let Some(currency) = currency {
+        if currency != account.currency {
+            let msg = format!(
+                "Amount is in {} but account {} holds {}",
+                currency, account_number, account.currency
+            );
+            return Err(AppError::new(Some(msg), ErrorType::CurrencyMismatch));
+        }
+    }
+    if amount <= BigDecimal::default() || !account.currency.fits(&amount) {
+        let msg = "Invalid hold amount".to_string();
+        return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+    }
+    if &amount > account.available_balance() {
+        return Err(AppError::from_type(ErrorType::InsufficientBalance));
+    }
+    let hold = Hold::new(account_number, amount, account.currency, ttl);
+    tx.insert(&hold).await?;
+    Ok(hold)
+}
+
+/// Settles part or all of a hold. The captured amount stops being reserved
+/// and is posted as a debit to `counterparty` (the cash-out account) or as a
+/// transfer to it, checked against the account's limits like any other.
+pub async fn capture(
+    tx: &mut dyn LedgerTransaction,
+    account_number: String,
+    hold_id: String,
+    amount: Option<BigDecimal>,
+    counterparty: String,
+    transaction_type: TransactionType,
+    policy: &LimitPolicy,
+) -> Result<CapturedHold, AppError> {
+    let mut hold = find_hold(tx, &account_number, &hold_id).await?;
+    let amount = hold.capture(amount)?;
+    save(tx, &hold).await?;
+    let posting = match transaction_type {
+        TransactionType::TRANSFER => Posting::Transfer {
+            sender_account_number: account_number,
+            recipient_account_number: counterparty,
+            amount,
+            conversion: None,
+        },
+        _ => Posting::DebitCredit {
+            account_number,
+            contra_account_number: counterparty,
+            amount,
+            transaction_type: TransactionType::DEBIT,
+        },
+    };
+    limits::check(tx, policy, &posting).await?;
+    let transaction = match posting {
+        Posting::Transfer {
+            sender_account_number,
+            recipient_account_number,
+            amount,
+            ..
+        } => {
+            ledger::transfer(
+                tx,
+                sender_account_number,
+                recipient_account_number,
+                amount,
+                None,
+            )
+            .await?
+        }
+        Posting::DebitCredit {
+            account_number,
+            contra_account_number,
+            amount,
+            transaction_type,
+        } => {
+            ledger::debit_credit(
+                tx,
+                account_number,
+                contra_account_number,
+                amount,
+                transaction_type,
+            )
+            .await?
+        }
+    };
+    Ok(CapturedHold { hold, transaction })
+}
+
+pub async fn release(
+    tx: &mut dyn LedgerTransaction,
+    account_number: String,
+    hold_id: String,
+) -> Result<Hold, AppError> {
+    let mut hold = find_hold(tx, &account_number, &hold_id).await?;
+    hold.release()?;
+    save(tx, &hold).await?;
+    Ok(hold)
+}
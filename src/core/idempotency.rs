@@ -0,0 +1,235 @@
+use super::{Filter, LedgerTransaction};
+use crate::domain::{
+    IdempotencyKey, IdempotencyRecord, Transaction, TransactionReceipt, IDEMPOTENCY_KEYS_TABLE,
+    TRANSACTIONS_TABLE,
+};
+use crate::error::{AppError, ErrorType};
+use ion_binary_rs::IonValue;
+use std::convert::TryFrom;
+
+/// Returns the receipt of the transaction stored under `idempotency_key`, if
+/// the key has been used before in its scope. A key first used with a
+/// different request is rejected.
+pub async fn replay(
+    tx: &mut dyn LedgerTransaction,
+    idempotency_key: Option<&IdempotencyKey>,
+) -> Result<Option<TransactionReceipt>, AppError> {
+    let idempotency_key = match idempotency_key {
+        Some(idempotency_key) => idempotency_key,
+        None => return Ok(None),
+    };
+    let filter = Filter::all()
+        .eq("scope", IonValue::String(idempotency_key.scope.clone()))
+        .eq(
+            "idempotency_key",
+            IonValue::String(idempotency_key.key.clone()),
+        );
+    let results = tx.find(IDEMPOTENCY_KEYS_TABLE, &filter).await?;
+    let record = match results.first() {
+        Some(doc) => IdempotencyRecord::try_from(doc)?,
+        None => return Ok(None),
+    };
+    if record.request_fingerprint != idempotency_key.request_fingerprint {
+        return Err(AppError::from_type(ErrorType::IdempotencyKeyReused));
+    }
+    let filter = Filter::all().eq("id", IonValue::String(record.transaction_id.clone()));
+    let results = tx.find(TRANSACTIONS_TABLE, &filter).await?;
+    let transaction = match results.first() {
+        Some(doc) => Transaction::try_from(doc)?,
+        None => {
+            let msg = format!(
+                "Journal entry {} for idempotency key {} is missing",
+                record.transaction_id, record.key
+            );
+            return Err(AppError::from_type(ErrorType::Custom(msg)));
+        }
+    };
+    info!(
+        "Replaying transaction {} for idempotency key {}",
+        transaction.id, record.key
+    );
+    Ok(Some(transaction.into()))
+}
+
+/// Stores the transaction behind `receipt` under `idempotency_key` in the
+/// same ledger transaction that produced it, so the key is only taken once
+/// the work has committed.
+pub async fn remember(
+    tx: &mut dyn LedgerTransaction,
+    idempotency_key: Option<IdempotencyKey>,
+    receipt: &TransactionReceipt,
+) -> Result<(), AppError> {
+    if let Some(idempotency_key) = idempotency_key {
+        let record = IdempotencyRecord::new(idempotency_key, receipt.transaction_id.clone());
+        tx.insert(&record).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{ledger, LedgerStorage, MemoryProcessor};
+    use crate::domain::{Account, Currency, TransactionType, TRANSACTIONS_TABLE};
+    use bigdecimal::BigDecimal;
+
+    async fn setup() -> (MemoryProcessor, String, String) {
+        let processor = MemoryProcessor::new();
+        let account = Account::new(
+            "Sam James".to_string(),
+            "2347038657970".to_string(),
+            Currency::USD,
+        );
+        let cash_in = Account::new("Cash In".to_string(), String::new(), Currency::USD);
+        processor.insert(&account).await.unwrap();
+        processor.insert(&cash_in).await.unwrap();
+        (processor, account.account_number, cash_in.account_number)
+    }
+
+    async fn credit(
+        processor: &MemoryProcessor,
+        account_number: &str,
+        cash_in: &str,
+        key: IdempotencyKey,
+    ) -> Result<TransactionReceipt, AppError> {
+        processor
+            .debit_credit(
+                account_number.to_string(),
+                cash_in.to_string(),
+                BigDecimal::from(25),
+                TransactionType::CREDIT,
+                Some(key),
+            )
+            .await
+    }
+
+    #[actix_rt::test]
+    async fn test_retry_with_same_key_is_applied_once() {
+        let (processor, account_number, cash_in) = setup().await;
+        let key = IdempotencyKey::new(
+            "key:teller".to_string(),
+            "abc".to_string(),
+            "CREDIT|25".to_string(),
+        )
+        .unwrap();
+
+        let first = credit(&processor, &account_number, &cash_in, key.clone())
+            .await
+            .unwrap();
+        let second = credit(&processor, &account_number, &cash_in, key)
+            .await
+            .unwrap();
+
+        assert_eq!(first.transaction_id, second.transaction_id);
+        assert_eq!(first.recipient_balance, second.recipient_balance);
+        let journal = processor
+            .query(TRANSACTIONS_TABLE, &Filter::all())
+            .await
+            .unwrap();
+        assert_eq!(journal.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_concurrent_requests_with_same_key_post_once() {
+        let (processor, account_number, cash_in) = setup().await;
+        let key = IdempotencyKey::new(
+            "key:teller".to_string(),
+            "abc".to_string(),
+            "CREDIT|25".to_string(),
+        )
+        .unwrap();
+        let mut first = processor.begin().await.unwrap();
+        let mut second = processor.begin().await.unwrap();
+        for tx in [&mut first, &mut second] {
+            assert!(replay(tx.as_mut(), Some(&key)).await.unwrap().is_none());
+            let receipt = ledger::debit_credit(
+                tx.as_mut(),
+                account_number.clone(),
+                cash_in.clone(),
+                BigDecimal::from(25),
+                TransactionType::CREDIT,
+            )
+            .await
+            .unwrap();
+            remember(tx.as_mut(), Some(key.clone()), &receipt)
+                .await
+                .unwrap();
+        }
+
+        first.commit().await.unwrap();
+        let err = second.commit().await.unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::TransactionConflict));
+
+        credit(&processor, &account_number, &cash_in, key)
+            .await
+            .unwrap();
+        let journal = processor
+            .query(TRANSACTIONS_TABLE, &Filter::all())
+            .await
+            .unwrap();
+        assert_eq!(journal.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_reused_key_with_different_request_is_rejected() {
+        let (processor, account_number, cash_in) = setup().await;
+        let key = IdempotencyKey::new(
+            "key:teller".to_string(),
+            "abc".to_string(),
+            "CREDIT|25".to_string(),
+        )
+        .unwrap();
+        credit(&processor, &account_number, &cash_in, key)
+            .await
+            .unwrap();
+
+        let key = IdempotencyKey::new(
+            "key:teller".to_string(),
+            "abc".to_string(),
+            "CREDIT|26".to_string(),
+        )
+        .unwrap();
+        let err = credit(&processor, &account_number, &cash_in, key)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::IdempotencyKeyReused));
+    }
+
+    #[actix_rt::test]
+    async fn test_same_key_from_another_principal_is_a_new_request() {
+        let (processor, account_number, cash_in) = setup().await;
+        let key = IdempotencyKey::new(
+            "key:teller".to_string(),
+            "abc".to_string(),
+            "CREDIT|25".to_string(),
+        )
+        .unwrap();
+        let first = credit(&processor, &account_number, &cash_in, key)
+            .await
+            .unwrap();
+
+        let key = IdempotencyKey::new(
+            "key:other-teller".to_string(),
+            "abc".to_string(),
+            "CREDIT|26".to_string(),
+        )
+        .unwrap();
+        let second = credit(&processor, &account_number, &cash_in, key)
+            .await
+            .unwrap();
+
+        assert_ne!(first.transaction_id, second.transaction_id);
+        let journal = processor
+            .query(TRANSACTIONS_TABLE, &Filter::all())
+            .await
+            .unwrap();
+        assert_eq!(journal.len(), 2);
+    }
+
+    #[test]
+    fn test_blank_key_is_rejected() {
+        let err = IdempotencyKey::new("key:teller".to_string(), " ".to_string(), String::new())
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::PayloadError));
+    }
+}
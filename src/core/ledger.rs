@@ -0,0 +1,333 @@
+use super::{holds, Filter, LedgerTransaction};
+use crate::domain::{
+    Account, AccountClosure, Currency, FxConversion, LedgerEntry, Transaction, TransactionReceipt,
+    TransactionType, ACCOUNTS_TABLE,
+};
+use crate::error::{AppError, ErrorType};
+use crate::util;
+use bigdecimal::BigDecimal;
+use ion_binary_rs::IonValue;
+use std::convert::TryFrom;
+
+/// Loads an account with the funds reserved by its open holds applied.
+pub async fn find_account(
+    tx: &mut dyn LedgerTransaction,
+    account_number: &str,
+) -> Result<Account, AppError> {
+    let filter = Filter::all().eq(
+        "account_number",
+        IonValue::String(account_number.to_string()),
+    );
+    let results = tx.find(ACCOUNTS_TABLE, &filter).await?;
+    let mut account = match results.first() {
+        Some(doc) => Account::try_from(doc)?,
+        None => {
+            return Err(AppError::from_type(ErrorType::AccountNotFound(
+                account_number.to_string(),
+            )))
+        }
+    };
+    let held = holds::held_by_account(&holds::find(tx, &filter).await?);
+    if let Some(amount) = held.get(account_number) {
+        account.set_held_amount(amount.clone());
+    }
+    Ok(account)
+}
+
+pub async fn find_accounts(
+    tx: &mut dyn LedgerTransaction,
+    filter: &Filter,
+) -> Result<Vec<Account>, AppError> {
+    let docs = tx.find(ACCOUNTS_TABLE, filter).await?;
+    let held = holds::held_by_account(&holds::find(tx, &Filter::all()).await?);
+    let mut accounts = Account::from_documents(docs);
+    for account in accounts.iter_mut() {
+        if let Some(amount) = held.get(&account.account_number) {
+            account.set_held_amount(amount.clone());
+        }
+    }
+    Ok(accounts)
+}
+
+async fn update_balance(
+    tx: &mut dyn LedgerTransaction,
+    account_number: &str,
+    balance: BigDecimal,
+) -> Result<(), AppError> {
+    let filter = Filter::all().eq(
+        "account_number",
+        IonValue::String(account_number.to_string()),
+    );
+    tx.update(
+        ACCOUNTS_TABLE,
+        &filter,
+        vec![("balance", IonValue::Decimal(balance))],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Balances of both legs of a posting, before and after it was applied.
+struct Posted {
+    currency: Currency,
+    debit_before: BigDecimal,
+    debit_after: BigDecimal,
+    credit_before: BigDecimal,
+    credit_after: BigDecimal,
+}
+
+/// Moves `amount` from the debit account to the credit account and records
+/// the balanced entry. Fails without writing anything when the debit account
+/// would go past its overdraft limit and `may_overdraw` is not set. The two
+/// accounts must differ, hold the same currency and have a status that allows
+/// the movement.
+async fn post(
+    tx: &mut dyn LedgerTransaction,
+    transaction_id: &str,
+    debit_account_number: &str,
+    credit_account_number: &str,
+    amount: &BigDecimal,
+    may_overdraw: bool,
+) -> Result<Posted, AppError> {
+    if debit_account_number == credit_account_number {
+        let msg = format!(
+            "Account {} cannot move money to itself",
+            debit_account_number
+        );
+        return Err(AppError::from_type(ErrorType::AccountError(msg)));
+    }
+    let debit_account = find_account(tx, debit_account_number).await?;
+    let credit_account = find_account(tx, credit_account_number).await?;
+    debit_account.ensure_can_send()?;
+    credit_account.ensure_can_receive()?;
+    let overdrawn = amount > debit_account.available_balance();
+    let (debit_before, currency) = (debit_account.balance, debit_account.currency);
+    let (credit_before, credit_currency) = (credit_account.balance, credit_account.currency);
+    if currency != credit_currency {
+        let msg = format!(
+            "Cannot move {} into {} account {} without an exchange rate",
+            currency, credit_currency, credit_account_number
+        );
+        return Err(AppError::new(Some(msg), ErrorType::CurrencyMismatch));
+    }
+    let debit_after = currency.round(&(debit_before.clone() - amount.clone()));
+    if !may_overdraw && overdrawn {
+        return Err(AppError::from_type(ErrorType::InsufficientBalance));
+    }
+    let credit_after = currency.round(&(credit_before.clone() + amount.clone()));
+
+    update_balance(tx, debit_account_number, debit_after.clone()).await?;
+    update_balance(tx, credit_account_number, credit_after.clone()).await?;
+
+    let entry = LedgerEntry::new(
+        transaction_id.to_string(),
+        debit_account_number.to_string(),
+        credit_account_number.to_string(),
+        amount.clone(),
+    );
+    tx.insert(&entry).await?;
+
+    Ok(Posted {
+        currency,
+        debit_before,
+        debit_after,
+        credit_before,
+        credit_after,
+    })
+}
+
+/// Credits or debits a customer account against `contra_account_number`, the
+/// system account money enters from or leaves to.
+pub async fn debit_credit(
+    tx: &mut dyn LedgerTransaction,
+    account_number: String,
+    contra_account_number: String,
+    amount: BigDecimal,
+    transaction_type: TransactionType,
+) -> Result<TransactionReceipt, AppError> {
+    let transaction_id = util::generate_alphanumeric(20)?;
+    let (currency, balance, new_bal) = match transaction_type {
+        TransactionType::CREDIT => post(
+            tx,
+            &transaction_id,
+            &contra_account_number,
+            &account_number,
+            &amount,
+            true,
+        )
+        .await
+        .map(|p| (p.currency, p.credit_before, p.credit_after))?,
+        TransactionType::DEBIT => post(
+            tx,
+            &transaction_id,
+            &account_number,
+            &contra_account_number,
+            &amount,
+            false,
+        )
+        .await
+        .map(|p| (p.currency, p.debit_before, p.debit_after))?,
+        TransactionType::TRANSFER => {
+            let msg = "Transfers must name a sender and a recipient".to_string();
+            return Err(AppError::from_type(ErrorType::Custom(msg)));
+        }
+    };
+    let journal_entry = Transaction::new(
+        transaction_id,
+        transaction_type,
+        currency.round(&amount),
+        currency,
+        account_number.clone(),
+        balance,
+        new_bal,
+    );
+    tx.insert(&journal_entry).await?;
+    Ok(journal_entry.into())
+}
+
+/// Moves `amount` from sender to recipient. Accounts in different currencies
+/// need a `conversion`, in which case each leg is posted against the FX
+/// position in its own currency.
+pub async fn transfer(
+    tx: &mut dyn LedgerTransaction,
+    sender_account_number: String,
+    recipient_account_number: String,
+    amount: BigDecimal,
+    conversion: Option<FxConversion>,
+) -> Result<TransactionReceipt, AppError> {
+    let transaction_id = util::generate_alphanumeric(20)?;
+    let (sender_leg, recipient_leg, converted) = match conversion {
+        None => {
+            let posted = post(
+                tx,
+                &transaction_id,
+                &sender_account_number,
+                &recipient_account_number,
+                &amount,
+                false,
+            )
+            .await?;
+            (
+                (posted.currency, posted.debit_before, posted.debit_after),
+                (posted.credit_before, posted.credit_after),
+                None,
+            )
+        }
+        Some(conversion) => {
+            let sold = post(
+                tx,
+                &transaction_id,
+                &sender_account_number,
+                &conversion.sender_position,
+                &amount,
+                false,
+            )
+            .await?;
+            let recipient_currency = find_account(tx, &recipient_account_number).await?.currency;
+            let converted = conversion.convert(&amount, recipient_currency);
+            let zero: BigDecimal = 0u32.into();
+            if converted <= zero {
+                let msg = "Converted amount is too small to transfer".to_string();
+                return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+            }
+            let bought = post(
+                tx,
+                &transaction_id,
+                &conversion.recipient_position,
+                &recipient_account_number,
+                &converted,
+                true,
+            )
+            .await?;
+            (
+                (sold.currency, sold.debit_before, sold.debit_after),
+                (bought.credit_before, bought.credit_after),
+                Some((conversion.rate, converted, bought.currency)),
+            )
+        }
+    };
+    let (currency, sender_before, sender_after) = sender_leg;
+    let (recipient_before, recipient_after) = recipient_leg;
+
+    let mut journal_entry = Transaction::new(
+        transaction_id,
+        TransactionType::TRANSFER,
+        currency.round(&amount),
+        currency,
+        recipient_account_number,
+        recipient_before,
+        recipient_after,
+    )
+    .with_sender(sender_account_number, sender_before, sender_after);
+    if let Some((rate, converted, converted_currency)) = converted {
+        journal_entry = journal_entry.with_conversion(rate, converted, converted_currency);
+    }
+    tx.insert(&journal_entry).await?;
+    Ok(journal_entry.into())
+}
+
+/// Moves whatever the account still holds to the settlement account and marks
+/// it closed. The account record is kept so it stays readable for audit.
+pub async fn close_account(
+    tx: &mut dyn LedgerTransaction,
+    account_number: String,
+    closure: AccountClosure,
+) -> Result<Account, AppError> {
+    let mut account = find_account(tx, &account_number).await?;
+    let zero: BigDecimal = 0u32.into();
+    if account.balance < zero {
+        let msg = format!(
+            "Account {} is overdrawn by {} {} and cannot be closed",
+            account_number,
+            -account.balance.clone(),
+            account.currency
+        );
+        return Err(AppError::from_type(ErrorType::AccountError(msg)));
+    }
+    if account.held_amount() > &zero {
+        let msg = format!(
+            "Account {} has open holds and cannot be closed",
+            account_number
+        );
+        return Err(AppError::from_type(ErrorType::AccountError(msg)));
+    }
+    if account.balance > zero {
+        let settlement_account_number = match closure.settlement_account_number {
+            Some(number) if number != account_number => number,
+            Some(_) => {
+                let msg = "An account cannot be settled into itself".to_string();
+                return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+            }
+            None => {
+                let msg = format!(
+                    "settlement_account_number is required to close an account holding {} {}",
+                    account.balance, account.currency
+                );
+                return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+            }
+        };
+        let balance = account.balance.clone();
+        transfer(
+            tx,
+            account_number.clone(),
+            settlement_account_number,
+            balance,
+            None,
+        )
+        .await?;
+        account = find_account(tx, &account_number).await?;
+    }
+    account.close(closure.reason)?;
+
+    let filter = Filter::all().eq("account_number", IonValue::String(account_number));
+    let mut values = vec![
+        ("status", IonValue::String(account.status.to_string())),
+        ("updated_at", IonValue::DateTime(account.updated_at)),
+    ];
+    if let (Some(reason), Some(closed_at)) = (&account.closure_reason, account.closed_at) {
+        values.push(("closure_reason", IonValue::String(reason.clone())));
+        values.push(("closed_at", IonValue::DateTime(closed_at)));
+    }
+    tx.update(ACCOUNTS_TABLE, &filter, values).await?;
+    Ok(account)
+}
@@ -0,0 +1,254 @@
+use super::{ledger, Filter, LedgerTransaction};
+use crate::domain::{LimitPolicy, Posting, Transaction, TransactionType, TRANSACTIONS_TABLE};
+use crate::error::{AppError, ErrorType};
+use chrono::prelude::*;
+use ion_binary_rs::IonValue;
+
+/// Debits and transfers `account_number` has sent since midnight UTC, read
+/// through `tx`, so postings made earlier in the same transaction count and a
+/// concurrent posting from the account conflicts with this one.
+async fn sent_today(
+    tx: &mut dyn LedgerTransaction,
+    account_number: &str,
+) -> Result<Vec<Transaction>, AppError> {
+    let account = IonValue::String(account_number.to_string());
+    let midnight: DateTime<FixedOffset> = Utc::today().and_hms(0, 0, 0).into();
+    let since = IonValue::DateTime(midnight);
+    let transfers = Filter::all()
+        .eq("sender_account_number", account.clone())
+        .gte("created_at", since.clone());
+    let debits = Filter::all()
+        .eq("recipient_account_number", account)
+        .eq(
+            "transaction_type",
+            IonValue::String(TransactionType::DEBIT.to_string()),
+        )
+        .gte("created_at", since);
+    let mut sent = Vec::new();
+    for filter in &[transfers, debits] {
+        let docs = tx.find(TRANSACTIONS_TABLE, filter).await?;
+        sent.extend(
+            Transaction::from_documents(docs)?
+                .into_iter()
+                .filter(|t| t.is_sent_by(account_number)),
+        );
+    }
+    Ok(sent)
+}
+
+/// Checks the money `posting` takes out of an account against that account's
+/// limits. Daily limits count what the account has already sent today. Run it
+/// in the ledger transaction that makes the posting.
+pub async fn check(
+    tx: &mut dyn LedgerTransaction,
+    policy: &LimitPolicy,
+    posting: &Posting,
+) -> Result<(), AppError> {
+    let (account_number, transaction_type, amount) = match posting.sender() {
+        Some(sender) => sender,
+        None => return Ok(()),
+    };
+    let account = ledger::find_account(tx, account_number).await?;
+    let limits = policy.limits_for(&account);
+    let exceeded = |limit: &str, msg: String| {
+        AppError::new(Some(msg), ErrorType::LimitExceeded(limit.to_string()))
+    };
+    if let Some(max) = &limits.max_transaction_amount {
+        if amount > max {
+            let msg = format!(
+                "Account {} cannot send more than {} {} in one transaction",
+                account.account_number, max, account.currency
+            );
+            return Err(exceeded("max_transaction_amount", msg));
+        }
+    }
+    if limits.max_daily_debit_total.is_none() && limits.max_daily_transfer_count.is_none() {
+        return Ok(());
+    }
+
+    let sent_today = sent_today(tx, account_number).await?;
+    if let Some(max) = &limits.max_daily_debit_total {
+        let total = sent_today
+            .iter()
+            .fold(amount.clone(), |total, sent| total + &sent.amount);
+        if &total > max {
+            let msg = format!(
+                "Account {} cannot send more than {} {} a day",
+                account.account_number, max, account.currency
+            );
+            return Err(exceeded("max_daily_debit_total", msg));
+        }
+    }
+    if let Some(max) = limits.max_daily_transfer_count {
+        let transfers = sent_today
+            .iter()
+            .filter(|sent| sent.transaction_type == TransactionType::TRANSFER)
+            .count();
+        if transaction_type == TransactionType::TRANSFER && transfers >= max as usize {
+            let msg = format!(
+                "Account {} cannot make more than {} transfers a day",
+                account.account_number, max
+            );
+            return Err(exceeded("max_daily_transfer_count", msg));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{LedgerStorage, MemoryProcessor};
+    use crate::domain::{Account, Currency, TransactionLimits};
+    use bigdecimal::BigDecimal;
+    use std::sync::Arc;
+
+    const CASH_OUT: &str = "CASH-OUT";
+
+    /// A USD account holding 1000 with the given limits, and a recipient.
+    async fn setup(limits: TransactionLimits) -> (MemoryProcessor, Account, Account) {
+        let processor = MemoryProcessor::new();
+        let mut sender = Account::new("Sam James".to_string(), String::new(), Currency::USD);
+        sender.limits = limits;
+        let recipient = Account::new("Ada Obi".to_string(), String::new(), Currency::USD);
+        processor.insert(&sender).await.unwrap();
+        let cash_in = Account::new("Cash In".to_string(), String::new(), Currency::USD);
+        processor.insert(&recipient).await.unwrap();
+        processor.insert(&cash_in).await.unwrap();
+        let mut cash_out = Account::new("Cash Out".to_string(), String::new(), Currency::USD);
+        cash_out.account_number = CASH_OUT.to_string();
+        processor.insert(&cash_out).await.unwrap();
+        processor
+            .debit_credit(
+                sender.account_number.clone(),
+                cash_in.account_number.clone(),
+                BigDecimal::from(1000),
+                TransactionType::CREDIT,
+                None,
+            )
+            .await
+            .unwrap();
+        (processor, sender, recipient)
+    }
+
+    fn debit(account: &Account, amount: u32) -> Posting {
+        Posting::DebitCredit {
+            account_number: account.account_number.clone(),
+            contra_account_number: CASH_OUT.to_string(),
+            amount: BigDecimal::from(amount),
+            transaction_type: TransactionType::DEBIT,
+        }
+    }
+
+    fn transfer(sender: &Account, recipient: &Account, amount: u32) -> Posting {
+        Posting::Transfer {
+            sender_account_number: sender.account_number.clone(),
+            recipient_account_number: recipient.account_number.clone(),
+            amount: BigDecimal::from(amount),
+            conversion: None,
+        }
+    }
+
+    async fn check_posting(processor: &MemoryProcessor, posting: Posting) -> Result<(), AppError> {
+        let policy = LimitPolicy::default();
+        let mut tx = processor.begin().await.unwrap();
+        let result = check(tx.as_mut(), &policy, &posting).await;
+        tx.rollback().await.unwrap();
+        result
+    }
+
+    fn limit_hit(result: Result<(), AppError>) -> String {
+        match result.unwrap_err().error_type {
+            ErrorType::LimitExceeded(limit) => limit,
+            e => panic!("expected a limit error, got {:?}", e),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_single_transaction_limit() {
+        let (processor, sender, _) = setup(TransactionLimits {
+            max_transaction_amount: Some(BigDecimal::from(100)),
+            ..TransactionLimits::default()
+        })
+        .await;
+
+        assert!(check_posting(&processor, debit(&sender, 100)).await.is_ok());
+        let result = check_posting(&processor, debit(&sender, 101)).await;
+        assert_eq!(limit_hit(result), "max_transaction_amount");
+    }
+
+    #[actix_rt::test]
+    async fn test_daily_limits_count_what_was_sent_today() {
+        let (processor, sender, recipient) = setup(TransactionLimits {
+            max_daily_debit_total: Some(BigDecimal::from(300)),
+            max_daily_transfer_count: Some(2),
+            ..TransactionLimits::default()
+        })
+        .await;
+        for _ in 0..2 {
+            processor
+                .post(transfer(&sender, &recipient, 100), None, Arc::default())
+                .await
+                .unwrap();
+        }
+
+        let result = check_posting(&processor, transfer(&sender, &recipient, 1)).await;
+        assert_eq!(limit_hit(result), "max_daily_transfer_count");
+
+        assert!(check_posting(&processor, debit(&sender, 100)).await.is_ok());
+        let result = check_posting(&processor, debit(&sender, 101)).await;
+        assert_eq!(limit_hit(result), "max_daily_debit_total");
+
+        // Money received does not count against the recipient.
+        assert!(
+            check_posting(&processor, transfer(&recipient, &sender, 500))
+                .await
+                .is_ok()
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_postings_are_checked_in_their_transaction() {
+        let (processor, sender, _) = setup(TransactionLimits {
+            max_daily_debit_total: Some(BigDecimal::from(300)),
+            ..TransactionLimits::default()
+        })
+        .await;
+
+        // Items of one batch count against each other.
+        let err = processor
+            .post_all(
+                vec![(debit(&sender, 200), None), (debit(&sender, 101), None)],
+                Arc::default(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::LimitExceeded(_)));
+        assert!(err.message().starts_with("Transaction 1: "));
+
+        // Of two postings made at the same time, the one committed second is
+        // checked again against the first.
+        let policy = LimitPolicy::default();
+        let mut first = processor.begin().await.unwrap();
+        let mut second = processor.begin().await.unwrap();
+        for tx in [&mut first, &mut second] {
+            check(tx.as_mut(), &policy, &debit(&sender, 200))
+                .await
+                .unwrap();
+            ledger::debit_credit(
+                tx.as_mut(),
+                sender.account_number.clone(),
+                CASH_OUT.to_string(),
+                BigDecimal::from(200),
+                TransactionType::DEBIT,
+            )
+            .await
+            .unwrap();
+        }
+        first.commit().await.unwrap();
+        let err = second.commit().await.unwrap_err();
+        assert!(err.is_retryable());
+        let result = check_posting(&processor, debit(&sender, 200)).await;
+        assert_eq!(limit_hit(result), "max_daily_debit_total");
+    }
+}
@@ -0,0 +1,654 @@
+use super::{Filter, LedgerStorage, LedgerTransaction};
+use crate::domain::QldbInsertable;
+use crate::error::{AppError, ErrorType};
+use crate::util;
+use async_trait::async_trait;
+use ion_binary_rs::IonValue;
+use qldb::Document;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+
+type Fields = HashMap<String, IonValue>;
+type RecordKey = (String, String);
+
+#[derive(Clone)]
+struct Record {
+    version: u64,
+    document: Fields,
+}
+
+#[derive(Default)]
+struct Store {
+    tables: HashMap<String, BTreeMap<String, Record>>,
+}
+
+impl Store {
+    fn version(&self, (table_name, document_id): &RecordKey) -> Option<u64> {
+        self.tables
+            .get(table_name)
+            .and_then(|table| table.get(document_id))
+            .map(|record| record.version)
+    }
+}
+
+/// Ledger kept in process memory. It follows the same rules as a QLDB
+/// transaction: nothing is visible outside a transaction until it commits, a
+/// commit applies all of its writes or none, and a commit fails with a
+/// retryable conflict if any document it read has changed in the meantime or
+/// a document matching one of its queries has appeared since.
+#[derive(Clone, Default)]
+pub struct MemoryProcessor {
+    store: Arc<Mutex<Store>>,
+}
+
+impl MemoryProcessor {
+    pub fn new() -> Self {
+        MemoryProcessor::default()
+    }
+}
+
+#[async_trait(?Send)]
+impl LedgerStorage for MemoryProcessor {
+    async fn begin(&self) -> Result<Box<dyn LedgerTransaction>, AppError> {
+        Ok(Box::new(MemoryTransaction {
+            store: self.store.clone(),
+            reads: HashMap::new(),
+            scans: Vec::new(),
+            writes: BTreeMap::new(),
+        }))
+    }
+}
+
+pub struct MemoryTransaction {
+    store: Arc<Mutex<Store>>,
+    /// Version of every committed document this transaction has looked at.
+    reads: HashMap<RecordKey, u64>,
+    /// Every query this transaction has run, so documents inserted into its
+    /// results by another transaction can be detected at commit.
+    scans: Vec<(String, Filter)>,
+    /// Pending inserts and updates.
+    writes: BTreeMap<RecordKey, Fields>,
+}
+
+impl MemoryTransaction {
+    fn matching(&mut self, table_name: &str, filter: &Filter) -> Vec<(String, Fields)> {
+        let store = self.store.lock().unwrap();
+        self.scans.push((table_name.to_string(), filter.clone()));
+        let mut visible: BTreeMap<String, Fields> = BTreeMap::new();
+        if let Some(table) = store.tables.get(table_name) {
+            for (document_id, record) in table {
+                let key = (table_name.to_string(), document_id.clone());
+                if self.writes.contains_key(&key) || !filter.matches(&record.document) {
+                    continue;
+                }
+                self.reads.entry(key).or_insert(record.version);
+                visible.insert(document_id.clone(), record.document.clone());
+            }
+        }
+        for ((table, document_id), pending) in &self.writes {
+            if table != table_name {
+                continue;
+            }
+            if filter.matches(pending) {
+                visible.insert(document_id.clone(), pending.clone());
+            }
+        }
+        visible.into_iter().collect()
+    }
+}
+
+#[async_trait(?Send)]
+impl LedgerTransaction for MemoryTransaction {
+    async fn find(&mut self, table_name: &str, filter: &Filter) -> Result<Vec<Document>, AppError> {
+        let mut docs = Vec::new();
+        for (_, document) in self.matching(table_name, filter) {
+            docs.push(Document::try_from(IonValue::Struct(document))?);
+        }
+        Ok(filter.arrange(docs))
+    }
+
+    async fn insert(&mut self, model: &dyn QldbInsertable) -> Result<String, AppError> {
+        let document_id = util::generate_alphanumeric(22)?;
+        let key = (model.table_name().to_string(), document_id.clone());
+        self.writes.insert(key, model.to_params());
+        Ok(document_id)
+    }
+
+    async fn update(
+        &mut self,
+        table_name: &str,
+        filter: &Filter,
+        values: Vec<(&'static str, IonValue)>,
+    ) -> Result<usize, AppError> {
+        let matches = self.matching(table_name, filter);
+        let count = matches.len();
+        for (document_id, mut document) in matches {
+            for (field, value) in &values {
+                document.insert(field.to_string(), value.clone());
+            }
+            let key = (table_name.to_string(), document_id);
+            self.writes.insert(key, document);
+        }
+        Ok(count)
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), AppError> {
+        let mut store = self.store.lock().unwrap();
+        for (key, version) in &self.reads {
+            if store.version(key) != Some(*version) {
+                return Err(AppError::from_type(ErrorType::TransactionConflict));
+            }
+        }
+        for (table_name, filter) in &self.scans {
+            let table = match store.tables.get(table_name) {
+                Some(table) => table,
+                None => continue,
+            };
+            let phantom = table.iter().any(|(document_id, record)| {
+                let key = (table_name.clone(), document_id.clone());
+                !self.reads.contains_key(&key)
+                    && !self.writes.contains_key(&key)
+                    && filter.matches(&record.document)
+            });
+            if phantom {
+                return Err(AppError::from_type(ErrorType::TransactionConflict));
+            }
+        }
+        for ((table_name, document_id), document) in self.writes {
+            let table = store.tables.entry(table_name).or_default();
+            let version = table.get(&document_id).map_or(0, |r| r.version) + 1;
+            table.insert(document_id, Record { version, document });
+        }
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> Result<(), AppError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        Account, Currency, FxConversion, IdempotencyKey, IdempotencyRecord, LedgerEntry, Posting,
+        Transaction, TransactionType, ACCOUNTS_TABLE, IDEMPOTENCY_KEYS_TABLE, TRANSACTIONS_TABLE,
+    };
+    use bigdecimal::BigDecimal;
+    use std::convert::TryInto;
+    use std::str::FromStr;
+
+    fn by_number(account: &Account) -> Filter {
+        Filter::all().eq(
+            "account_number",
+            IonValue::String(account.account_number.clone()),
+        )
+    }
+
+    async fn balance_of(processor: &MemoryProcessor, account: &Account) -> BigDecimal {
+        let docs = processor
+            .query(ACCOUNTS_TABLE, &by_number(account))
+            .await
+            .unwrap();
+        let account: Account = docs.first().unwrap().try_into().unwrap();
+        account.balance
+    }
+
+    async fn open_account(processor: &MemoryProcessor, balance: &str) -> Account {
+        open_account_in(processor, balance, Currency::USD).await
+    }
+
+    async fn open_account_in(
+        processor: &MemoryProcessor,
+        balance: &str,
+        currency: Currency,
+    ) -> Account {
+        let account = Account::new(
+            "Sam James".to_string(),
+            "2347038657970".to_string(),
+            currency,
+        );
+        processor.insert(&account).await.unwrap();
+        if balance != "0" {
+            let amount = BigDecimal::from_str(balance).unwrap();
+            let cash_in = Account::new("Cash In".to_string(), String::new(), currency);
+            processor.insert(&cash_in).await.unwrap();
+            processor
+                .debit_credit(
+                    account.account_number.clone(),
+                    cash_in.account_number,
+                    amount,
+                    TransactionType::CREDIT,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        account
+    }
+
+    #[actix_rt::test]
+    async fn test_insert_and_query() {
+        let processor = MemoryProcessor::new();
+        let account = open_account(&processor, "0").await;
+
+        let docs = processor
+            .query(ACCOUNTS_TABLE, &Filter::all())
+            .await
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+
+        let docs = processor
+            .query(ACCOUNTS_TABLE, &by_number(&account))
+            .await
+            .unwrap();
+        let found: Account = docs.first().unwrap().try_into().unwrap();
+        assert_eq!(found.account_number, account.account_number);
+    }
+
+    #[actix_rt::test]
+    async fn test_transfer_updates_both_balances() {
+        let processor = MemoryProcessor::new();
+        let sender = open_account(&processor, "100").await;
+        let recipient = open_account(&processor, "0").await;
+
+        processor
+            .transfer(
+                sender.account_number.clone(),
+                recipient.account_number.clone(),
+                BigDecimal::from(40),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(balance_of(&processor, &sender).await, BigDecimal::from(60));
+        assert_eq!(
+            balance_of(&processor, &recipient).await,
+            BigDecimal::from(40)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_post_all_is_all_or_nothing() {
+        let processor = MemoryProcessor::new();
+        let sender = open_account(&processor, "100").await;
+        let recipient = open_account(&processor, "0").await;
+        let posting = |amount: u32| Posting::Transfer {
+            sender_account_number: sender.account_number.clone(),
+            recipient_account_number: recipient.account_number.clone(),
+            amount: BigDecimal::from(amount),
+            conversion: None,
+        };
+
+        let err = processor
+            .post_all(
+                vec![(posting(60), None), (posting(60), None)],
+                Arc::default(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::InsufficientBalance));
+        assert!(err.message().starts_with("Transaction 1: "));
+        assert_eq!(balance_of(&processor, &sender).await, BigDecimal::from(100));
+
+        let receipts = processor
+            .post_all(
+                vec![(posting(60), None), (posting(40), None)],
+                Arc::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(receipts.len(), 2);
+        assert_eq!(
+            balance_of(&processor, &recipient).await,
+            BigDecimal::from(100)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_transfer_to_unknown_account_changes_nothing() {
+        let processor = MemoryProcessor::new();
+        let sender = open_account(&processor, "100").await;
+
+        let err = processor
+            .transfer(
+                sender.account_number.clone(),
+                "0000000000".to_string(),
+                BigDecimal::from(40),
+                None,
+                None,
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err.error_type, ErrorType::AccountNotFound(_)));
+        assert_eq!(balance_of(&processor, &sender).await, BigDecimal::from(100));
+    }
+
+    #[actix_rt::test]
+    async fn test_posting_to_the_same_account_is_refused() {
+        let processor = MemoryProcessor::new();
+        let account = open_account(&processor, "100").await;
+
+        let err = processor
+            .transfer(
+                account.account_number.clone(),
+                account.account_number.clone(),
+                BigDecimal::from(40),
+                None,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::AccountError(_)));
+
+        let err = processor
+            .debit_credit(
+                account.account_number.clone(),
+                account.account_number.clone(),
+                BigDecimal::from(40),
+                TransactionType::CREDIT,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::AccountError(_)));
+        assert_eq!(
+            balance_of(&processor, &account).await,
+            BigDecimal::from(100)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_insufficient_balance_is_an_error() {
+        let processor = MemoryProcessor::new();
+        let sender = open_account(&processor, "100").await;
+        let recipient = open_account(&processor, "0").await;
+
+        let err = processor
+            .transfer(
+                sender.account_number.clone(),
+                recipient.account_number.clone(),
+                BigDecimal::from(101),
+                None,
+                None,
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err.error_type, ErrorType::InsufficientBalance));
+        assert_eq!(balance_of(&processor, &sender).await, BigDecimal::from(100));
+        let journal = processor
+            .query(TRANSACTIONS_TABLE, &Filter::all())
+            .await
+            .unwrap();
+        assert_eq!(journal.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_overdraft_limit_is_enforced() {
+        let processor = MemoryProcessor::new();
+        let recipient = open_account(&processor, "0").await;
+        let mut sender = Account::new("Acme".to_string(), String::new(), Currency::USD);
+        sender.set_overdraft_limit(BigDecimal::from(50)).unwrap();
+        processor.insert(&sender).await.unwrap();
+
+        let receipt = processor
+            .transfer(
+                sender.account_number.clone(),
+                recipient.account_number.clone(),
+                BigDecimal::from(30),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(receipt.sender_balance, Some(BigDecimal::from(-30)));
+
+        let err = processor
+            .transfer(
+                sender.account_number.clone(),
+                recipient.account_number.clone(),
+                BigDecimal::from(21),
+                None,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::InsufficientBalance));
+        assert_eq!(balance_of(&processor, &sender).await, BigDecimal::from(-30));
+    }
+
+    #[actix_rt::test]
+    async fn test_receipt_reports_resulting_balances() {
+        let processor = MemoryProcessor::new();
+        let sender = open_account(&processor, "100").await;
+        let recipient = open_account(&processor, "0").await;
+
+        let receipt = processor
+            .transfer(
+                sender.account_number.clone(),
+                recipient.account_number.clone(),
+                BigDecimal::from_str("40.5").unwrap(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(receipt.transaction_type, TransactionType::TRANSFER);
+        assert_eq!(receipt.amount.to_string(), "40.50");
+        assert_eq!(
+            receipt.sender_balance,
+            Some(BigDecimal::from_str("59.5").unwrap())
+        );
+        assert_eq!(
+            receipt.recipient_balance,
+            BigDecimal::from_str("40.5").unwrap()
+        );
+        let filter = Filter::all().eq("id", IonValue::String(receipt.transaction_id));
+        let journal = processor.query(TRANSACTIONS_TABLE, &filter).await.unwrap();
+        assert_eq!(journal.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_postings_keep_the_ledger_balanced() {
+        let processor = MemoryProcessor::new();
+        let sender = open_account(&processor, "100").await;
+        let recipient = open_account(&processor, "0").await;
+        let cash_out = open_account(&processor, "0").await;
+        processor
+            .transfer(
+                sender.account_number.clone(),
+                recipient.account_number.clone(),
+                BigDecimal::from(40),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        processor
+            .debit_credit(
+                recipient.account_number.clone(),
+                cash_out.account_number.clone(),
+                BigDecimal::from(15),
+                TransactionType::DEBIT,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let accounts = processor
+            .query(ACCOUNTS_TABLE, &Filter::all())
+            .await
+            .unwrap();
+        let total = accounts.iter().fold(BigDecimal::from(0), |sum, doc| {
+            let account: Account = doc.try_into().unwrap();
+            sum + account.balance
+        });
+        assert_eq!(total, BigDecimal::from(0));
+
+        let entries = processor
+            .query("ledger_entries", &Filter::all())
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 3);
+        for doc in &entries {
+            let entry: LedgerEntry = doc.try_into().unwrap();
+            assert_ne!(entry.debit_account_number, entry.credit_account_number);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_cross_currency_transfer_needs_conversion() {
+        let processor = MemoryProcessor::new();
+        let sender = open_account_in(&processor, "100", Currency::USD).await;
+        let recipient = open_account_in(&processor, "0", Currency::JPY).await;
+        let usd_position = open_account_in(&processor, "0", Currency::USD).await;
+        let jpy_position = open_account_in(&processor, "0", Currency::JPY).await;
+
+        let err = processor
+            .transfer(
+                sender.account_number.clone(),
+                recipient.account_number.clone(),
+                BigDecimal::from(10),
+                None,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::CurrencyMismatch));
+        assert_eq!(balance_of(&processor, &sender).await, BigDecimal::from(100));
+
+        let conversion = FxConversion {
+            rate: BigDecimal::from_str("151.237").unwrap(),
+            sender_position: usd_position.account_number.clone(),
+            recipient_position: jpy_position.account_number.clone(),
+        };
+        processor
+            .transfer(
+                sender.account_number.clone(),
+                recipient.account_number.clone(),
+                BigDecimal::from(10),
+                Some(conversion),
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(balance_of(&processor, &sender).await, BigDecimal::from(90));
+        assert_eq!(
+            balance_of(&processor, &recipient).await,
+            BigDecimal::from(1512)
+        );
+        assert_eq!(
+            balance_of(&processor, &usd_position).await,
+            BigDecimal::from(10)
+        );
+        assert_eq!(
+            balance_of(&processor, &jpy_position).await,
+            BigDecimal::from(-1512)
+        );
+
+        let filter = Filter::all().eq("transaction_type", IonValue::String("TRANSFER".to_string()));
+        let docs = processor.query("transactions", &filter).await.unwrap();
+        let entry: Transaction = docs.first().unwrap().try_into().unwrap();
+        assert_eq!(entry.amount, BigDecimal::from(10));
+        assert_eq!(entry.currency, Currency::USD);
+        assert_eq!(
+            entry.fx_rate,
+            Some(BigDecimal::from_str("151.237").unwrap())
+        );
+        assert_eq!(entry.converted_amount, Some(BigDecimal::from(1512)));
+        assert_eq!(entry.converted_currency, Some(Currency::JPY));
+    }
+
+    #[actix_rt::test]
+    async fn test_uncommitted_writes_are_isolated() {
+        let processor = MemoryProcessor::new();
+        let account = open_account(&processor, "100").await;
+
+        let mut tx = processor.begin().await.unwrap();
+        tx.update(
+            ACCOUNTS_TABLE,
+            &by_number(&account),
+            vec![("balance", IonValue::Decimal(BigDecimal::from(5)))],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            balance_of(&processor, &account).await,
+            BigDecimal::from(100)
+        );
+
+        tx.rollback().await.unwrap();
+        assert_eq!(
+            balance_of(&processor, &account).await,
+            BigDecimal::from(100)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_conflicting_commit_is_retryable() {
+        let processor = MemoryProcessor::new();
+        let account = open_account(&processor, "100").await;
+        let new_balance = vec![("balance", IonValue::Decimal(BigDecimal::from(1)))];
+
+        let mut first = processor.begin().await.unwrap();
+        let mut second = processor.begin().await.unwrap();
+        first
+            .find(ACCOUNTS_TABLE, &by_number(&account))
+            .await
+            .unwrap();
+        second
+            .find(ACCOUNTS_TABLE, &by_number(&account))
+            .await
+            .unwrap();
+        first
+            .update(ACCOUNTS_TABLE, &by_number(&account), new_balance.clone())
+            .await
+            .unwrap();
+        second
+            .update(ACCOUNTS_TABLE, &by_number(&account), new_balance)
+            .await
+            .unwrap();
+
+        first.commit().await.unwrap();
+        let err = second.commit().await.unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::TransactionConflict));
+        assert!(err.is_retryable());
+    }
+
+    #[actix_rt::test]
+    async fn test_phantom_insert_is_a_conflict() {
+        let processor = MemoryProcessor::new();
+        let key = IdempotencyKey::new(
+            "key:teller".to_string(),
+            "abc".to_string(),
+            "CREDIT|25".to_string(),
+        )
+        .unwrap();
+        let filter = Filter::all().eq("idempotency_key", IonValue::String(key.key.clone()));
+
+        let mut first = processor.begin().await.unwrap();
+        let mut second = processor.begin().await.unwrap();
+        for (tx, transaction_id) in [(&mut first, "TX1"), (&mut second, "TX2")] {
+            let found = tx.find(IDEMPOTENCY_KEYS_TABLE, &filter).await.unwrap();
+            assert!(found.is_empty());
+            let record = IdempotencyRecord::new(key.clone(), transaction_id.to_string());
+            tx.insert(&record).await.unwrap();
+        }
+
+        first.commit().await.unwrap();
+        let err = second.commit().await.unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::TransactionConflict));
+        let docs = processor
+            .query(IDEMPOTENCY_KEYS_TABLE, &filter)
+            .await
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+    }
+}
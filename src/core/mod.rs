@@ -1,2 +1,19 @@
+mod holds;
+mod idempotency;
+mod ledger;
+mod limits;
+mod memory;
 mod processor;
+mod rates;
+mod sql;
+mod storage;
+pub use memory::MemoryProcessor;
 pub use processor::QldbProcessor;
+pub use rates::{Rates, StaticRateProvider};
+pub use sql::SqlProcessor;
+#[cfg(test)]
+pub use storage::HOSTILE_ACCOUNT_NUMBERS;
+pub use storage::{
+    check_identifier, read_within, transaction_within, Filter, LedgerStorage, LedgerTransaction,
+    Storage,
+};
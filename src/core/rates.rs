@@ -0,0 +1,122 @@
+use crate::domain::Currency;
+use crate::error::{AppError, ErrorType};
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub type Rates = Arc<dyn RateProvider>;
+
+/// Decimal places kept in a derived reverse rate. `1 / rate` rarely ends, and
+/// BigDecimal would otherwise carry it to 100 digits.
+const REVERSE_RATE_SCALE: i64 = 8;
+
+/// Source of exchange rates used to quote cross-currency transfers.
+#[async_trait(?Send)]
+pub trait RateProvider: Send + Sync {
+    /// Units of `to` paid for one unit of `from`.
+    async fn rate(&self, from: Currency, to: Currency) -> Result<BigDecimal, AppError>;
+}
+
+/// Fixed rates for local use, loaded once at startup. The reverse of a
+/// configured pair is derived when it is not listed itself, rounded half up
+/// to `REVERSE_RATE_SCALE` places.
+#[derive(Debug, Default)]
+pub struct StaticRateProvider {
+    rates: HashMap<(Currency, Currency), BigDecimal>,
+}
+
+impl StaticRateProvider {
+    pub fn new(rates: HashMap<(Currency, Currency), BigDecimal>) -> Self {
+        StaticRateProvider { rates }
+    }
+
+    /// Reads a rates file with one `FROM/TO=RATE` pair per line, e.g.
+    /// `USD/EUR=0.92`. Blank lines and lines starting with `#` are ignored.
+    pub fn from_file(path: &str) -> Result<Self, AppError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            let msg = format!("Unable to read FX rates file {}: {}", path, e);
+            AppError::from_type(ErrorType::Custom(msg))
+        })?;
+        StaticRateProvider::parse(&contents)
+    }
+
+    fn parse(contents: &str) -> Result<Self, AppError> {
+        let mut rates = HashMap::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || {
+                let msg = format!("Invalid FX rate on line {}: {}", number + 1, line);
+                AppError::from_type(ErrorType::Custom(msg))
+            };
+            let (pair, rate) = line.split_once('=').ok_or_else(invalid)?;
+            let (from, to) = pair.split_once('/').ok_or_else(invalid)?;
+            let rate = BigDecimal::from_str(rate.trim()).map_err(|_| invalid())?;
+            if rate <= 0u32.into() {
+                return Err(invalid());
+            }
+            rates.insert((from.trim().parse()?, to.trim().parse()?), rate);
+        }
+        Ok(StaticRateProvider::new(rates))
+    }
+}
+
+#[async_trait(?Send)]
+impl RateProvider for StaticRateProvider {
+    async fn rate(&self, from: Currency, to: Currency) -> Result<BigDecimal, AppError> {
+        if from == to {
+            return Ok(1u32.into());
+        }
+        if let Some(rate) = self.rates.get(&(from, to)) {
+            return Ok(rate.clone());
+        }
+        if let Some(rate) = self.rates.get(&(to, from)) {
+            let reverse = BigDecimal::from(1) / rate;
+            return Ok(reverse
+                .with_scale(REVERSE_RATE_SCALE + 1)
+                .round(REVERSE_RATE_SCALE));
+        }
+        let msg = format!("No exchange rate available from {} to {}", from, to);
+        Err(AppError::new(Some(msg), ErrorType::CurrencyMismatch))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn test_parse_and_derive_reverse_rate() {
+        let provider = StaticRateProvider::parse("# test rates\n\nUSD/EUR = 0.8\n").unwrap();
+        assert_eq!(
+            provider.rate(Currency::USD, Currency::EUR).await.unwrap(),
+            BigDecimal::from_str("0.8").unwrap()
+        );
+        assert_eq!(
+            provider.rate(Currency::EUR, Currency::USD).await.unwrap(),
+            BigDecimal::from_str("1.25").unwrap()
+        );
+        assert!(provider.rate(Currency::USD, Currency::JPY).await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_reverse_rate_is_rounded() {
+        let provider = StaticRateProvider::parse("USD/EUR=0.9\nUSD/GBP=0.6").unwrap();
+        let rate = provider.rate(Currency::EUR, Currency::USD).await.unwrap();
+        assert_eq!(rate.to_string(), "1.11111111");
+        let rate = provider.rate(Currency::GBP, Currency::USD).await.unwrap();
+        assert_eq!(rate.to_string(), "1.66666667");
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_lines() {
+        assert!(StaticRateProvider::parse("USD-EUR=0.8").is_err());
+        assert!(StaticRateProvider::parse("USD/EUR=abc").is_err());
+        assert!(StaticRateProvider::parse("USD/EUR=0").is_err());
+        assert!(StaticRateProvider::parse("USD/XYZ=1").is_err());
+    }
+}
@@ -0,0 +1,884 @@
+use super::{check_identifier, Filter, LedgerStorage, LedgerTransaction};
+use crate::domain::QldbInsertable;
+use crate::error::{AppError, ErrorType};
+use crate::util;
+use actix_web::error::BlockingError;
+use actix_web::web;
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use ion_binary_rs::IonValue;
+use qldb::Document;
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{params, params_from_iter, Connection, Statement};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Schema migrations, applied in order on startup. Never edit a migration
+/// that has been released; add a new one instead.
+const MIGRATIONS: &[(i64, &str, &str)] = &[
+    (
+        1,
+        "create_accounts_and_transactions",
+        include_str!("../../migrations/0001_create_accounts_and_transactions.sql"),
+    ),
+    (
+        2,
+        "extend_transactions_journal",
+        include_str!("../../migrations/0002_extend_transactions_journal.sql"),
+    ),
+    (
+        3,
+        "create_ledger_entries",
+        include_str!("../../migrations/0003_create_ledger_entries.sql"),
+    ),
+    (
+        4,
+        "create_idempotency_keys",
+        include_str!("../../migrations/0004_create_idempotency_keys.sql"),
+    ),
+    (
+        5,
+        "add_currency",
+        include_str!("../../migrations/0005_add_currency.sql"),
+    ),
+    (
+        6,
+        "fx_quotes_and_conversions",
+        include_str!("../../migrations/0006_fx_quotes_and_conversions.sql"),
+    ),
+    (
+        7,
+        "add_account_status",
+        include_str!("../../migrations/0007_add_account_status.sql"),
+    ),
+    (
+        8,
+        "add_account_closure",
+        include_str!("../../migrations/0008_add_account_closure.sql"),
+    ),
+    (
+        9,
+        "add_overdraft_limit",
+        include_str!("../../migrations/0009_add_overdraft_limit.sql"),
+    ),
+    (
+        10,
+        "create_holds",
+        include_str!("../../migrations/0010_create_holds.sql"),
+    ),
+    (
+        11,
+        "add_account_limits",
+        include_str!("../../migrations/0011_add_account_limits.sql"),
+    ),
+    (
+        12,
+        "create_schedules",
+        include_str!("../../migrations/0012_create_schedules.sql"),
+    ),
+    (
+        13,
+        "index_ledger_entries_by_account",
+        include_str!("../../migrations/0013_index_ledger_entries_by_account.sql"),
+    ),
+    (
+        14,
+        "create_api_keys",
+        include_str!("../../migrations/0014_create_api_keys.sql"),
+    ),
+    (
+        15,
+        "add_api_key_role",
+        include_str!("../../migrations/0015_add_api_key_role.sql"),
+    ),
+    (
+        16,
+        "create_customers",
+        include_str!("../../migrations/0016_create_customers.sql"),
+    ),
+    (
+        17,
+        "joint_accounts",
+        include_str!("../../migrations/0017_joint_accounts.sql"),
+    ),
+    (
+        18,
+        "soft_delete_customers",
+        include_str!("../../migrations/0018_soft_delete_customers.sql"),
+    ),
+];
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connections kept open between transactions.
+const MAX_IDLE_CONNECTIONS: usize = 8;
+
+type Pool = Arc<Mutex<Vec<Connection>>>;
+
+/// Ledger stored in a SQLite database file. Write transactions are opened
+/// with `BEGIN IMMEDIATE`, which takes the database write lock up front, so
+/// the rows a transfer reads cannot change before it commits. Read-only work
+/// opens a deferred transaction, which only takes the lock if it ends up
+/// writing.
+///
+/// rusqlite blocks, so every statement runs on the blocking thread pool and
+/// waiting on a busy database never stalls an actix worker. Connections are
+/// reused across transactions.
+#[derive(Clone)]
+pub struct SqlProcessor {
+    path: String,
+    idle: Pool,
+}
+
+impl SqlProcessor {
+    pub fn new(path: String) -> Result<Self, AppError> {
+        let mut conn = connect(&path)?;
+        conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+        migrate(&mut conn)?;
+        Ok(SqlProcessor {
+            path,
+            idle: Arc::new(Mutex::new(vec![conn])),
+        })
+    }
+
+    /// Runs `work` on the blocking thread pool with an idle connection, or a
+    /// new one if none is idle.
+    async fn with_connection<R, F>(&self, work: F) -> Result<(Connection, R), AppError>
+    where
+        F: FnOnce(&mut Connection) -> Result<R, AppError> + Send + 'static,
+        R: Send + 'static,
+    {
+        let idle = self.idle.lock().unwrap().pop();
+        let path = self.path.clone();
+        web::block(move || {
+            let mut conn = match idle {
+                Some(conn) => conn,
+                None => connect(&path)?,
+            };
+            let result = work(&mut conn)?;
+            Ok((conn, result))
+        })
+        .await
+        .map_err(from_blocking)
+    }
+
+    async fn begin_with(
+        &self,
+        statement: &'static str,
+    ) -> Result<Box<dyn LedgerTransaction>, AppError> {
+        let (conn, _) = self
+            .with_connection(move |conn| Ok(conn.execute_batch(statement)?))
+            .await?;
+        Ok(Box::new(SqlTransaction {
+            conn: Some(conn),
+            idle: self.idle.clone(),
+        }))
+    }
+}
+
+fn connect(path: &str) -> Result<Connection, AppError> {
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    Ok(conn)
+}
+
+/// Keeps `conn` for a later transaction. It must not be inside a transaction.
+fn release(idle: &Pool, conn: Connection) {
+    let mut idle = idle.lock().unwrap();
+    if idle.len() < MAX_IDLE_CONNECTIONS {
+        idle.push(conn);
+    }
+}
+
+fn from_blocking(e: BlockingError<AppError>) -> AppError {
+    match e {
+        BlockingError::Error(e) => e,
+        BlockingError::Canceled => {
+            let msg = "Database work was canceled".to_string();
+            AppError::from_type(ErrorType::Custom(msg))
+        }
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), AppError> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at TEXT NOT NULL
+        )",
+    )?;
+    let current: i64 = conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
+        [],
+        |row| row.get(0),
+    )?;
+    for (version, name, sql) in MIGRATIONS.iter().filter(|m| m.0 > current) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(sql)?;
+        tx.execute(
+            "INSERT INTO schema_migrations (version, name, applied_at) VALUES (?, ?, ?)",
+            params![version, name, Utc::now().to_rfc3339()],
+        )?;
+        tx.commit()?;
+        info!("Applied migration {:04} {}", version, name);
+    }
+    Ok(())
+}
+
+#[async_trait(?Send)]
+impl LedgerStorage for SqlProcessor {
+    async fn begin(&self) -> Result<Box<dyn LedgerTransaction>, AppError> {
+        self.begin_with("BEGIN IMMEDIATE").await
+    }
+
+    async fn begin_read(&self) -> Result<Box<dyn LedgerTransaction>, AppError> {
+        self.begin_with("BEGIN").await
+    }
+
+    async fn query(&self, table_name: &str, filter: &Filter) -> Result<Vec<Document>, AppError> {
+        let (query_str, values) = select(table_name, filter)?;
+        let (conn, docs) = self
+            .with_connection(move |conn| read_documents(&mut conn.prepare(&query_str)?, values))
+            .await?;
+        release(&self.idle, conn);
+        Ok(docs)
+    }
+}
+
+/// A transaction on a connection of the pool. The connection goes back to the
+/// pool once the transaction has ended; if it is dropped while still open,
+/// closing the connection rolls the transaction back.
+pub struct SqlTransaction {
+    conn: Option<Connection>,
+    idle: Pool,
+}
+
+impl SqlTransaction {
+    /// Runs `work` on the blocking thread pool with the transaction's
+    /// connection.
+    async fn run<R, F>(&mut self, work: F) -> Result<R, AppError>
+    where
+        F: FnOnce(&mut Connection) -> Result<R, AppError> + Send + 'static,
+        R: Send + 'static,
+    {
+        let mut conn = match self.conn.take() {
+            Some(conn) => conn,
+            None => {
+                let msg = "Transaction lost its database connection".to_string();
+                return Err(AppError::from_type(ErrorType::Custom(msg)));
+            }
+        };
+        let (conn, result) = web::block(move || {
+            let result = work(&mut conn);
+            Ok((conn, result))
+        })
+        .await
+        .map_err(from_blocking)?;
+        self.conn = Some(conn);
+        result
+    }
+
+    async fn end(mut self: Box<Self>, statement: &'static str) -> Result<(), AppError> {
+        self.run(move |conn| Ok(conn.execute_batch(statement)?))
+            .await?;
+        if let Some(conn) = self.conn.take() {
+            release(&self.idle, conn);
+        }
+        Ok(())
+    }
+}
+
+fn to_sql(value: &IonValue) -> Result<Value, AppError> {
+    let value = match value {
+        IonValue::Null(_) => Value::Null,
+        IonValue::Bool(value) => Value::Integer(*value as i64),
+        IonValue::Integer(value) => Value::Integer(*value),
+        IonValue::Decimal(value) => Value::Text(value.to_string()),
+        IonValue::DateTime(value) => Value::Text(value.to_rfc3339()),
+        IonValue::String(value) | IonValue::Symbol(value) => Value::Text(value.clone()),
+        value => {
+            let msg = format!("Unsupported column value: {:?}", value);
+            return Err(AppError::from_type(ErrorType::Custom(msg)));
+        }
+    };
+    Ok(value)
+}
+
+fn from_sql(decl_type: &str, value: ValueRef) -> Result<Option<IonValue>, AppError> {
+    let bad_value = |e: String| AppError::from_type(ErrorType::Custom(e));
+    let value = match value {
+        ValueRef::Null => return Ok(None),
+        ValueRef::Integer(value) if decl_type == "BOOLEAN" => IonValue::Bool(value != 0),
+        ValueRef::Integer(value) => IonValue::Integer(value),
+        ValueRef::Real(value) => IonValue::Float(value),
+        ValueRef::Blob(value) => IonValue::Blob(value.to_vec()),
+        ValueRef::Text(value) => {
+            let text = std::str::from_utf8(value)?;
+            if decl_type.starts_with("DECIMAL") {
+                let decimal = BigDecimal::from_str(text).map_err(|e| bad_value(e.to_string()))?;
+                IonValue::Decimal(decimal)
+            } else if decl_type.starts_with("TIMESTAMP") {
+                let datetime =
+                    DateTime::parse_from_rfc3339(text).map_err(|e| bad_value(e.to_string()))?;
+                IonValue::DateTime(datetime)
+            } else {
+                IonValue::String(text.to_string())
+            }
+        }
+    };
+    Ok(Some(value))
+}
+
+fn bind_values(filter: &Filter) -> Result<Vec<Value>, AppError> {
+    filter.values().into_iter().map(to_sql).collect()
+}
+
+fn read_documents(stmt: &mut Statement, values: Vec<Value>) -> Result<Vec<Document>, AppError> {
+    let columns: Vec<(String, String)> = stmt
+        .columns()
+        .iter()
+        .map(|c| {
+            let decl_type = c.decl_type().unwrap_or("TEXT").to_uppercase();
+            (c.name().to_string(), decl_type)
+        })
+        .collect();
+    let mut rows = stmt.query(params_from_iter(values))?;
+    let mut docs = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut fields = HashMap::new();
+        for (index, (name, decl_type)) in columns.iter().enumerate() {
+            if name == "document_id" {
+                continue;
+            }
+            if let Some(value) = from_sql(decl_type, row.get_ref(index)?)? {
+                fields.insert(name.clone(), value);
+            }
+        }
+        docs.push(Document::try_from(IonValue::Struct(fields))?);
+    }
+    Ok(docs)
+}
+
+/// Query and bound values selecting the rows of `table_name` that match
+/// `filter`.
+fn select(table_name: &str, filter: &Filter) -> Result<(String, Vec<Value>), AppError> {
+    let query_str = format!(
+        "SELECT * FROM {}{}{}",
+        check_identifier(table_name)?,
+        filter.where_clause("")?,
+        filter.order_clause()?
+    );
+    Ok((query_str, bind_values(filter)?))
+}
+
+#[async_trait(?Send)]
+impl LedgerTransaction for SqlTransaction {
+    async fn find(&mut self, table_name: &str, filter: &Filter) -> Result<Vec<Document>, AppError> {
+        let (query_str, values) = select(table_name, filter)?;
+        self.run(move |conn| read_documents(&mut conn.prepare(&query_str)?, values))
+            .await
+    }
+
+    async fn insert(&mut self, model: &dyn QldbInsertable) -> Result<String, AppError> {
+        let document_id = util::generate_alphanumeric(22)?;
+        let mut columns = vec!["document_id".to_string()];
+        let mut values = vec![Value::Text(document_id.clone())];
+        for (field, value) in model.to_params() {
+            check_identifier(&field)?;
+            columns.push(field);
+            values.push(to_sql(&value)?);
+        }
+        let placeholders = vec!["?"; columns.len()].join(", ");
+        let query_str = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            check_identifier(model.table_name())?,
+            columns.join(", "),
+            placeholders
+        );
+        self.run(move |conn| Ok(conn.execute(&query_str, params_from_iter(values))?))
+            .await?;
+        Ok(document_id)
+    }
+
+    async fn update(
+        &mut self,
+        table_name: &str,
+        filter: &Filter,
+        values: Vec<(&'static str, IonValue)>,
+    ) -> Result<usize, AppError> {
+        let mut assignments = Vec::new();
+        for (field, _) in &values {
+            assignments.push(format!("{} = ?", check_identifier(field)?));
+        }
+        let query_str = format!(
+            "UPDATE {} SET {}{}",
+            check_identifier(table_name)?,
+            assignments.join(", "),
+            filter.where_clause("")?
+        );
+        let mut params = Vec::new();
+        for (_, value) in &values {
+            params.push(to_sql(value)?);
+        }
+        params.extend(bind_values(filter)?);
+        self.run(move |conn| Ok(conn.execute(&query_str, params_from_iter(params))?))
+            .await
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), AppError> {
+        self.end("COMMIT").await
+    }
+
+    async fn rollback(self: Box<Self>) -> Result<(), AppError> {
+        self.end("ROLLBACK").await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::HOSTILE_ACCOUNT_NUMBERS;
+    use crate::domain::{
+        Account, AccountClosure, AccountStatus, Currency, IdempotencyKey, Transaction,
+        TransactionType, ACCOUNTS_TABLE,
+    };
+    use std::convert::TryInto;
+
+    struct TempDb {
+        path: String,
+    }
+
+    impl TempDb {
+        fn new() -> Self {
+            let name = format!(
+                "bank-service-{}.db",
+                util::generate_alphanumeric(12).unwrap()
+            );
+            let path = std::env::temp_dir().join(name);
+            TempDb {
+                path: path.to_string_lossy().to_string(),
+            }
+        }
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            for suffix in &["", "-wal", "-shm"] {
+                let _ = std::fs::remove_file(format!("{}{}", self.path, suffix));
+            }
+        }
+    }
+
+    fn by_number(account_number: &str) -> Filter {
+        Filter::all().eq(
+            "account_number",
+            IonValue::String(account_number.to_string()),
+        )
+    }
+
+    async fn find(processor: &SqlProcessor, account_number: &str) -> Account {
+        let docs = processor
+            .query(ACCOUNTS_TABLE, &by_number(account_number))
+            .await
+            .unwrap();
+        docs.first().unwrap().try_into().unwrap()
+    }
+
+    async fn open_account(processor: &SqlProcessor, balance: u32) -> Account {
+        let account = Account::new(
+            "Sam James".to_string(),
+            "2347038657970".to_string(),
+            Currency::USD,
+        );
+        processor.insert(&account).await.unwrap();
+        if balance > 0 {
+            let cash_in = Account::new("Cash In".to_string(), String::new(), Currency::USD);
+            processor.insert(&cash_in).await.unwrap();
+            processor
+                .debit_credit(
+                    account.account_number.clone(),
+                    cash_in.account_number,
+                    BigDecimal::from(balance),
+                    TransactionType::CREDIT,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        account
+    }
+
+    #[test]
+    fn test_migrations_are_applied_once() {
+        let db = TempDb::new();
+        SqlProcessor::new(db.path.clone()).unwrap();
+        let processor = SqlProcessor::new(db.path.clone()).unwrap();
+
+        let conn = connect(&processor.path).unwrap();
+        let applied: i64 = conn
+            .query_row("SELECT COUNT(*) FROM schema_migrations", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(applied, MIGRATIONS.len() as i64);
+    }
+
+    #[actix_rt::test]
+    async fn test_account_round_trip() {
+        let db = TempDb::new();
+        let processor = SqlProcessor::new(db.path.clone()).unwrap();
+        let account = open_account(&processor, 0).await;
+
+        let stored = find(&processor, &account.account_number).await;
+        assert_eq!(stored.name, account.name);
+        assert_eq!(stored.phone, account.phone);
+        assert_eq!(stored.balance, account.balance);
+        assert_eq!(stored.created_at, account.created_at);
+    }
+
+    #[actix_rt::test]
+    async fn test_closed_account_is_kept() {
+        let db = TempDb::new();
+        let processor = SqlProcessor::new(db.path.clone()).unwrap();
+        let account = open_account(&processor, 30).await;
+        let settlement = open_account(&processor, 0).await;
+
+        let closure = AccountClosure {
+            settlement_account_number: Some(settlement.account_number.clone()),
+            reason: "Customer request".to_string(),
+        };
+        let closed = processor
+            .close_account(account.account_number.clone(), closure)
+            .await
+            .unwrap();
+
+        let stored = find(&processor, &account.account_number).await;
+        assert_eq!(stored.status, AccountStatus::CLOSED);
+        assert_eq!(stored.closure_reason.as_deref(), Some("Customer request"));
+        assert_eq!(stored.closed_at, closed.closed_at);
+        assert_eq!(stored.balance, BigDecimal::from(0));
+        let settlement = find(&processor, &settlement.account_number).await;
+        assert_eq!(settlement.balance, BigDecimal::from(30));
+    }
+
+    #[actix_rt::test]
+    async fn test_transfer_updates_both_balances() {
+        let db = TempDb::new();
+        let processor = SqlProcessor::new(db.path.clone()).unwrap();
+        let sender = open_account(&processor, 100).await;
+        let recipient = open_account(&processor, 0).await;
+
+        processor
+            .transfer(
+                sender.account_number.clone(),
+                recipient.account_number.clone(),
+                "40.50".parse().unwrap(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let sender = find(&processor, &sender.account_number).await;
+        let recipient = find(&processor, &recipient.account_number).await;
+        assert_eq!(sender.balance, "59.50".parse().unwrap());
+        assert_eq!(recipient.balance, "40.50".parse().unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_posting_to_the_same_account_is_refused() {
+        let db = TempDb::new();
+        let processor = SqlProcessor::new(db.path.clone()).unwrap();
+        let account = open_account(&processor, 100).await;
+
+        let err = processor
+            .transfer(
+                account.account_number.clone(),
+                account.account_number.clone(),
+                BigDecimal::from(40),
+                None,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::AccountError(_)));
+
+        let err = processor
+            .debit_credit(
+                account.account_number.clone(),
+                account.account_number.clone(),
+                BigDecimal::from(40),
+                TransactionType::CREDIT,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::AccountError(_)));
+        let stored = find(&processor, &account.account_number).await;
+        assert_eq!(stored.balance, BigDecimal::from(100));
+    }
+
+    #[actix_rt::test]
+    async fn test_transfer_writes_journal_entry() {
+        let db = TempDb::new();
+        let processor = SqlProcessor::new(db.path.clone()).unwrap();
+        let sender = open_account(&processor, 100).await;
+        let recipient = open_account(&processor, 0).await;
+
+        processor
+            .transfer(
+                sender.account_number.clone(),
+                recipient.account_number.clone(),
+                BigDecimal::from(30),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let filter = Filter::all().eq("transaction_type", IonValue::String("TRANSFER".to_string()));
+        let docs = processor.query("transactions", &filter).await.unwrap();
+        assert_eq!(docs.len(), 1);
+        let entry: Transaction = docs.first().unwrap().try_into().unwrap();
+        assert_eq!(entry.amount, BigDecimal::from(30));
+        assert_eq!(entry.sender_account_number, Some(sender.account_number));
+        assert_eq!(entry.sender_balance_before, Some(BigDecimal::from(100)));
+        assert_eq!(entry.sender_balance_after, Some(BigDecimal::from(70)));
+        assert_eq!(entry.recipient_balance_before, BigDecimal::from(0));
+        assert_eq!(entry.recipient_balance_after, BigDecimal::from(30));
+    }
+
+    #[actix_rt::test]
+    async fn test_journal_is_paged_by_the_query() {
+        let db = TempDb::new();
+        let processor = SqlProcessor::new(db.path.clone()).unwrap();
+        let account = open_account(&processor, 10).await;
+        let cash_in = Account::new("Cash In".to_string(), String::new(), Currency::USD);
+        processor.insert(&cash_in).await.unwrap();
+        for amount in &[20, 30] {
+            processor
+                .debit_credit(
+                    account.account_number.clone(),
+                    cash_in.account_number.clone(),
+                    BigDecimal::from(*amount),
+                    TransactionType::CREDIT,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        let journal = |filter: Filter| {
+            let processor = processor.clone();
+            let filter = filter
+                .eq(
+                    "recipient_account_number",
+                    IonValue::String(account.account_number.clone()),
+                )
+                .descending(&["created_at", "id"])
+                .limit(2);
+            async move {
+                let docs = processor.query("transactions", &filter).await.unwrap();
+                Transaction::from_documents(docs).unwrap()
+            }
+        };
+
+        let page = journal(Filter::all()).await;
+        let amounts: Vec<_> = page.iter().map(|t| t.amount.clone()).collect();
+        assert_eq!(amounts, vec![BigDecimal::from(30), BigDecimal::from(20)]);
+        let last = &page[1];
+        let page = journal(Filter::all().before(
+            ("created_at", IonValue::DateTime(last.created_at)),
+            ("id", IonValue::String(last.id.clone())),
+        ))
+        .await;
+        let amounts: Vec<_> = page.iter().map(|t| t.amount.clone()).collect();
+        assert_eq!(amounts, vec![BigDecimal::from(10)]);
+    }
+
+    #[actix_rt::test]
+    async fn test_idempotency_key_survives_restart() {
+        let db = TempDb::new();
+        let processor = SqlProcessor::new(db.path.clone()).unwrap();
+        let sender = open_account(&processor, 100).await;
+        let recipient = open_account(&processor, 0).await;
+        let key = IdempotencyKey::new(
+            "key:teller".to_string(),
+            "retry-1".to_string(),
+            "TRANSFER|30".to_string(),
+        )
+        .unwrap();
+        let transfer = |processor: SqlProcessor| {
+            let sender = sender.account_number.clone();
+            let recipient = recipient.account_number.clone();
+            let key = key.clone();
+            async move {
+                processor
+                    .transfer(sender, recipient, BigDecimal::from(30), None, Some(key))
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let first = transfer(processor).await;
+        let processor = SqlProcessor::new(db.path.clone()).unwrap();
+        let second = transfer(processor.clone()).await;
+
+        assert_eq!(first.transaction_id, second.transaction_id);
+        let sender = find(&processor, &sender.account_number).await;
+        assert_eq!(sender.balance, BigDecimal::from(70));
+    }
+
+    #[actix_rt::test]
+    async fn test_hostile_account_numbers_are_plain_values() {
+        let db = TempDb::new();
+        let processor = SqlProcessor::new(db.path.clone()).unwrap();
+        let account = open_account(&processor, 100).await;
+
+        for hostile in HOSTILE_ACCOUNT_NUMBERS {
+            let docs = processor
+                .query(ACCOUNTS_TABLE, &by_number(hostile))
+                .await
+                .unwrap();
+            assert!(docs.is_empty(), "{:?} matched an account", hostile);
+
+            let mut tx = processor.begin().await.unwrap();
+            let updated = tx
+                .update(
+                    ACCOUNTS_TABLE,
+                    &by_number(hostile),
+                    vec![("balance", IonValue::Decimal(BigDecimal::from(0)))],
+                )
+                .await
+                .unwrap();
+            tx.commit().await.unwrap();
+            assert_eq!(updated, 0);
+        }
+
+        let accounts = processor
+            .query(ACCOUNTS_TABLE, &Filter::all())
+            .await
+            .unwrap();
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(
+            find(&processor, &account.account_number).await.balance,
+            BigDecimal::from(100)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_hostile_account_number_is_stored_verbatim() {
+        let db = TempDb::new();
+        let processor = SqlProcessor::new(db.path.clone()).unwrap();
+        let hostile = HOSTILE_ACCOUNT_NUMBERS[2];
+        let account = Account::with_number(
+            hostile.to_string(),
+            "Sam James".to_string(),
+            String::new(),
+            Currency::USD,
+        );
+        processor.insert(&account).await.unwrap();
+
+        assert_eq!(find(&processor, hostile).await.account_number, hostile);
+        let mut tx = processor.begin().await.unwrap();
+        let updated = tx
+            .update(
+                ACCOUNTS_TABLE,
+                &by_number(hostile),
+                vec![("balance", IonValue::Decimal(BigDecimal::from(5)))],
+            )
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!(updated, 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_reads_do_not_hold_the_write_lock() {
+        let db = TempDb::new();
+        let processor = SqlProcessor::new(db.path.clone()).unwrap();
+        let account = open_account(&processor, 100).await;
+
+        let mut read = processor.begin_read().await.unwrap();
+        read.find(ACCOUNTS_TABLE, &by_number(&account.account_number))
+            .await
+            .unwrap();
+        let mut write = processor.begin().await.unwrap();
+        write
+            .update(
+                ACCOUNTS_TABLE,
+                &by_number(&account.account_number),
+                vec![("balance", IonValue::Decimal(BigDecimal::from(5)))],
+            )
+            .await
+            .unwrap();
+        write.commit().await.unwrap();
+        read.commit().await.unwrap();
+
+        // Both connections went back to the pool.
+        assert_eq!(processor.idle.lock().unwrap().len(), 2);
+        let account = find(&processor, &account.account_number).await;
+        assert_eq!(account.balance, BigDecimal::from(5));
+    }
+
+    #[actix_rt::test]
+    async fn test_stale_read_that_writes_is_a_conflict() {
+        let db = TempDb::new();
+        let processor = SqlProcessor::new(db.path.clone()).unwrap();
+        let account = open_account(&processor, 100).await;
+        let set_balance =
+            |balance: u32| vec![("balance", IonValue::Decimal(BigDecimal::from(balance)))];
+
+        let mut read = processor.begin_read().await.unwrap();
+        read.find(ACCOUNTS_TABLE, &by_number(&account.account_number))
+            .await
+            .unwrap();
+        let mut write = processor.begin().await.unwrap();
+        write
+            .update(
+                ACCOUNTS_TABLE,
+                &by_number(&account.account_number),
+                set_balance(5),
+            )
+            .await
+            .unwrap();
+        write.commit().await.unwrap();
+        let err = read
+            .update(
+                ACCOUNTS_TABLE,
+                &by_number(&account.account_number),
+                set_balance(6),
+            )
+            .await
+            .unwrap_err();
+        assert!(err.is_retryable());
+        read.rollback().await.unwrap();
+
+        let account = find(&processor, &account.account_number).await;
+        assert_eq!(account.balance, BigDecimal::from(5));
+    }
+
+    #[actix_rt::test]
+    async fn test_rollback_discards_writes() {
+        let db = TempDb::new();
+        let processor = SqlProcessor::new(db.path.clone()).unwrap();
+        let account = open_account(&processor, 100).await;
+
+        let mut tx = processor.begin().await.unwrap();
+        tx.update(
+            ACCOUNTS_TABLE,
+            &by_number(&account.account_number),
+            vec![("balance", IonValue::Decimal(BigDecimal::from(5)))],
+        )
+        .await
+        .unwrap();
+        tx.rollback().await.unwrap();
+
+        let account = find(&processor, &account.account_number).await;
+        assert_eq!(account.balance, BigDecimal::from(100));
+    }
+}
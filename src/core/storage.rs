@@ -0,0 +1,701 @@
+use super::holds;
+use super::{idempotency, ledger, limits};
+use crate::domain::{
+    Account, AccountClosure, CapturedHold, Currency, Hold, IdempotencyKey, LimitPolicy, Posting,
+    QldbInsertable, TransactionReceipt, TransactionType,
+};
+use crate::error::{AppError, ErrorType};
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use ion_binary_rs::IonValue;
+use qldb::Document;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+pub type Storage = Arc<dyn LedgerStorage>;
+
+pub type TxFuture<'t, R> = Pin<Box<dyn Future<Output = Result<R, AppError>> + 't>>;
+
+/// Number of times a unit of work is re-run after an optimistic concurrency
+/// conflict before the conflict is handed back to the caller.
+const MAX_RETRIES: usize = 3;
+
+/// Table and field names are written into the query text, unlike values, so
+/// only plain identifiers are accepted for them.
+pub fn check_identifier(name: &str) -> Result<&str, AppError> {
+    let mut chars = name.chars();
+    let valid = matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(name)
+    } else {
+        let msg = format!("Invalid identifier in query: {:?}", name);
+        Err(AppError::from_type(ErrorType::Custom(msg)))
+    }
+}
+
+/// How a field is compared with a value.
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Eq,
+    Lt,
+    Gte,
+}
+
+impl Op {
+    fn sql(self) -> &'static str {
+        match self {
+            Op::Eq => "=",
+            Op::Lt => "<",
+            Op::Gte => ">=",
+        }
+    }
+
+    fn holds(self, field: Option<&IonValue>, value: &IonValue) -> bool {
+        match self {
+            Op::Eq => field == Some(value),
+            Op::Lt => field.and_then(|f| compare(f, value)) == Some(Ordering::Less),
+            Op::Gte => matches!(
+                field.and_then(|f| compare(f, value)),
+                Some(Ordering::Greater | Ordering::Equal)
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    Compare(&'static str, Op, IonValue),
+    /// `(first, second) < (a, b)`, spelled out for stores without row values.
+    Before((&'static str, IonValue), (&'static str, IonValue)),
+}
+
+/// Orders values of the same kind. Values of different kinds do not compare.
+fn compare(a: &IonValue, b: &IonValue) -> Option<Ordering> {
+    match (a, b) {
+        (IonValue::Integer(a), IonValue::Integer(b)) => a.partial_cmp(b),
+        (IonValue::Decimal(a), IonValue::Decimal(b)) => a.partial_cmp(b),
+        (IonValue::DateTime(a), IonValue::DateTime(b)) => a.partial_cmp(b),
+        (IonValue::String(a) | IonValue::Symbol(a), IonValue::String(b) | IonValue::Symbol(b)) => {
+            a.partial_cmp(b)
+        }
+        _ => None,
+    }
+}
+
+/// Conditions used to select documents from a table, with an optional order
+/// and row limit. Field names are always supplied by the code, values are
+/// always sent as query parameters.
+///
+/// SQL stores compare values as stored: timestamps are RFC 3339 text in UTC,
+/// which sorts in time order, but decimals are text too and must only be
+/// compared for equality.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    conditions: Vec<Condition>,
+    descending: Vec<&'static str>,
+    limit: Option<usize>,
+}
+
+impl Filter {
+    pub fn all() -> Self {
+        Filter::default()
+    }
+
+    pub fn eq(mut self, field: &'static str, value: IonValue) -> Self {
+        self.conditions
+            .push(Condition::Compare(field, Op::Eq, value));
+        self
+    }
+
+    pub fn lt(mut self, field: &'static str, value: IonValue) -> Self {
+        self.conditions
+            .push(Condition::Compare(field, Op::Lt, value));
+        self
+    }
+
+    pub fn gte(mut self, field: &'static str, value: IonValue) -> Self {
+        self.conditions
+            .push(Condition::Compare(field, Op::Gte, value));
+        self
+    }
+
+    /// Documents that sort before `(first, second)` when ordered by the two
+    /// fields, as a pagination cursor.
+    pub fn before(
+        mut self,
+        first: (&'static str, IonValue),
+        second: (&'static str, IonValue),
+    ) -> Self {
+        self.conditions.push(Condition::Before(first, second));
+        self
+    }
+
+    /// Returns documents ordered by `fields`, highest first.
+    pub fn descending(mut self, fields: &[&'static str]) -> Self {
+        self.descending = fields.to_vec();
+        self
+    }
+
+    /// Returns at most `limit` documents.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Values to bind to the placeholders of `where_clause`, in order.
+    pub fn values(&self) -> Vec<&IonValue> {
+        let mut values = Vec::new();
+        for condition in &self.conditions {
+            match condition {
+                Condition::Compare(_, _, value) => values.push(value),
+                Condition::Before((_, first), (_, second)) => {
+                    values.extend(&[first, first, second])
+                }
+            }
+        }
+        values
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conditions.is_empty()
+    }
+
+    /// Renders the conditions as a ` WHERE` clause with `?` placeholders, each
+    /// field prefixed with `qualifier` (e.g. `"b."`). Values never appear in
+    /// the clause; bind them in order with `values()`.
+    pub fn where_clause(&self, qualifier: &str) -> Result<String, AppError> {
+        if self.is_empty() {
+            return Ok(String::new());
+        }
+        let mut conditions = Vec::new();
+        for condition in &self.conditions {
+            conditions.push(match condition {
+                Condition::Compare(field, op, _) => {
+                    format!("{}{} {} ?", qualifier, check_identifier(field)?, op.sql())
+                }
+                Condition::Before((first, _), (second, _)) => {
+                    let first = format!("{}{}", qualifier, check_identifier(first)?);
+                    let second = format!("{}{}", qualifier, check_identifier(second)?);
+                    format!("({0} < ? OR ({0} = ? AND {1} < ?))", first, second)
+                }
+            });
+        }
+        Ok(format!(" WHERE {}", conditions.join(" AND ")))
+    }
+
+    /// Renders the order and limit as ` ORDER BY` and ` LIMIT` clauses, for
+    /// stores that support them. Others apply them with `arrange`.
+    pub fn order_clause(&self) -> Result<String, AppError> {
+        let mut clause = String::new();
+        if !self.descending.is_empty() {
+            let mut fields = Vec::new();
+            for field in &self.descending {
+                fields.push(format!("{} DESC", check_identifier(field)?));
+            }
+            clause.push_str(&format!(" ORDER BY {}", fields.join(", ")));
+        }
+        if let Some(limit) = self.limit {
+            clause.push_str(&format!(" LIMIT {}", limit));
+        }
+        Ok(clause)
+    }
+
+    pub fn matches(&self, document: &HashMap<String, IonValue>) -> bool {
+        self.conditions.iter().all(|condition| match condition {
+            Condition::Compare(field, op, value) => op.holds(document.get(*field), value),
+            Condition::Before((first, a), (second, b)) => {
+                Op::Lt.holds(document.get(*first), a)
+                    || (Op::Eq.holds(document.get(*first), a)
+                        && Op::Lt.holds(document.get(*second), b))
+            }
+        })
+    }
+
+    /// Sorts and truncates `documents` that match the conditions.
+    pub fn arrange(&self, mut documents: Vec<Document>) -> Vec<Document> {
+        if !self.descending.is_empty() {
+            documents.sort_by(|a, b| {
+                self.descending
+                    .iter()
+                    .map(|field| match (b.get(field), a.get(field)) {
+                        (Some(b), Some(a)) => compare(b, a).unwrap_or(Ordering::Equal),
+                        (b, a) => b.is_some().cmp(&a.is_some()),
+                    })
+                    .find(|ordering| *ordering != Ordering::Equal)
+                    .unwrap_or(Ordering::Equal)
+            });
+        }
+        if let Some(limit) = self.limit {
+            documents.truncate(limit);
+        }
+        documents
+    }
+}
+
+/// A single ledger transaction. Reads and writes made through it become
+/// visible to other transactions only once `commit` succeeds.
+#[async_trait(?Send)]
+pub trait LedgerTransaction {
+    async fn find(&mut self, table_name: &str, filter: &Filter) -> Result<Vec<Document>, AppError>;
+
+    async fn insert(&mut self, model: &dyn QldbInsertable) -> Result<String, AppError>;
+
+    async fn update(
+        &mut self,
+        table_name: &str,
+        filter: &Filter,
+        values: Vec<(&'static str, IonValue)>,
+    ) -> Result<usize, AppError>;
+
+    async fn commit(self: Box<Self>) -> Result<(), AppError>;
+
+    async fn rollback(self: Box<Self>) -> Result<(), AppError>;
+}
+
+#[async_trait(?Send)]
+pub trait LedgerStorage: Send + Sync {
+    async fn begin(&self) -> Result<Box<dyn LedgerTransaction>, AppError>;
+
+    /// Begins a transaction meant for reading. Backends that lock up front
+    /// for writes can start it without the lock.
+    async fn begin_read(&self) -> Result<Box<dyn LedgerTransaction>, AppError> {
+        self.begin().await
+    }
+
+    async fn insert(&self, model: &dyn QldbInsertable) -> Result<String, AppError> {
+        let mut tx = self.begin().await?;
+        let result = tx.insert(model).await;
+        finish(tx, result).await
+    }
+
+    async fn query(&self, table_name: &str, filter: &Filter) -> Result<Vec<Document>, AppError> {
+        let mut tx = self.begin_read().await?;
+        let result = tx.find(table_name, filter).await;
+        finish(tx, result).await
+    }
+
+    /// Loads an account with what its open holds reserve applied.
+    async fn find_account(&self, account_number: String) -> Result<Account, AppError> {
+        read_within(self, |tx| {
+            let account_number = account_number.clone();
+            Box::pin(async move { ledger::find_account(tx, &account_number).await })
+        })
+        .await
+    }
+
+    /// Loads the accounts matching `filter`, like `find_account`.
+    async fn find_accounts(&self, filter: Filter) -> Result<Vec<Account>, AppError> {
+        read_within(self, |tx| {
+            let filter = filter.clone();
+            Box::pin(async move { ledger::find_accounts(tx, &filter).await })
+        })
+        .await
+    }
+
+    /// Receipt of the transaction already made with `idempotency_key`, if any.
+    async fn replay(
+        &self,
+        idempotency_key: IdempotencyKey,
+    ) -> Result<Option<TransactionReceipt>, AppError> {
+        read_within(self, |tx| {
+            let idempotency_key = idempotency_key.clone();
+            Box::pin(async move { idempotency::replay(tx, Some(&idempotency_key)).await })
+        })
+        .await
+    }
+
+    #[cfg(test)]
+    async fn debit_credit(
+        &self,
+        account_number: String,
+        contra_account_number: String,
+        amount: BigDecimal,
+        transaction_type: TransactionType,
+        idempotency_key: Option<IdempotencyKey>,
+    ) -> Result<TransactionReceipt, AppError> {
+        let posting = Posting::DebitCredit {
+            account_number,
+            contra_account_number,
+            amount,
+            transaction_type,
+        };
+        self.post(posting, idempotency_key, Arc::default()).await
+    }
+
+    #[cfg(test)]
+    async fn transfer(
+        &self,
+        sender_account_number: String,
+        recipient_account_number: String,
+        amount: BigDecimal,
+        conversion: Option<crate::domain::FxConversion>,
+        idempotency_key: Option<IdempotencyKey>,
+    ) -> Result<TransactionReceipt, AppError> {
+        let posting = Posting::Transfer {
+            sender_account_number,
+            recipient_account_number,
+            amount,
+            conversion,
+        };
+        self.post(posting, idempotency_key, Arc::default()).await
+    }
+
+    /// Posts a customer transaction, checked against the limits of the
+    /// account it takes money from.
+    async fn post(
+        &self,
+        posting: Posting,
+        idempotency_key: Option<IdempotencyKey>,
+        limits: Arc<LimitPolicy>,
+    ) -> Result<TransactionReceipt, AppError> {
+        transaction_within(self, |tx| {
+            let posting = posting.clone();
+            let idempotency_key = idempotency_key.clone();
+            let limits = limits.clone();
+            Box::pin(async move { post(tx, posting, idempotency_key, Some(&limits)).await })
+        })
+        .await
+    }
+
+    /// Posts every item in one ledger transaction, so either all of them are
+    /// written or none is. Each item is checked against the limits left by
+    /// the ones before it. A failure names the position of the item.
+    async fn post_all(
+        &self,
+        postings: Vec<(Posting, Option<IdempotencyKey>)>,
+        limits: Arc<LimitPolicy>,
+    ) -> Result<Vec<TransactionReceipt>, AppError> {
+        transaction_within(self, |tx| {
+            let postings = postings.clone();
+            let limits = limits.clone();
+            Box::pin(async move {
+                let mut receipts = Vec::new();
+                for (index, (posting, idempotency_key)) in postings.into_iter().enumerate() {
+                    let receipt = post(tx, posting, idempotency_key, Some(&limits))
+                        .await
+                        .map_err(|e| e.for_item(index))?;
+                    receipts.push(receipt);
+                }
+                Ok(receipts)
+            })
+        })
+        .await
+    }
+
+    /// Inserts the accounts and posts their opening balances in one ledger
+    /// transaction. Receipts are in the order of the accounts, `None` where
+    /// there was nothing to post.
+    async fn open_accounts(
+        &self,
+        accounts: Vec<(Account, Option<Posting>)>,
+    ) -> Result<Vec<Option<TransactionReceipt>>, AppError> {
+        transaction_within(self, |tx| {
+            let accounts = accounts.clone();
+            Box::pin(async move {
+                let mut receipts = Vec::new();
+                for (account, opening_balance) in accounts {
+                    tx.insert(&account).await?;
+                    let receipt = match opening_balance {
+                        Some(posting) => Some(post(tx, posting, None, None).await?),
+                        None => None,
+                    };
+                    receipts.push(receipt);
+                }
+                Ok(receipts)
+            })
+        })
+        .await
+    }
+
+    async fn find_holds(&self, account_number: String) -> Result<Vec<Hold>, AppError> {
+        read_within(self, |tx| {
+            let filter =
+                Filter::all().eq("account_number", IonValue::String(account_number.clone()));
+            Box::pin(async move { holds::find(tx, &filter).await })
+        })
+        .await
+    }
+
+    async fn place_hold(
+        &self,
+        account_number: String,
+        amount: BigDecimal,
+        currency: Option<Currency>,
+        ttl: chrono::Duration,
+    ) -> Result<Hold, AppError> {
+        transaction_within(self, |tx| {
+            let account_number = account_number.clone();
+            let amount = amount.clone();
+            Box::pin(async move { holds::place(tx, account_number, amount, currency, ttl).await })
+        })
+        .await
+    }
+
+    async fn capture_hold(
+        &self,
+        account_number: String,
+        hold_id: String,
+        amount: Option<BigDecimal>,
+        counterparty: String,
+        transaction_type: TransactionType,
+        limits: Arc<LimitPolicy>,
+    ) -> Result<CapturedHold, AppError> {
+        transaction_within(self, |tx| {
+            let account_number = account_number.clone();
+            let hold_id = hold_id.clone();
+            let amount = amount.clone();
+            let counterparty = counterparty.clone();
+            let transaction_type = transaction_type.clone();
+            let limits = limits.clone();
+            Box::pin(async move {
+                holds::capture(
+                    tx,
+                    account_number,
+                    hold_id,
+                    amount,
+                    counterparty,
+                    transaction_type,
+                    &limits,
+                )
+                .await
+            })
+        })
+        .await
+    }
+
+    async fn release_hold(
+        &self,
+        account_number: String,
+        hold_id: String,
+    ) -> Result<Hold, AppError> {
+        transaction_within(self, |tx| {
+            let account_number = account_number.clone();
+            let hold_id = hold_id.clone();
+            Box::pin(async move { holds::release(tx, account_number, hold_id).await })
+        })
+        .await
+    }
+
+    async fn close_account(
+        &self,
+        account_number: String,
+        closure: AccountClosure,
+    ) -> Result<Account, AppError> {
+        transaction_within(self, |tx| {
+            let account_number = account_number.clone();
+            let closure = closure.clone();
+            Box::pin(async move { ledger::close_account(tx, account_number, closure).await })
+        })
+        .await
+    }
+}
+
+/// Runs `work` inside a fresh transaction, committing on success and rolling
+/// back on failure. Conflicting commits are retried with a new transaction.
+pub async fn transaction_within<S, R, F>(storage: &S, work: F) -> Result<R, AppError>
+where
+    S: LedgerStorage + ?Sized,
+    F: for<'t> Fn(&'t mut dyn LedgerTransaction) -> TxFuture<'t, R>,
+{
+    retry_within(storage, false, work).await
+}
+
+/// Like `transaction_within`, for work that reads. The transaction is begun
+/// with `begin_read`; if it conflicts, which a read only does when it also
+/// writes (e.g. expiring a hold), it is retried with `begin`.
+pub async fn read_within<S, R, F>(storage: &S, work: F) -> Result<R, AppError>
+where
+    S: LedgerStorage + ?Sized,
+    F: for<'t> Fn(&'t mut dyn LedgerTransaction) -> TxFuture<'t, R>,
+{
+    retry_within(storage, true, work).await
+}
+
+async fn retry_within<S, R, F>(storage: &S, read: bool, work: F) -> Result<R, AppError>
+where
+    S: LedgerStorage + ?Sized,
+    F: for<'t> Fn(&'t mut dyn LedgerTransaction) -> TxFuture<'t, R>,
+{
+    let mut attempt = 0;
+    loop {
+        let mut tx = if read && attempt == 0 {
+            storage.begin_read().await?
+        } else {
+            storage.begin().await?
+        };
+        let result = work(tx.as_mut()).await;
+        match finish(tx, result).await {
+            Err(e) if e.is_retryable() && attempt < MAX_RETRIES => {
+                attempt += 1;
+                debug!("Retrying transaction after conflict (attempt {})", attempt);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Makes `posting` unless `idempotency_key` was already used, checking it
+/// against `limits` first when they are given.
+async fn post(
+    tx: &mut dyn LedgerTransaction,
+    posting: Posting,
+    idempotency_key: Option<IdempotencyKey>,
+    limits: Option<&LimitPolicy>,
+) -> Result<TransactionReceipt, AppError> {
+    if let Some(receipt) = idempotency::replay(tx, idempotency_key.as_ref()).await? {
+        return Ok(receipt);
+    }
+    if let Some(policy) = limits {
+        limits::check(tx, policy, &posting).await?;
+    }
+    let receipt = match posting {
+        Posting::DebitCredit {
+            account_number,
+            contra_account_number,
+            amount,
+            transaction_type,
+        } => {
+            ledger::debit_credit(
+                tx,
+                account_number,
+                contra_account_number,
+                amount,
+                transaction_type,
+            )
+            .await?
+        }
+        Posting::Transfer {
+            sender_account_number,
+            recipient_account_number,
+            amount,
+            conversion,
+        } => {
+            ledger::transfer(
+                tx,
+                sender_account_number,
+                recipient_account_number,
+                amount,
+                conversion,
+            )
+            .await?
+        }
+    };
+    idempotency::remember(tx, idempotency_key, &receipt).await?;
+    Ok(receipt)
+}
+
+async fn finish<R>(
+    tx: Box<dyn LedgerTransaction>,
+    result: Result<R, AppError>,
+) -> Result<R, AppError> {
+    match result {
+        Ok(value) => {
+            tx.commit().await?;
+            Ok(value)
+        }
+        Err(e) => {
+            tx.rollback().await?;
+            Err(e)
+        }
+    }
+}
+
+/// Account numbers crafted to break out of a quoted query value. Lookups with
+/// any of them must behave like a lookup of an unknown account.
+#[cfg(test)]
+pub const HOSTILE_ACCOUNT_NUMBERS: &[&str] = &[
+    "' OR '1'='1",
+    "x' OR 1=1 --",
+    "'; DELETE FROM accounts; --",
+    "\"; DROP TABLE accounts; --",
+    "1' UNION SELECT * FROM accounts --",
+    "` OR true",
+    "?",
+    "%",
+    "'",
+    "\\' OR 1=1",
+    "0000000000' OR b.balance > 0 OR '",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::prelude::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_where_clause_only_holds_placeholders() {
+        for hostile in HOSTILE_ACCOUNT_NUMBERS {
+            let filter = Filter::all().eq("account_number", IonValue::String(hostile.to_string()));
+            let clause = filter.where_clause("b.").unwrap();
+            assert_eq!(clause, " WHERE b.account_number = ?");
+            assert_eq!(
+                filter.values(),
+                vec![&IonValue::String(hostile.to_string())]
+            );
+        }
+    }
+
+    #[test]
+    fn test_identifiers_are_checked() {
+        assert!(check_identifier("ledger_entries").is_ok());
+        assert!(check_identifier("accounts; DROP TABLE accounts").is_err());
+        assert!(check_identifier("a.b").is_err());
+        assert!(check_identifier("1accounts").is_err());
+        assert!(check_identifier("").is_err());
+
+        let filter = Filter::all().eq("name = name OR 1", IonValue::String("x".to_string()));
+        assert!(filter.where_clause("").is_err());
+        let filter = Filter::all().descending(&["created_at; DROP TABLE accounts"]);
+        assert!(filter.order_clause().is_err());
+    }
+
+    #[test]
+    fn test_page_filter() {
+        let at = |hour: u32| IonValue::DateTime(Utc.ymd(2026, 1, 1).and_hms(hour, 0, 0).into());
+        let doc = |hour: u32, id: &str| {
+            let mut doc = HashMap::new();
+            doc.insert("created_at".to_string(), at(hour));
+            doc.insert("id".to_string(), IonValue::String(id.to_string()));
+            doc
+        };
+        let filter = Filter::all()
+            .gte("created_at", at(9))
+            .before(
+                ("created_at", at(12)),
+                ("id", IonValue::String("m".to_string())),
+            )
+            .descending(&["created_at", "id"])
+            .limit(2);
+
+        assert_eq!(
+            filter.where_clause("").unwrap(),
+            " WHERE created_at >= ? AND (created_at < ? OR (created_at = ? AND id < ?))"
+        );
+        assert_eq!(filter.values().len(), 4);
+        assert_eq!(
+            filter.order_clause().unwrap(),
+            " ORDER BY created_at DESC, id DESC LIMIT 2"
+        );
+        assert!(filter.matches(&doc(9, "z")));
+        assert!(filter.matches(&doc(12, "a")));
+        assert!(!filter.matches(&doc(12, "m")));
+        assert!(!filter.matches(&doc(13, "a")));
+        assert!(!filter.matches(&doc(8, "a")));
+
+        let docs = vec![doc(9, "a"), doc(11, "a"), doc(11, "b")]
+            .into_iter()
+            .map(|doc| Document::try_from(IonValue::Struct(doc)).unwrap())
+            .collect();
+        let ids: Vec<String> = filter
+            .arrange(docs)
+            .iter()
+            .map(|doc| doc.get_value("id").unwrap())
+            .collect();
+        assert_eq!(ids, vec!["b", "a"]);
+    }
+}
@@ -1,24 +1,143 @@
 use bigdecimal::BigDecimal;
 use chrono::prelude::*;
-use ion_binary_rs::IonValue;
+use ion_binary_rs::{IonValue, NullIonValue};
 use qldb::Document;
 use std::collections::HashMap;
 use std::fmt::{Display, Error as FmtError, Formatter};
+use std::str::FromStr;
 use std::{convert::TryFrom, convert::TryInto};
 
 use super::default_datetime;
 use super::QldbInsertable;
-use crate::error::AppError;
+use super::Validator;
+use super::{optional_value, AccountTier, Currency, Customer, NewCustomer, TransactionLimits};
+use crate::error::{AppError, ErrorType};
 use crate::util;
 use serde::{Deserialize, Serialize};
 
-const TABLE_NAME: &str = "accounts";
-#[derive(Debug, Serialize, Deserialize)]
+pub const TABLE_NAME: &str = "accounts";
+
+/// Where an account is in its lifecycle. Only active accounts can send money;
+/// frozen and dormant accounts can still receive it, closed accounts can do
+/// neither.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccountStatus {
+    #[default]
+    ACTIVE,
+    FROZEN,
+    DORMANT,
+    CLOSED,
+}
+
+impl AccountStatus {
+    pub fn can_send(&self) -> bool {
+        matches!(self, AccountStatus::ACTIVE)
+    }
+
+    pub fn can_receive(&self) -> bool {
+        !matches!(self, AccountStatus::CLOSED)
+    }
+
+    /// Closing is final; every other state can be left for any other one
+    /// except that only active accounts become dormant.
+    pub fn can_change_to(&self, next: AccountStatus) -> bool {
+        match (self, next) {
+            (AccountStatus::CLOSED, _) => false,
+            (current, next) if *current == next => false,
+            (_, AccountStatus::DORMANT) => *self == AccountStatus::ACTIVE,
+            _ => true,
+        }
+    }
+}
+
+impl Display for AccountStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for AccountStatus {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ACTIVE" => Ok(AccountStatus::ACTIVE),
+            "FROZEN" => Ok(AccountStatus::FROZEN),
+            "DORMANT" => Ok(AccountStatus::DORMANT),
+            "CLOSED" => Ok(AccountStatus::CLOSED),
+            _ => Err(AppError::from_type(ErrorType::Custom(format!(
+                "Unknown account status: {}",
+                s
+            )))),
+        }
+    }
+}
+
+/// Who has to agree before money leaves a jointly held account: any one of
+/// its owners, or all of them.
+#[allow(clippy::upper_case_acronyms, non_camel_case_types)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SigningRule {
+    #[default]
+    ANY_ONE,
+    ALL,
+}
+
+impl Display for SigningRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for SigningRule {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ANY_ONE" => Ok(SigningRule::ANY_ONE),
+            "ALL" => Ok(SigningRule::ALL),
+            _ => Err(AppError::from_type(ErrorType::Custom(format!(
+                "Unknown signing rule: {}",
+                s
+            )))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Account {
     pub account_number: String,
+    /// Customer holding the account. Accounts opened before customers
+    /// existed, and the bank's own accounts, have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub customer_id: Option<String>,
     pub name: String,
     pub phone: String,
     pub balance: BigDecimal,
+    /// How far below zero the balance may go.
+    #[serde(default)]
+    pub overdraft_limit: BigDecimal,
+    /// Funds reserved by open holds.
+    #[serde(skip_deserializing)]
+    held_amount: BigDecimal,
+    /// Balance plus overdraft less holds, what the account can still spend.
+    #[serde(skip_deserializing)]
+    available_balance: BigDecimal,
+    pub currency: Currency,
+    #[serde(default)]
+    pub status: AccountStatus,
+    #[serde(default)]
+    pub tier: AccountTier,
+    #[serde(default)]
+    pub signing_rule: SigningRule,
+    /// Limits set on this account, overriding those of its tier.
+    #[serde(default)]
+    pub limits: TransactionLimits,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub closure_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub closed_at: Option<DateTime<FixedOffset>>,
     #[serde(skip, default = "default_datetime")]
     pub created_at: DateTime<FixedOffset>,
     #[serde(skip, default = "default_datetime")]
@@ -26,19 +145,190 @@ pub struct Account {
 }
 
 impl Account {
-    pub fn new(name: String, phone: String) -> Account {
+    pub fn new(name: String, phone: String, currency: Currency) -> Account {
         let account_number = util::generate_numeric(10).unwrap();
+        Account::with_number(account_number, name, phone, currency)
+    }
+
+    /// A new account held by `customer`, under the customer's name and phone.
+    pub fn for_customer(customer: &Customer, currency: Currency) -> Account {
+        let mut account = Account::new(customer.full_name(), customer.phone.clone(), currency);
+        account.customer_id = Some(customer.id.clone());
+        account
+    }
+
+    pub fn with_number(
+        account_number: String,
+        name: String,
+        phone: String,
+        currency: Currency,
+    ) -> Account {
         let now: DateTime<FixedOffset> = Utc::now().into();
+        let zero = BigDecimal::default().with_scale(currency.minor_units());
         Account {
             account_number,
+            customer_id: None,
             name,
             phone,
-            balance: BigDecimal::default().with_scale(2),
+            balance: zero.clone(),
+            overdraft_limit: zero.clone(),
+            held_amount: zero.clone(),
+            available_balance: zero,
+            currency,
+            status: AccountStatus::ACTIVE,
+            tier: AccountTier::default(),
+            signing_rule: SigningRule::default(),
+            limits: TransactionLimits::default(),
+            closure_reason: None,
+            closed_at: None,
             created_at: now,
             updated_at: now,
         }
     }
 
+    /// Moves the account to `status`. Closing needs a zero balance so no money
+    /// is left behind in a closed account.
+    pub fn change_status(&mut self, status: AccountStatus) -> Result<(), AppError> {
+        if !self.status.can_change_to(status) {
+            let msg = format!(
+                "Account {} cannot go from {} to {}",
+                self.account_number, self.status, status
+            );
+            return Err(AppError::from_type(ErrorType::AccountError(msg)));
+        }
+        if status == AccountStatus::CLOSED && self.balance != BigDecimal::default() {
+            let msg = format!(
+                "Account {} still holds {} {} and cannot be closed",
+                self.account_number, self.balance, self.currency
+            );
+            return Err(AppError::from_type(ErrorType::AccountError(msg)));
+        }
+        self.status = status;
+        self.updated_at = Utc::now().into();
+        Ok(())
+    }
+
+    /// Closes the account for good, keeping why and when for audit.
+    pub fn close(&mut self, reason: String) -> Result<(), AppError> {
+        let reason = reason.trim().to_string();
+        if reason.is_empty() {
+            let msg = "A closure reason is required".to_string();
+            return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+        }
+        self.change_status(AccountStatus::CLOSED)?;
+        self.closure_reason = Some(reason);
+        self.closed_at = Some(self.updated_at);
+        Ok(())
+    }
+
+    pub fn available_balance(&self) -> &BigDecimal {
+        &self.available_balance
+    }
+
+    pub fn held_amount(&self) -> &BigDecimal {
+        &self.held_amount
+    }
+
+    /// Records the funds reserved by open holds, which are kept outside the
+    /// account document.
+    pub fn set_held_amount(&mut self, held_amount: BigDecimal) {
+        self.held_amount = held_amount.with_scale(self.currency.minor_units());
+        self.update_available_balance();
+    }
+
+    fn update_available_balance(&mut self) {
+        self.available_balance =
+            self.balance.clone() + self.overdraft_limit.clone() - self.held_amount.clone();
+    }
+
+    pub fn set_overdraft_limit(&mut self, limit: BigDecimal) -> Result<(), AppError> {
+        if self.status == AccountStatus::CLOSED {
+            let msg = format!("Account {} is CLOSED", self.account_number);
+            return Err(AppError::from_type(ErrorType::AccountError(msg)));
+        }
+        if limit < BigDecimal::default() || !self.currency.fits(&limit) {
+            let msg = format!(
+                "Overdraft limit must be a non-negative {} amount with at most {} decimal places",
+                self.currency,
+                self.currency.minor_units()
+            );
+            return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+        }
+        self.overdraft_limit = limit.with_scale(self.currency.minor_units());
+        self.update_available_balance();
+        self.updated_at = Utc::now().into();
+        Ok(())
+    }
+
+    pub fn set_limits(
+        &mut self,
+        tier: Option<AccountTier>,
+        limits: TransactionLimits,
+    ) -> Result<(), AppError> {
+        limits.check(self.currency)?;
+        if let Some(tier) = tier {
+            self.tier = tier;
+        }
+        self.limits = limits;
+        self.updated_at = Utc::now().into();
+        Ok(())
+    }
+
+    pub fn set_signing_rule(&mut self, signing_rule: SigningRule) -> Result<(), AppError> {
+        if self.status == AccountStatus::CLOSED {
+            let msg = format!("Account {} is CLOSED", self.account_number);
+            return Err(AppError::from_type(ErrorType::AccountError(msg)));
+        }
+        self.signing_rule = signing_rule;
+        self.updated_at = Utc::now().into();
+        Ok(())
+    }
+
+    pub fn ensure_can_send(&self) -> Result<(), AppError> {
+        if self.status.can_send() {
+            return Ok(());
+        }
+        let msg = format!(
+            "Account {} is {} and cannot send money",
+            self.account_number, self.status
+        );
+        Err(AppError::from_type(ErrorType::AccountError(msg)))
+    }
+
+    pub fn ensure_can_receive(&self) -> Result<(), AppError> {
+        if self.status.can_receive() {
+            return Ok(());
+        }
+        let msg = format!(
+            "Account {} is {} and cannot receive money",
+            self.account_number, self.status
+        );
+        Err(AppError::from_type(ErrorType::AccountError(msg)))
+    }
+
+    /// The account's own limits as document fields, null where none is set.
+    pub fn limit_params(&self) -> Vec<(&'static str, IonValue)> {
+        let decimal = |amount: &Option<BigDecimal>| match amount {
+            Some(amount) => IonValue::Decimal(amount.clone()),
+            None => IonValue::Null(NullIonValue::Decimal),
+        };
+        let count = match self.limits.max_daily_transfer_count {
+            Some(count) => IonValue::Integer(count.into()),
+            None => IonValue::Null(NullIonValue::Integer),
+        };
+        vec![
+            (
+                "max_transaction_amount",
+                decimal(&self.limits.max_transaction_amount),
+            ),
+            (
+                "max_daily_debit_total",
+                decimal(&self.limits.max_daily_debit_total),
+            ),
+            ("max_daily_transfer_count", count),
+        ]
+    }
+
     pub fn from_documents(result: Vec<Document>) -> Vec<Self> {
         result
             .iter()
@@ -50,8 +340,8 @@ impl Account {
 
 impl Display for Account {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
-        write!(f, "Account {{ account_number: {}, name: {}, phone: {}, balance: {}, created_at: {}, updated_at: {} }}", 
-        self.account_number, self.name, self.phone, self.balance, self.created_at, self.updated_at)
+        write!(f, "Account {{ account_number: {}, name: {}, phone: {}, balance: {}, currency: {}, status: {}, created_at: {}, updated_at: {} }}", 
+        self.account_number, self.name, self.phone, self.balance, self.currency, self.status, self.created_at, self.updated_at)
     }
 }
 
@@ -66,6 +356,12 @@ impl QldbInsertable for Account {
             "account_number".to_string(),
             IonValue::String(self.account_number.to_string()),
         );
+        if let Some(customer_id) = &self.customer_id {
+            params.insert(
+                "customer_id".to_string(),
+                IonValue::String(customer_id.clone()),
+            );
+        }
         params.insert("name".to_string(), IonValue::String(self.name.to_string()));
         params.insert(
             "phone".to_string(),
@@ -75,13 +371,44 @@ impl QldbInsertable for Account {
             "balance".to_string(),
             IonValue::Decimal(self.balance.clone()),
         );
+        params.insert(
+            "overdraft_limit".to_string(),
+            IonValue::Decimal(self.overdraft_limit.clone()),
+        );
+        params.insert(
+            "currency".to_string(),
+            IonValue::String(self.currency.to_string()),
+        );
+        params.insert(
+            "status".to_string(),
+            IonValue::String(self.status.to_string()),
+        );
+        params.insert("tier".to_string(), IonValue::String(self.tier.to_string()));
+        params.insert(
+            "signing_rule".to_string(),
+            IonValue::String(self.signing_rule.to_string()),
+        );
+        params.extend(
+            self.limit_params()
+                .into_iter()
+                .map(|(field, value)| (field.to_string(), value)),
+        );
+        if let Some(reason) = &self.closure_reason {
+            params.insert(
+                "closure_reason".to_string(),
+                IonValue::String(reason.to_string()),
+            );
+        }
+        if let Some(closed_at) = self.closed_at {
+            params.insert("closed_at".to_string(), IonValue::DateTime(closed_at));
+        }
         params.insert(
             "created_at".to_string(),
-            IonValue::DateTime(self.created_at.clone()),
+            IonValue::DateTime(self.created_at),
         );
         params.insert(
             "updated_at".to_string(),
-            IonValue::DateTime(self.updated_at.clone()),
+            IonValue::DateTime(self.updated_at),
         );
         params
     }
@@ -95,13 +422,60 @@ impl TryFrom<&Document> for Account {
         let name: String = doc.get_value("name")?;
         let phone: String = doc.get_value("phone")?;
         let balance: BigDecimal = doc.get_value("balance")?;
+        let overdraft_limit: Option<BigDecimal> = doc.get_optional_value("overdraft_limit")?;
+        // Accounts opened before multi-currency support hold dollars.
+        let currency: Option<String> = doc.get_optional_value("currency")?;
+        let currency = match currency {
+            Some(code) => code.parse()?,
+            None => Currency::USD,
+        };
+        let balance = balance.with_scale(currency.minor_units());
+        let overdraft_limit = overdraft_limit
+            .unwrap_or_default()
+            .with_scale(currency.minor_units());
+        let status: Option<String> = doc.get_optional_value("status")?;
+        let status = match status {
+            Some(status) => status.parse()?,
+            None => AccountStatus::ACTIVE,
+        };
+        let tier: Option<String> = doc.get_optional_value("tier")?;
+        let tier = match tier {
+            Some(tier) => tier.parse()?,
+            None => AccountTier::default(),
+        };
+        let signing_rule: Option<String> = doc.get_optional_value("signing_rule")?;
+        let signing_rule = match signing_rule {
+            Some(signing_rule) => signing_rule.parse()?,
+            None => SigningRule::default(),
+        };
+        let max_daily_transfer_count: Option<i64> =
+            optional_value(doc, "max_daily_transfer_count")?;
+        let limits = TransactionLimits {
+            max_transaction_amount: optional_value(doc, "max_transaction_amount")?,
+            max_daily_debit_total: optional_value(doc, "max_daily_debit_total")?,
+            max_daily_transfer_count: max_daily_transfer_count
+                .map(|count| count.try_into().unwrap_or(u32::MAX)),
+        };
+        let closure_reason: Option<String> = doc.get_optional_value("closure_reason")?;
+        let closed_at: Option<DateTime<FixedOffset>> = doc.get_optional_value("closed_at")?;
         let created_at: DateTime<FixedOffset> = doc.get_value("created_at")?;
         let updated_at: DateTime<FixedOffset> = doc.get_value("updated_at")?;
         let account = Account {
             account_number,
+            customer_id: optional_value(doc, "customer_id")?,
             name,
             phone,
-            balance: balance.with_scale(2),
+            available_balance: balance.clone() + overdraft_limit.clone(),
+            held_amount: BigDecimal::default().with_scale(currency.minor_units()),
+            balance,
+            overdraft_limit,
+            currency,
+            status,
+            tier,
+            signing_rule,
+            limits,
+            closure_reason,
+            closed_at,
             created_at,
             updated_at,
         };
@@ -109,14 +483,53 @@ impl TryFrom<&Document> for Account {
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// Opens an account for an existing customer, given by `customer_id`, or for
+/// a new one described in `customer`. Existing customers in
+/// `joint_customer_ids` hold the account with them.
+#[derive(Debug, Default, Deserialize)]
 pub struct NewAccount {
-    pub name: String,
-    pub phone: String,
+    pub customer_id: Option<String>,
+    pub customer: Option<NewCustomer>,
+    #[serde(default)]
+    pub joint_customer_ids: Vec<String>,
+    #[serde(default)]
+    pub signing_rule: SigningRule,
+    #[serde(default)]
+    pub currency: Currency,
 }
 
-impl Into<Account> for NewAccount {
-    fn into(self) -> Account {
-        Account::new(self.name, self.phone)
+impl NewAccount {
+    /// Checks the request and normalizes the details of a new customer.
+    pub fn validate(&mut self) -> Result<(), AppError> {
+        let mut validator = Validator::default();
+        if let Some(customer_id) = &self.customer_id {
+            if customer_id.trim().is_empty() {
+                validator.invalid("customer_id", "must not be empty");
+            }
+        }
+        if let Some(customer) = &mut self.customer {
+            customer.check(&mut validator, "customer.");
+        }
+        if self
+            .joint_customer_ids
+            .iter()
+            .any(|id| id.trim().is_empty())
+        {
+            validator.invalid("joint_customer_ids", "must not contain empty ids");
+        }
+        validator.finish()
     }
 }
+
+#[derive(Debug, Deserialize)]
+pub struct OverdraftLimit {
+    pub overdraft_limit: BigDecimal,
+}
+
+/// A request to close an account. Any remaining balance is moved to the
+/// settlement account, which must hold the same currency.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccountClosure {
+    pub settlement_account_number: Option<String>,
+    pub reason: String,
+}
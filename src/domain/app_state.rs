@@ -1,13 +1,92 @@
-use crate::{core::QldbProcessor, util::Config, AppError};
+use crate::{
+    core::{MemoryProcessor, QldbProcessor, Rates, SqlProcessor, StaticRateProvider, Storage},
+    domain::{LimitPolicy, ScheduleRetry, SystemAccounts},
+    error::ErrorType,
+    service::AccountService,
+    util::{Config, JwtVerifier, StorageBackend},
+    AppError,
+};
+use chrono::Duration;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub processor: QldbProcessor,
+    pub processor: Storage,
+    pub system_accounts: SystemAccounts,
+    pub rates: Rates,
+    pub fx_quote_ttl: Duration,
+    pub hold_ttl: Duration,
+    /// How long a transfer waits for the approval of every owner.
+    pub approval_ttl: Duration,
+    pub limits: Arc<LimitPolicy>,
+    pub schedule_retry: ScheduleRetry,
+    pub auth_enabled: bool,
+    /// Verifier for bearer tokens, when they are configured.
+    pub jwt: Option<Arc<JwtVerifier>>,
 }
 
 impl AppState {
     pub async fn new(config: Config) -> Result<AppState, AppError> {
-        let processor = QldbProcessor::new(config.ledger_name, config.session_pool_size).await?;
-        Ok(AppState { processor })
+        let system_accounts = SystemAccounts::from(&config);
+        let rates: Rates = if std::path::Path::new(&config.fx_rates_path).exists() {
+            Arc::new(StaticRateProvider::from_file(&config.fx_rates_path)?)
+        } else {
+            warn!(
+                "FX rates file {} not found, cross-currency transfers are disabled",
+                config.fx_rates_path
+            );
+            Arc::new(StaticRateProvider::default())
+        };
+        let limits = if std::path::Path::new(&config.limits_path).exists() {
+            LimitPolicy::from_file(&config.limits_path)?
+        } else {
+            warn!(
+                "Limits file {} not found, transactions are not limited",
+                config.limits_path
+            );
+            LimitPolicy::default()
+        };
+        let fx_quote_ttl = Duration::seconds(config.fx_quote_ttl_seconds);
+        let hold_ttl = Duration::seconds(config.hold_ttl_seconds);
+        let approval_ttl = Duration::seconds(config.approval_ttl_seconds);
+        let schedule_retry = ScheduleRetry {
+            max_attempts: config.schedule_max_attempts.max(1),
+            delay: Duration::seconds(config.schedule_retry_delay_seconds),
+        };
+        let jwt = JwtVerifier::from_config(&config)?.map(Arc::new);
+        if !config.auth_enabled {
+            warn!("Authentication is disabled, every endpoint is open");
+        }
+        let processor: Storage = match config.storage_backend {
+            StorageBackend::Qldb => match (config.ledger_name, config.session_pool_size) {
+                (Some(ledger_name), Some(session_pool_size)) => {
+                    Arc::new(QldbProcessor::new(ledger_name, session_pool_size).await?)
+                }
+                _ => {
+                    let msg = "LEDGER_NAME and SESSION_POOL_SIZE are required by the qldb storage backend";
+                    return Err(AppError::from_type(ErrorType::Custom(msg.to_string())));
+                }
+            },
+            StorageBackend::Memory => {
+                warn!("Using in-memory storage, data will be lost on shutdown");
+                Arc::new(MemoryProcessor::new())
+            }
+            StorageBackend::Sqlite => Arc::new(SqlProcessor::new(config.database_path)?),
+        };
+        AccountService::new(processor.clone())
+            .open_system_accounts(&system_accounts)
+            .await?;
+        Ok(AppState {
+            processor,
+            system_accounts,
+            rates,
+            fx_quote_ttl,
+            hold_ttl,
+            approval_ttl,
+            limits: Arc::new(limits),
+            schedule_retry,
+            auth_enabled: config.auth_enabled,
+            jwt,
+        })
     }
 }
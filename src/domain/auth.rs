@@ -0,0 +1,263 @@
+use chrono::prelude::*;
+use ion_binary_rs::IonValue;
+use qldb::Document;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::str::FromStr;
+
+use super::{optional_value, QldbInsertable};
+use crate::error::{AppError, ErrorType};
+use crate::util;
+
+pub const TABLE_NAME: &str = "api_keys";
+
+const KEY_PREFIX: &str = "bk_";
+const KEY_LENGTH: u32 = 40;
+
+/// What a caller is allowed to do. Customers read and send from their own
+/// accounts, tellers also credit and debit any account, admins can do
+/// anything and auditors can read everything but change nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Customer,
+    Teller,
+    Admin,
+    Auditor,
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        let role = match self {
+            Role::Customer => "customer",
+            Role::Teller => "teller",
+            Role::Admin => "admin",
+            Role::Auditor => "auditor",
+        };
+        write!(f, "{}", role)
+    }
+}
+
+impl FromStr for Role {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "customer" => Ok(Role::Customer),
+            "teller" => Ok(Role::Teller),
+            "admin" => Ok(Role::Admin),
+            "auditor" => Ok(Role::Auditor),
+            _ => Err(AppError::from_type(ErrorType::Custom(format!(
+                "Unknown role: {}",
+                s
+            )))),
+        }
+    }
+}
+
+/// Static credential for a client. Only the SHA-256 hash of the key is
+/// stored; the key itself is shown once, when it is created. Keys are for
+/// staff and back-office services, so they cannot have the customer role.
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub role: Role,
+    #[serde(skip)]
+    pub key_hash: String,
+    pub created_at: DateTime<FixedOffset>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<DateTime<FixedOffset>>,
+}
+
+impl ApiKey {
+    /// A new key for `name` and the secret to hand to the client.
+    pub fn generate(name: String, role: Role) -> Result<(ApiKey, String), AppError> {
+        if role == Role::Customer {
+            return Err(AppError::new(
+                Some("API keys cannot have the customer role".to_string()),
+                ErrorType::PayloadError,
+            ));
+        }
+        let key = format!("{}{}", KEY_PREFIX, util::generate_alphanumeric(KEY_LENGTH)?);
+        let api_key = ApiKey {
+            id: util::generate_alphanumeric(20)?,
+            name,
+            role,
+            key_hash: ApiKey::hash(&key),
+            created_at: Utc::now().into(),
+            revoked_at: None,
+        };
+        Ok((api_key, key))
+    }
+
+    pub fn hash(key: &str) -> String {
+        format!("{:x}", Sha256::digest(key.as_bytes()))
+    }
+
+    pub fn from_documents(docs: Vec<Document>) -> Result<Vec<Self>, AppError> {
+        docs.iter().map(ApiKey::try_from).collect()
+    }
+}
+
+impl QldbInsertable for ApiKey {
+    fn table_name(&self) -> &str {
+        TABLE_NAME
+    }
+
+    fn to_params(&self) -> HashMap<String, IonValue> {
+        let mut params = HashMap::new();
+        params.insert("id".to_string(), IonValue::String(self.id.clone()));
+        params.insert("name".to_string(), IonValue::String(self.name.clone()));
+        params.insert("role".to_string(), IonValue::String(self.role.to_string()));
+        params.insert(
+            "key_hash".to_string(),
+            IonValue::String(self.key_hash.clone()),
+        );
+        params.insert(
+            "created_at".to_string(),
+            IonValue::DateTime(self.created_at),
+        );
+        if let Some(revoked_at) = self.revoked_at {
+            params.insert("revoked_at".to_string(), IonValue::DateTime(revoked_at));
+        }
+        params
+    }
+}
+
+impl TryFrom<&Document> for ApiKey {
+    type Error = AppError;
+
+    fn try_from(doc: &Document) -> Result<Self, Self::Error> {
+        // Keys created before roles existed had full access.
+        let role: Option<String> = doc.get_optional_value("role")?;
+        let role = match role {
+            Some(role) => role.parse()?,
+            None => Role::Admin,
+        };
+        Ok(ApiKey {
+            id: doc.get_value("id")?,
+            name: doc.get_value("name")?,
+            role,
+            key_hash: doc.get_value("key_hash")?,
+            created_at: doc.get_value("created_at")?,
+            revoked_at: optional_value(doc, "revoked_at")?,
+        })
+    }
+}
+
+/// Who a request was authenticated as.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Principal {
+    ApiKey {
+        id: String,
+        name: String,
+        role: Role,
+    },
+    /// A bearer token. For customers, `subject` is their customer id and
+    /// `account_numbers` are the accounts they own.
+    Token {
+        subject: String,
+        role: Role,
+        account_numbers: Vec<String>,
+    },
+    /// Every request when authentication is disabled. It has full access.
+    Anonymous,
+}
+
+impl Principal {
+    pub fn role(&self) -> Role {
+        match self {
+            Principal::ApiKey { role, .. } | Principal::Token { role, .. } => *role,
+            Principal::Anonymous => Role::Admin,
+        }
+    }
+
+    /// Whether the account is one of the customer's own.
+    pub fn owns(&self, account_number: &str) -> bool {
+        match self {
+            Principal::Token {
+                account_numbers, ..
+            } => account_numbers.iter().any(|owned| owned == account_number),
+            _ => false,
+        }
+    }
+
+    /// Id of the customer a customer token was issued to.
+    pub fn customer_id(&self) -> Option<&str> {
+        match self {
+            Principal::Token { subject, role, .. } if *role == Role::Customer => Some(subject),
+            _ => None,
+        }
+    }
+
+    /// Namespace of the idempotency keys the principal sends, so a key one
+    /// client uses never answers another client's request.
+    pub fn idempotency_scope(&self) -> String {
+        match self {
+            Principal::ApiKey { id, .. } => format!("key:{}", id),
+            Principal::Token { subject, .. } => format!("token:{}", subject),
+            Principal::Anonymous => "anonymous".to_string(),
+        }
+    }
+
+    /// Whether the principal is the customer with this id.
+    pub fn is_customer(&self, customer_id: &str) -> bool {
+        self.customer_id() == Some(customer_id)
+    }
+
+    /// Customers can only see their own accounts; every other role sees all.
+    pub fn can_read(&self, account_number: &str) -> bool {
+        self.role() != Role::Customer || self.owns(account_number)
+    }
+
+    /// Fails with `Forbidden` unless the principal has one of `roles`.
+    pub fn require(&self, roles: &[Role]) -> Result<(), AppError> {
+        if roles.contains(&self.role()) {
+            Ok(())
+        } else {
+            Err(AppError::new(
+                Some(format!("The {} role cannot do this", self.role())),
+                ErrorType::Forbidden,
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_principal_permissions() {
+        let customer = Principal::Token {
+            subject: "sam@example.com".to_string(),
+            role: Role::Customer,
+            account_numbers: vec!["0123456789".to_string()],
+        };
+        assert!(customer.can_read("0123456789"));
+        assert!(!customer.can_read("9876543210"));
+        assert!(customer.is_customer("sam@example.com"));
+        assert_eq!(customer.customer_id(), Some("sam@example.com"));
+        assert!(customer.require(&[Role::Teller, Role::Admin]).is_err());
+
+        let auditor = Principal::ApiKey {
+            id: "key".to_string(),
+            name: "audit".to_string(),
+            role: Role::Auditor,
+        };
+        assert!(auditor.can_read("9876543210"));
+        assert!(!auditor.owns("9876543210"));
+        let err = auditor.require(&[Role::Admin]).unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::Forbidden));
+        assert_eq!(err.message(), "The auditor role cannot do this");
+
+        assert_ne!(customer.idempotency_scope(), auditor.idempotency_scope());
+        assert!(Principal::Anonymous.require(&[Role::Admin]).is_ok());
+        assert_eq!("teller".parse::<Role>().unwrap(), Role::Teller);
+        assert!("root".parse::<Role>().is_err());
+    }
+}
@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+
+use super::{NewTransaction, TransactionReceipt};
+use crate::error::{AppError, AppErrorResponse, ErrorType};
+
+pub const MAX_BATCH_SIZE: usize = 1000;
+
+/// Largest batch request body accepted, in bytes.
+pub const MAX_BATCH_BYTES: usize = 1024 * 1024;
+
+/// `atomic` batches are posted in one ledger transaction and fail as a whole;
+/// `best_effort` batches post each item on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchMode {
+    Atomic,
+    BestEffort,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewBatch {
+    pub mode: BatchMode,
+    pub transactions: Vec<NewTransaction>,
+}
+
+impl NewBatch {
+    pub fn check(&self) -> Result<(), AppError> {
+        if self.transactions.is_empty() || self.transactions.len() > MAX_BATCH_SIZE {
+            let msg = format!(
+                "A batch must have between 1 and {} transactions",
+                MAX_BATCH_SIZE
+            );
+            return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of one item, at position `index` of the request.
+#[derive(Debug, Serialize)]
+pub struct BatchItemResult {
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<TransactionReceipt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<AppErrorResponse>,
+}
+
+impl BatchItemResult {
+    pub fn new(index: usize, result: Result<TransactionReceipt, AppError>) -> BatchItemResult {
+        let (receipt, error) = match result {
+            Ok(receipt) => (Some(receipt), None),
+            Err(e) => (None, Some(e.response())),
+        };
+        BatchItemResult {
+            index,
+            receipt,
+            error,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchResult {
+    pub mode: BatchMode,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<BatchItemResult>,
+}
+
+impl BatchResult {
+    pub fn new(mode: BatchMode, results: Vec<BatchItemResult>) -> BatchResult {
+        let failed = results.iter().filter(|r| r.error.is_some()).count();
+        BatchResult {
+            mode,
+            succeeded: results.len() - failed,
+            failed,
+            results,
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::str::FromStr;
+
+use crate::error::{AppError, ErrorType};
+
+/// ISO 4217 currencies an account can be held in.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Currency {
+    #[default]
+    USD,
+    EUR,
+    GBP,
+    NGN,
+    JPY,
+    KWD,
+}
+
+impl Currency {
+    pub const ALL: [Currency; 6] = [
+        Currency::USD,
+        Currency::EUR,
+        Currency::GBP,
+        Currency::NGN,
+        Currency::JPY,
+        Currency::KWD,
+    ];
+
+    /// Number of digits after the decimal point in the smallest unit of the
+    /// currency, e.g. 2 for cents and 0 for yen.
+    pub fn minor_units(&self) -> i64 {
+        match self {
+            Currency::JPY => 0,
+            Currency::KWD => 3,
+            _ => 2,
+        }
+    }
+
+    /// Whether `amount` can be expressed in whole minor units.
+    pub fn fits(&self, amount: &BigDecimal) -> bool {
+        let (_, scale) = amount.normalized().as_bigint_and_exponent();
+        scale <= self.minor_units()
+    }
+
+    /// Rounds `amount` half up to the minor unit and pins it to that scale.
+    pub fn round(&self, amount: &BigDecimal) -> BigDecimal {
+        amount
+            .round(self.minor_units())
+            .with_scale(self.minor_units())
+    }
+}
+
+/// Exchange applied to a cross-currency transfer. The sender's amount is paid
+/// into the FX position in the sender's currency, and `rate` times that amount
+/// is paid out of the FX position in the recipient's currency.
+#[derive(Debug, Clone)]
+pub struct FxConversion {
+    pub rate: BigDecimal,
+    pub sender_position: String,
+    pub recipient_position: String,
+}
+
+impl FxConversion {
+    /// `amount` at `rate`, rounded to the minor unit of `currency`, the
+    /// currency it is paid out in.
+    pub fn convert(&self, amount: &BigDecimal, currency: Currency) -> BigDecimal {
+        currency.round(&(amount * &self.rate))
+    }
+}
+
+impl Display for Currency {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for Currency {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Currency::ALL
+            .iter()
+            .find(|currency| currency.to_string() == s)
+            .copied()
+            .ok_or_else(|| {
+                AppError::new(
+                    Some(format!("Unsupported currency: {}", s)),
+                    ErrorType::PayloadError,
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_minor_unit_scale() {
+        let amount = BigDecimal::from_str("10.50").unwrap();
+        assert!(Currency::USD.fits(&amount));
+        assert!(!Currency::JPY.fits(&amount));
+        assert!(Currency::JPY.fits(&BigDecimal::from(1000)));
+        assert!(!Currency::USD.fits(&BigDecimal::from_str("0.001").unwrap()));
+        assert!(Currency::KWD.fits(&BigDecimal::from_str("0.001").unwrap()));
+    }
+
+    #[test]
+    fn test_round_half_up_to_minor_unit() {
+        let amount = BigDecimal::from_str("12.345").unwrap();
+        assert_eq!(Currency::USD.round(&amount).to_string(), "12.35");
+        assert_eq!(Currency::JPY.round(&amount).to_string(), "12");
+        assert_eq!(Currency::KWD.round(&amount).to_string(), "12.345");
+    }
+
+    #[test]
+    fn test_converted_amount_is_in_minor_units() {
+        let conversion = FxConversion {
+            rate: BigDecimal::from_str("1.11111111").unwrap(),
+            sender_position: String::new(),
+            recipient_position: String::new(),
+        };
+        let amount = BigDecimal::from_str("10.05").unwrap();
+        assert_eq!(
+            conversion.convert(&amount, Currency::USD).to_string(),
+            "11.17"
+        );
+        assert_eq!(conversion.convert(&amount, Currency::JPY).to_string(), "11");
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("NGN".parse::<Currency>().unwrap(), Currency::NGN);
+        assert!("usd".parse::<Currency>().is_err());
+    }
+}
@@ -0,0 +1,332 @@
+use chrono::prelude::*;
+use ion_binary_rs::IonValue;
+use qldb::Document;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::str::FromStr;
+
+use super::{optional_value, QldbInsertable, Validator};
+use crate::error::{AppError, ErrorType};
+use crate::util;
+
+pub const TABLE_NAME: &str = "customers";
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Where a customer is in identity checks. New customers start pending.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KycStatus {
+    #[default]
+    PENDING,
+    VERIFIED,
+    REJECTED,
+}
+
+impl Display for KycStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for KycStatus {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "PENDING" => Ok(KycStatus::PENDING),
+            "VERIFIED" => Ok(KycStatus::VERIFIED),
+            "REJECTED" => Ok(KycStatus::REJECTED),
+            _ => Err(AppError::from_type(ErrorType::Custom(format!(
+                "Unknown KYC status: {}",
+                s
+            )))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewCustomer {
+    pub first_name: String,
+    pub last_name: String,
+    pub phone: String,
+    pub email: Option<String>,
+    pub address: Option<String>,
+    pub date_of_birth: Option<NaiveDate>,
+}
+
+impl NewCustomer {
+    /// Checks the customer's details and normalizes them, e.g. the phone
+    /// number to E.164.
+    pub fn validate(&mut self) -> Result<(), AppError> {
+        let mut validator = Validator::default();
+        self.check(&mut validator, "");
+        validator.finish()
+    }
+
+    /// Records the problems with the details in `validator`, each field named
+    /// after `prefix`.
+    pub fn check(&mut self, validator: &mut Validator, prefix: &str) {
+        let field = |name: &str| format!("{}{}", prefix, name);
+        self.first_name = validator.name(&field("first_name"), &self.first_name);
+        self.last_name = validator.name(&field("last_name"), &self.last_name);
+        self.phone = validator.phone(&field("phone"), &self.phone);
+        if let Some(email) = &self.email {
+            self.email = Some(validator.email(&field("email"), email));
+        }
+        if let Some(address) = &self.address {
+            self.address = Some(validator.address(&field("address"), address));
+        }
+    }
+}
+
+/// Changes to a customer. Fields left out keep their value.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CustomerUpdate {
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub phone: Option<String>,
+    pub email: Option<String>,
+    pub address: Option<String>,
+    pub date_of_birth: Option<NaiveDate>,
+    pub kyc_status: Option<KycStatus>,
+}
+
+impl CustomerUpdate {
+    /// Checks and normalizes the fields being changed, like
+    /// `NewCustomer::validate`.
+    pub fn validate(&mut self) -> Result<(), AppError> {
+        let mut validator = Validator::default();
+        if let Some(first_name) = &self.first_name {
+            self.first_name = Some(validator.name("first_name", first_name));
+        }
+        if let Some(last_name) = &self.last_name {
+            self.last_name = Some(validator.name("last_name", last_name));
+        }
+        if let Some(phone) = &self.phone {
+            self.phone = Some(validator.phone("phone", phone));
+        }
+        if let Some(email) = &self.email {
+            self.email = Some(validator.email("email", email));
+        }
+        if let Some(address) = &self.address {
+            self.address = Some(validator.address("address", address));
+        }
+        validator.finish()
+    }
+}
+
+/// A person who holds accounts. Accounts keep the name and phone they were
+/// opened with; the customer record is where they are kept up to date.
+#[derive(Debug, Clone, Serialize)]
+pub struct Customer {
+    pub id: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub phone: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_of_birth: Option<NaiveDate>,
+    pub kyc_status: KycStatus,
+    pub created_at: DateTime<FixedOffset>,
+    pub updated_at: DateTime<FixedOffset>,
+    /// Set when the customer is deleted. The record is kept for the accounts
+    /// and journal entries that name it, but is no longer found.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<FixedOffset>>,
+}
+
+impl Customer {
+    pub fn new(new_customer: NewCustomer) -> Result<Customer, AppError> {
+        let now: DateTime<FixedOffset> = Utc::now().into();
+        Ok(Customer {
+            id: util::generate_alphanumeric(20)?,
+            first_name: new_customer.first_name,
+            last_name: new_customer.last_name,
+            phone: new_customer.phone,
+            email: new_customer.email,
+            address: new_customer.address,
+            date_of_birth: new_customer.date_of_birth,
+            kyc_status: KycStatus::default(),
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+        })
+    }
+
+    /// The name accounts opened for the customer are given.
+    pub fn full_name(&self) -> String {
+        format!("{} {}", self.first_name, self.last_name)
+    }
+
+    /// Applies `update` and returns the document fields it changed.
+    pub fn apply(&mut self, update: CustomerUpdate) -> Vec<(&'static str, IonValue)> {
+        let mut values = Vec::new();
+        if let Some(first_name) = update.first_name {
+            values.push(("first_name", IonValue::String(first_name.clone())));
+            self.first_name = first_name;
+        }
+        if let Some(last_name) = update.last_name {
+            values.push(("last_name", IonValue::String(last_name.clone())));
+            self.last_name = last_name;
+        }
+        if let Some(phone) = update.phone {
+            values.push(("phone", IonValue::String(phone.clone())));
+            self.phone = phone;
+        }
+        if let Some(email) = update.email {
+            values.push(("email", IonValue::String(email.clone())));
+            self.email = Some(email);
+        }
+        if let Some(address) = update.address {
+            values.push(("address", IonValue::String(address.clone())));
+            self.address = Some(address);
+        }
+        if let Some(date_of_birth) = update.date_of_birth {
+            let date = date_of_birth.format(DATE_FORMAT).to_string();
+            values.push(("date_of_birth", IonValue::String(date)));
+            self.date_of_birth = Some(date_of_birth);
+        }
+        if let Some(kyc_status) = update.kyc_status {
+            values.push(("kyc_status", IonValue::String(kyc_status.to_string())));
+            self.kyc_status = kyc_status;
+        }
+        self.updated_at = Utc::now().into();
+        values.push(("updated_at", IonValue::DateTime(self.updated_at)));
+        values
+    }
+
+    /// Marks the customer deleted and returns the document fields it changed.
+    pub fn delete(&mut self) -> Vec<(&'static str, IonValue)> {
+        let now: DateTime<FixedOffset> = Utc::now().into();
+        self.deleted_at = Some(now);
+        self.updated_at = now;
+        vec![
+            ("deleted_at", IonValue::DateTime(now)),
+            ("updated_at", IonValue::DateTime(now)),
+        ]
+    }
+
+    pub fn from_documents(docs: Vec<Document>) -> Result<Vec<Self>, AppError> {
+        docs.iter().map(|doc| doc.try_into()).collect()
+    }
+}
+
+impl QldbInsertable for Customer {
+    fn table_name(&self) -> &str {
+        TABLE_NAME
+    }
+
+    fn to_params(&self) -> HashMap<String, IonValue> {
+        let mut params = HashMap::new();
+        params.insert("id".to_string(), IonValue::String(self.id.clone()));
+        params.insert(
+            "first_name".to_string(),
+            IonValue::String(self.first_name.clone()),
+        );
+        params.insert(
+            "last_name".to_string(),
+            IonValue::String(self.last_name.clone()),
+        );
+        params.insert("phone".to_string(), IonValue::String(self.phone.clone()));
+        if let Some(email) = &self.email {
+            params.insert("email".to_string(), IonValue::String(email.clone()));
+        }
+        if let Some(address) = &self.address {
+            params.insert("address".to_string(), IonValue::String(address.clone()));
+        }
+        if let Some(date_of_birth) = self.date_of_birth {
+            params.insert(
+                "date_of_birth".to_string(),
+                IonValue::String(date_of_birth.format(DATE_FORMAT).to_string()),
+            );
+        }
+        params.insert(
+            "kyc_status".to_string(),
+            IonValue::String(self.kyc_status.to_string()),
+        );
+        params.insert(
+            "created_at".to_string(),
+            IonValue::DateTime(self.created_at),
+        );
+        params.insert(
+            "updated_at".to_string(),
+            IonValue::DateTime(self.updated_at),
+        );
+        if let Some(deleted_at) = self.deleted_at {
+            params.insert("deleted_at".to_string(), IonValue::DateTime(deleted_at));
+        }
+        params
+    }
+}
+
+impl TryFrom<&Document> for Customer {
+    type Error = AppError;
+
+    fn try_from(doc: &Document) -> Result<Self, Self::Error> {
+        let date_of_birth: Option<String> = optional_value(doc, "date_of_birth")?;
+        let date_of_birth = match date_of_birth {
+            Some(date) => Some(NaiveDate::parse_from_str(&date, DATE_FORMAT).map_err(|e| {
+                AppError::from_type(ErrorType::Custom(format!(
+                    "Invalid date of birth {}: {}",
+                    date, e
+                )))
+            })?),
+            None => None,
+        };
+        let kyc_status: String = doc.get_value("kyc_status")?;
+        Ok(Customer {
+            id: doc.get_value("id")?,
+            first_name: doc.get_value("first_name")?,
+            last_name: doc.get_value("last_name")?,
+            phone: doc.get_value("phone")?,
+            email: optional_value(doc, "email")?,
+            address: optional_value(doc, "address")?,
+            date_of_birth,
+            kyc_status: kyc_status.parse()?,
+            created_at: doc.get_value("created_at")?,
+            updated_at: doc.get_value("updated_at")?,
+            deleted_at: optional_value(doc, "deleted_at")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_changes_only_given_fields() {
+        let mut customer = Customer::new(NewCustomer {
+            first_name: "Sam".to_string(),
+            last_name: "James".to_string(),
+            phone: "2347038657970".to_string(),
+            email: None,
+            address: None,
+            date_of_birth: None,
+        })
+        .unwrap();
+        assert_eq!(customer.full_name(), "Sam James");
+        assert_eq!(customer.kyc_status, KycStatus::PENDING);
+
+        let values = customer.apply(CustomerUpdate {
+            email: Some("sam@example.com".to_string()),
+            date_of_birth: NaiveDate::from_ymd_opt(1990, 4, 1),
+            kyc_status: Some(KycStatus::VERIFIED),
+            ..CustomerUpdate::default()
+        });
+        let fields: Vec<&str> = values.iter().map(|(field, _)| *field).collect();
+        assert_eq!(
+            fields,
+            vec!["email", "date_of_birth", "kyc_status", "updated_at"]
+        );
+        assert_eq!(values[1].1, IonValue::String("1990-04-01".to_string()));
+        assert_eq!(customer.first_name, "Sam");
+        assert_eq!(customer.kyc_status, KycStatus::VERIFIED);
+    }
+}
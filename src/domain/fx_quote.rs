@@ -0,0 +1,96 @@
+use bigdecimal::BigDecimal;
+use chrono::prelude::*;
+use ion_binary_rs::IonValue;
+use qldb::Document;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use super::{Currency, QldbInsertable};
+use crate::error::AppError;
+use crate::util;
+
+pub const TABLE_NAME: &str = "fx_quotes";
+
+#[derive(Debug, Deserialize)]
+pub struct NewFxQuote {
+    pub from: Currency,
+    pub to: Currency,
+}
+
+/// Exchange rate promised to a client until `expires_at`. Its id can be sent
+/// with a transfer between accounts in `from` and `to` currencies.
+#[derive(Debug, Clone, Serialize)]
+pub struct FxQuote {
+    pub id: String,
+    pub from: Currency,
+    pub to: Currency,
+    pub rate: BigDecimal,
+    pub created_at: DateTime<FixedOffset>,
+    pub expires_at: DateTime<FixedOffset>,
+}
+
+impl FxQuote {
+    pub fn new(from: Currency, to: Currency, rate: BigDecimal, ttl: chrono::Duration) -> FxQuote {
+        let now: DateTime<FixedOffset> = Utc::now().into();
+        FxQuote {
+            id: util::generate_alphanumeric(20).unwrap(),
+            from,
+            to,
+            rate,
+            created_at: now,
+            expires_at: now + ttl,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Utc::now() >= self.expires_at
+    }
+}
+
+impl QldbInsertable for FxQuote {
+    fn table_name(&self) -> &str {
+        TABLE_NAME
+    }
+
+    fn to_params(&self) -> HashMap<String, IonValue> {
+        let mut params = HashMap::new();
+        params.insert("id".to_string(), IonValue::String(self.id.clone()));
+        params.insert(
+            "from_currency".to_string(),
+            IonValue::String(self.from.to_string()),
+        );
+        params.insert(
+            "to_currency".to_string(),
+            IonValue::String(self.to.to_string()),
+        );
+        params.insert("rate".to_string(), IonValue::Decimal(self.rate.clone()));
+        params.insert(
+            "created_at".to_string(),
+            IonValue::DateTime(self.created_at),
+        );
+        params.insert(
+            "expires_at".to_string(),
+            IonValue::DateTime(self.expires_at),
+        );
+        params
+    }
+}
+
+impl TryFrom<&Document> for FxQuote {
+    type Error = AppError;
+
+    fn try_from(doc: &Document) -> Result<Self, Self::Error> {
+        let from: String = doc.get_value("from_currency")?;
+        let to: String = doc.get_value("to_currency")?;
+        let quote = FxQuote {
+            id: doc.get_value("id")?,
+            from: from.parse()?,
+            to: to.parse()?,
+            rate: doc.get_value("rate")?,
+            created_at: doc.get_value("created_at")?,
+            expires_at: doc.get_value("expires_at")?,
+        };
+        Ok(quote)
+    }
+}
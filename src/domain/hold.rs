@@ -0,0 +1,297 @@
+use bigdecimal::BigDecimal;
+use chrono::prelude::*;
+use ion_binary_rs::IonValue;
+use qldb::Document;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::str::FromStr;
+
+use super::{Currency, QldbInsertable, TransactionReceipt};
+use crate::error::{AppError, ErrorType};
+use crate::util;
+
+pub const TABLE_NAME: &str = "holds";
+
+#[allow(clippy::upper_case_acronyms, non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HoldStatus {
+    ACTIVE,
+    PARTIALLY_CAPTURED,
+    CAPTURED,
+    RELEASED,
+    EXPIRED,
+}
+
+impl HoldStatus {
+    /// Open holds still reserve whatever has not been captured.
+    pub fn is_open(&self) -> bool {
+        matches!(self, HoldStatus::ACTIVE | HoldStatus::PARTIALLY_CAPTURED)
+    }
+}
+
+impl Display for HoldStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for HoldStatus {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ACTIVE" => Ok(HoldStatus::ACTIVE),
+            "PARTIALLY_CAPTURED" => Ok(HoldStatus::PARTIALLY_CAPTURED),
+            "CAPTURED" => Ok(HoldStatus::CAPTURED),
+            "RELEASED" => Ok(HoldStatus::RELEASED),
+            "EXPIRED" => Ok(HoldStatus::EXPIRED),
+            _ => Err(AppError::from_type(ErrorType::Custom(format!(
+                "Unknown hold status: {}",
+                s
+            )))),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewHold {
+    pub amount: BigDecimal,
+    pub currency: Option<Currency>,
+}
+
+/// Captures `amount` of a hold, or all that is left of it. Without a
+/// recipient the money leaves the bank as a debit.
+#[derive(Debug, Deserialize)]
+pub struct HoldCapture {
+    pub amount: Option<BigDecimal>,
+    pub recipient_account_number: Option<String>,
+}
+
+/// Funds reserved on an account. They stay in the ledger balance but cannot
+/// be spent until the hold is captured, released or expires.
+#[derive(Debug, Clone, Serialize)]
+pub struct Hold {
+    pub id: String,
+    pub account_number: String,
+    pub amount: BigDecimal,
+    pub captured_amount: BigDecimal,
+    pub currency: Currency,
+    pub status: HoldStatus,
+    pub created_at: DateTime<FixedOffset>,
+    pub expires_at: DateTime<FixedOffset>,
+    pub updated_at: DateTime<FixedOffset>,
+}
+
+impl Hold {
+    pub fn new(
+        account_number: String,
+        amount: BigDecimal,
+        currency: Currency,
+        ttl: chrono::Duration,
+    ) -> Hold {
+        let now: DateTime<FixedOffset> = Utc::now().into();
+        Hold {
+            id: util::generate_alphanumeric(20).unwrap(),
+            account_number,
+            amount: currency.round(&amount),
+            captured_amount: BigDecimal::default().with_scale(currency.minor_units()),
+            currency,
+            status: HoldStatus::ACTIVE,
+            created_at: now,
+            expires_at: now + ttl,
+            updated_at: now,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Utc::now() >= self.expires_at
+    }
+
+    /// Amount still reserved by the hold.
+    pub fn remaining(&self) -> BigDecimal {
+        if self.status.is_open() {
+            self.amount.clone() - self.captured_amount.clone()
+        } else {
+            BigDecimal::default()
+        }
+    }
+
+    /// Takes `amount` (by default all that is left) out of the hold.
+    pub fn capture(&mut self, amount: Option<BigDecimal>) -> Result<BigDecimal, AppError> {
+        self.ensure_open()?;
+        let remaining = self.remaining();
+        let amount = amount.unwrap_or_else(|| remaining.clone());
+        if amount <= BigDecimal::default() || !self.currency.fits(&amount) {
+            let msg = "Invalid capture amount".to_string();
+            return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+        }
+        if amount > remaining {
+            let msg = format!(
+                "Hold {} only has {} {} left to capture",
+                self.id, remaining, self.currency
+            );
+            return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+        }
+        self.captured_amount = self
+            .currency
+            .round(&(self.captured_amount.clone() + amount.clone()));
+        self.status = if self.captured_amount == self.amount {
+            HoldStatus::CAPTURED
+        } else {
+            HoldStatus::PARTIALLY_CAPTURED
+        };
+        self.updated_at = Utc::now().into();
+        Ok(amount)
+    }
+
+    pub fn release(&mut self) -> Result<(), AppError> {
+        self.ensure_open()?;
+        self.status = HoldStatus::RELEASED;
+        self.updated_at = Utc::now().into();
+        Ok(())
+    }
+
+    /// Marks an open hold past its expiry as expired. Returns whether it did.
+    pub fn expire(&mut self) -> bool {
+        if !self.status.is_open() || !self.is_expired() {
+            return false;
+        }
+        self.status = HoldStatus::EXPIRED;
+        self.updated_at = Utc::now().into();
+        true
+    }
+
+    fn ensure_open(&self) -> Result<(), AppError> {
+        if self.status.is_open() && !self.is_expired() {
+            return Ok(());
+        }
+        let status = if self.status.is_open() {
+            HoldStatus::EXPIRED
+        } else {
+            self.status
+        };
+        let msg = format!("Hold {} is {}", self.id, status);
+        Err(AppError::new(Some(msg), ErrorType::PayloadError))
+    }
+
+    pub fn from_documents(docs: Vec<Document>) -> Result<Vec<Self>, AppError> {
+        docs.iter().map(|doc| doc.try_into()).collect()
+    }
+}
+
+impl QldbInsertable for Hold {
+    fn table_name(&self) -> &str {
+        TABLE_NAME
+    }
+
+    fn to_params(&self) -> HashMap<String, IonValue> {
+        let mut params = HashMap::new();
+        params.insert("id".to_string(), IonValue::String(self.id.clone()));
+        params.insert(
+            "account_number".to_string(),
+            IonValue::String(self.account_number.clone()),
+        );
+        params.insert("amount".to_string(), IonValue::Decimal(self.amount.clone()));
+        params.insert(
+            "captured_amount".to_string(),
+            IonValue::Decimal(self.captured_amount.clone()),
+        );
+        params.insert(
+            "currency".to_string(),
+            IonValue::String(self.currency.to_string()),
+        );
+        params.insert(
+            "status".to_string(),
+            IonValue::String(self.status.to_string()),
+        );
+        params.insert(
+            "created_at".to_string(),
+            IonValue::DateTime(self.created_at),
+        );
+        params.insert(
+            "expires_at".to_string(),
+            IonValue::DateTime(self.expires_at),
+        );
+        params.insert(
+            "updated_at".to_string(),
+            IonValue::DateTime(self.updated_at),
+        );
+        params
+    }
+}
+
+impl TryFrom<&Document> for Hold {
+    type Error = AppError;
+
+    fn try_from(doc: &Document) -> Result<Self, Self::Error> {
+        let currency: Currency = doc.get_value::<String>("currency")?.parse()?;
+        let status: String = doc.get_value("status")?;
+        let amount: BigDecimal = doc.get_value("amount")?;
+        let captured_amount: BigDecimal = doc.get_value("captured_amount")?;
+        let hold = Hold {
+            id: doc.get_value("id")?,
+            account_number: doc.get_value("account_number")?,
+            amount: amount.with_scale(currency.minor_units()),
+            captured_amount: captured_amount.with_scale(currency.minor_units()),
+            currency,
+            status: status.parse()?,
+            created_at: doc.get_value("created_at")?,
+            expires_at: doc.get_value("expires_at")?,
+            updated_at: doc.get_value("updated_at")?,
+        };
+        Ok(hold)
+    }
+}
+
+/// A capture and the ledger transaction it produced.
+#[derive(Debug, Serialize)]
+pub struct CapturedHold {
+    pub hold: Hold,
+    pub transaction: TransactionReceipt,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn hold(amount: u32) -> Hold {
+        Hold::new(
+            "0123456789".to_string(),
+            BigDecimal::from(amount),
+            Currency::USD,
+            Duration::minutes(5),
+        )
+    }
+
+    #[test]
+    fn test_partial_then_full_capture() {
+        let mut hold = hold(100);
+
+        let captured = hold.capture(Some(BigDecimal::from(30))).unwrap();
+        assert_eq!(captured, BigDecimal::from(30));
+        assert_eq!(hold.status, HoldStatus::PARTIALLY_CAPTURED);
+        assert_eq!(hold.remaining(), BigDecimal::from(70));
+
+        assert!(hold.capture(Some(BigDecimal::from(71))).is_err());
+        let captured = hold.capture(None).unwrap();
+        assert_eq!(captured, BigDecimal::from(70));
+        assert_eq!(hold.status, HoldStatus::CAPTURED);
+        assert_eq!(hold.remaining(), BigDecimal::from(0));
+        assert!(hold.release().is_err());
+    }
+
+    #[test]
+    fn test_expired_hold_reserves_nothing() {
+        let mut hold = hold(100);
+        hold.expires_at = hold.created_at - Duration::seconds(1);
+
+        assert!(hold.capture(None).is_err());
+        assert!(hold.expire());
+        assert_eq!(hold.status, HoldStatus::EXPIRED);
+        assert_eq!(hold.remaining(), BigDecimal::from(0));
+        assert!(!hold.expire());
+    }
+}
@@ -0,0 +1,118 @@
+use chrono::prelude::*;
+use ion_binary_rs::IonValue;
+use qldb::Document;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use super::QldbInsertable;
+use crate::error::{AppError, ErrorType};
+
+pub const TABLE_NAME: &str = "idempotency_keys";
+
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Scope of the keys the scheduler makes its payments with.
+pub const SCHEDULER_SCOPE: &str = "scheduler";
+/// Scope of the keys approved transfers sent without a key are made with.
+pub const PENDING_SCOPE: &str = "pending";
+
+/// Client supplied `Idempotency-Key` together with a fingerprint of the
+/// request it was sent with, so a reused key can be told apart from a retry.
+/// Keys only need to be unique within their `scope`, the principal that sent
+/// them, so two clients cannot replay or block each other's requests.
+#[derive(Debug, Clone)]
+pub struct IdempotencyKey {
+    pub scope: String,
+    pub key: String,
+    pub request_fingerprint: String,
+}
+
+impl IdempotencyKey {
+    pub fn new(
+        scope: String,
+        key: String,
+        request_fingerprint: String,
+    ) -> Result<IdempotencyKey, AppError> {
+        if key.trim().is_empty() || key.len() > MAX_KEY_LENGTH {
+            return Err(AppError::new(
+                Some(format!(
+                    "Idempotency-Key must be between 1 and {} characters",
+                    MAX_KEY_LENGTH
+                )),
+                ErrorType::PayloadError,
+            ));
+        }
+        Ok(IdempotencyKey {
+            scope,
+            key,
+            request_fingerprint,
+        })
+    }
+}
+
+/// Outcome of the first request made with an idempotency key: the journal
+/// entry it produced. It is written in the same ledger transaction as the
+/// money movement it describes.
+#[derive(Debug, Clone)]
+pub struct IdempotencyRecord {
+    pub scope: String,
+    pub key: String,
+    pub request_fingerprint: String,
+    pub transaction_id: String,
+    pub created_at: DateTime<FixedOffset>,
+}
+
+impl IdempotencyRecord {
+    pub fn new(idempotency_key: IdempotencyKey, transaction_id: String) -> IdempotencyRecord {
+        IdempotencyRecord {
+            scope: idempotency_key.scope,
+            key: idempotency_key.key,
+            request_fingerprint: idempotency_key.request_fingerprint,
+            transaction_id,
+            created_at: Utc::now().into(),
+        }
+    }
+}
+
+impl QldbInsertable for IdempotencyRecord {
+    fn table_name(&self) -> &str {
+        TABLE_NAME
+    }
+
+    fn to_params(&self) -> HashMap<String, IonValue> {
+        let mut params = HashMap::new();
+        params.insert("scope".to_string(), IonValue::String(self.scope.clone()));
+        params.insert(
+            "idempotency_key".to_string(),
+            IonValue::String(self.key.clone()),
+        );
+        params.insert(
+            "request_fingerprint".to_string(),
+            IonValue::String(self.request_fingerprint.clone()),
+        );
+        params.insert(
+            "transaction_id".to_string(),
+            IonValue::String(self.transaction_id.clone()),
+        );
+        params.insert(
+            "created_at".to_string(),
+            IonValue::DateTime(self.created_at),
+        );
+        params
+    }
+}
+
+impl TryFrom<&Document> for IdempotencyRecord {
+    type Error = AppError;
+
+    fn try_from(doc: &Document) -> Result<Self, Self::Error> {
+        let record = IdempotencyRecord {
+            scope: doc.get_value("scope")?,
+            key: doc.get_value("idempotency_key")?,
+            request_fingerprint: doc.get_value("request_fingerprint")?,
+            transaction_id: doc.get_value("transaction_id")?,
+            created_at: doc.get_value("created_at")?,
+        };
+        Ok(record)
+    }
+}
@@ -0,0 +1,165 @@
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+
+use super::{Account, Currency, Validator};
+use crate::error::{AppError, AppErrorResponse};
+
+/// Largest import file accepted over HTTP, in bytes.
+pub const MAX_IMPORT_BYTES: usize = 10 * 1024 * 1024;
+
+/// Length of the account numbers the service generates; imported numbers
+/// must look the same.
+const ACCOUNT_NUMBER_LENGTH: usize = 10;
+
+/// One line of an account import file. Blank `account_number` gets a new
+/// number, blank `opening_balance` is zero and blank `currency` is the
+/// default currency.
+#[derive(Debug, Deserialize)]
+pub struct ImportRow {
+    pub name: String,
+    pub phone: String,
+    #[serde(default)]
+    pub account_number: Option<String>,
+    #[serde(default)]
+    pub opening_balance: Option<BigDecimal>,
+    #[serde(default)]
+    pub currency: Option<Currency>,
+}
+
+impl ImportRow {
+    /// The account to open and its opening balance. Every problem with the
+    /// row is reported together.
+    pub fn into_account(self) -> Result<(Account, BigDecimal), AppError> {
+        let mut validator = Validator::default();
+        let name = validator.name("name", &self.name);
+        let phone = validator.phone("phone", &self.phone);
+        let currency = self.currency.unwrap_or_default();
+        let opening_balance = self.opening_balance.unwrap_or_default();
+        if opening_balance < BigDecimal::from(0) {
+            validator.invalid("opening_balance", "must not be negative");
+        } else if !currency.fits(&opening_balance) {
+            validator.invalid(
+                "opening_balance",
+                format!(
+                    "must not have more than {} decimal places",
+                    currency.minor_units()
+                ),
+            );
+        }
+        if let Some(account_number) = &self.account_number {
+            if account_number.len() != ACCOUNT_NUMBER_LENGTH
+                || !account_number.chars().all(|c| c.is_ascii_digit())
+            {
+                validator.invalid(
+                    "account_number",
+                    format!("must be {} digits", ACCOUNT_NUMBER_LENGTH),
+                );
+            }
+        }
+        validator.finish()?;
+        let account = match self.account_number {
+            Some(account_number) => Account::with_number(account_number, name, phone, currency),
+            None => Account::new(name, phone, currency),
+        };
+        Ok((account, opening_balance))
+    }
+}
+
+/// Outcome of one line of the file; `row` is its line number, counting the
+/// header as line 1.
+#[derive(Debug, Serialize)]
+pub struct ImportRowResult {
+    pub row: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account_number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<AppErrorResponse>,
+}
+
+impl ImportRowResult {
+    pub fn imported(row: u64, account_number: String, transaction_id: Option<String>) -> Self {
+        ImportRowResult {
+            row,
+            account_number: Some(account_number),
+            transaction_id,
+            error: None,
+        }
+    }
+
+    pub fn failed(row: u64, error: &AppError) -> Self {
+        ImportRowResult {
+            row,
+            account_number: None,
+            transaction_id: None,
+            error: Some(error.response()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub failed: usize,
+    pub results: Vec<ImportRowResult>,
+}
+
+impl ImportReport {
+    pub fn new(mut results: Vec<ImportRowResult>) -> ImportReport {
+        results.sort_by_key(|result| result.row);
+        let failed = results.iter().filter(|r| r.error.is_some()).count();
+        ImportReport {
+            imported: results.len() - failed,
+            failed,
+            results,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(account_number: Option<&str>, opening_balance: &str) -> ImportRow {
+        ImportRow {
+            name: "Sam James".to_string(),
+            phone: "2347038657970".to_string(),
+            account_number: account_number.map(str::to_string),
+            opening_balance: Some(opening_balance.parse().unwrap()),
+            currency: Some(Currency::USD),
+        }
+    }
+
+    #[test]
+    fn test_row_validation() {
+        let (account, balance) = row(Some("0123456789"), "10.50").into_account().unwrap();
+        assert_eq!(account.account_number, "0123456789");
+        assert_eq!(balance, "10.50".parse().unwrap());
+        assert_eq!(
+            row(None, "0")
+                .into_account()
+                .unwrap()
+                .0
+                .account_number
+                .len(),
+            10
+        );
+
+        assert!(row(Some("12345"), "0").into_account().is_err());
+        assert!(row(Some("01234567AB"), "0").into_account().is_err());
+        assert!(row(None, "-1").into_account().is_err());
+        assert!(row(None, "0.001").into_account().is_err());
+
+        let mut bad = row(Some("12345"), "-1");
+        bad.name = " ".to_string();
+        bad.phone = "not a phone".to_string();
+        let err = bad.into_account().unwrap_err();
+        let fields = err.response().fields.unwrap();
+        let names: Vec<&str> = fields.keys().map(String::as_str).collect();
+        assert_eq!(
+            names,
+            vec!["account_number", "name", "opening_balance", "phone"]
+        );
+    }
+}
@@ -0,0 +1,414 @@
+use bigdecimal::BigDecimal;
+use chrono::prelude::*;
+use ion_binary_rs::IonValue;
+use qldb::Document;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::str::FromStr;
+
+use super::{optional_value, Currency, IdempotencyKey, NewTransaction, QldbInsertable};
+use super::{SigningRule, TransactionType, PENDING_SCOPE};
+use crate::error::{AppError, ErrorType};
+use crate::util;
+
+pub const OWNERS_TABLE_NAME: &str = "account_owners";
+pub const PENDING_TABLE_NAME: &str = "pending_transactions";
+pub const APPROVALS_TABLE_NAME: &str = "transaction_approvals";
+
+/// A customer who holds an account together with its primary holder, the
+/// account's `customer_id`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountOwner {
+    pub account_number: String,
+    pub customer_id: String,
+    pub created_at: DateTime<FixedOffset>,
+}
+
+impl AccountOwner {
+    pub fn new(account_number: String, customer_id: String) -> AccountOwner {
+        AccountOwner {
+            account_number,
+            customer_id,
+            created_at: Utc::now().into(),
+        }
+    }
+
+    pub fn from_documents(docs: Vec<Document>) -> Result<Vec<Self>, AppError> {
+        docs.iter().map(|doc| doc.try_into()).collect()
+    }
+}
+
+impl QldbInsertable for AccountOwner {
+    fn table_name(&self) -> &str {
+        OWNERS_TABLE_NAME
+    }
+
+    fn to_params(&self) -> HashMap<String, IonValue> {
+        let mut params = HashMap::new();
+        params.insert(
+            "account_number".to_string(),
+            IonValue::String(self.account_number.clone()),
+        );
+        params.insert(
+            "customer_id".to_string(),
+            IonValue::String(self.customer_id.clone()),
+        );
+        params.insert(
+            "created_at".to_string(),
+            IonValue::DateTime(self.created_at),
+        );
+        params
+    }
+}
+
+impl TryFrom<&Document> for AccountOwner {
+    type Error = AppError;
+
+    fn try_from(doc: &Document) -> Result<Self, Self::Error> {
+        Ok(AccountOwner {
+            account_number: doc.get_value("account_number")?,
+            customer_id: doc.get_value("customer_id")?,
+            created_at: doc.get_value("created_at")?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewAccountOwner {
+    pub customer_id: String,
+}
+
+/// Everyone who holds an account and what it takes to send money from it.
+#[derive(Debug, Serialize)]
+pub struct AccountOwners {
+    pub account_number: String,
+    pub signing_rule: SigningRule,
+    pub customer_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SigningRuleChange {
+    pub signing_rule: SigningRule,
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PendingStatus {
+    PENDING,
+    EXECUTED,
+    FAILED,
+    EXPIRED,
+}
+
+impl Display for PendingStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for PendingStatus {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "PENDING" => Ok(PendingStatus::PENDING),
+            "EXECUTED" => Ok(PendingStatus::EXECUTED),
+            "FAILED" => Ok(PendingStatus::FAILED),
+            "EXPIRED" => Ok(PendingStatus::EXPIRED),
+            _ => Err(AppError::from_type(ErrorType::Custom(format!(
+                "Unknown pending transaction status: {}",
+                s
+            )))),
+        }
+    }
+}
+
+/// One owner's consent to a pending transaction.
+#[derive(Debug, Clone, Serialize)]
+pub struct Approval {
+    #[serde(skip)]
+    pub pending_transaction_id: String,
+    pub customer_id: String,
+    pub approved_at: DateTime<FixedOffset>,
+}
+
+impl Approval {
+    pub fn new(pending_transaction_id: String, customer_id: String) -> Approval {
+        Approval {
+            pending_transaction_id,
+            customer_id,
+            approved_at: Utc::now().into(),
+        }
+    }
+
+    pub fn from_documents(docs: Vec<Document>) -> Result<Vec<Self>, AppError> {
+        docs.iter().map(|doc| doc.try_into()).collect()
+    }
+}
+
+impl QldbInsertable for Approval {
+    fn table_name(&self) -> &str {
+        APPROVALS_TABLE_NAME
+    }
+
+    fn to_params(&self) -> HashMap<String, IonValue> {
+        let mut params = HashMap::new();
+        params.insert(
+            "pending_transaction_id".to_string(),
+            IonValue::String(self.pending_transaction_id.clone()),
+        );
+        params.insert(
+            "customer_id".to_string(),
+            IonValue::String(self.customer_id.clone()),
+        );
+        params.insert(
+            "approved_at".to_string(),
+            IonValue::DateTime(self.approved_at),
+        );
+        params
+    }
+}
+
+impl TryFrom<&Document> for Approval {
+    type Error = AppError;
+
+    fn try_from(doc: &Document) -> Result<Self, Self::Error> {
+        Ok(Approval {
+            pending_transaction_id: doc.get_value("pending_transaction_id")?,
+            customer_id: doc.get_value("customer_id")?,
+            approved_at: doc.get_value("approved_at")?,
+        })
+    }
+}
+
+/// A transfer out of an account whose owners must all approve it. It is made
+/// once the last of them does, and lapses if they have not by `expires_at`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingTransaction {
+    pub id: String,
+    pub sender_account_number: String,
+    pub recipient_account_number: String,
+    pub amount: BigDecimal,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<Currency>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fx_quote_id: Option<String>,
+    pub status: PendingStatus,
+    pub approvals: Vec<Approval>,
+    #[serde(skip)]
+    pub idempotency_scope: Option<String>,
+    #[serde(skip)]
+    pub idempotency_key: Option<String>,
+    #[serde(skip)]
+    pub request_fingerprint: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure_reason: Option<String>,
+    pub created_at: DateTime<FixedOffset>,
+    pub expires_at: DateTime<FixedOffset>,
+    pub updated_at: DateTime<FixedOffset>,
+}
+
+impl PendingTransaction {
+    /// Holds back a transfer. Only transfers need approval.
+    pub fn new(
+        transaction: &NewTransaction,
+        idempotency_key: Option<&IdempotencyKey>,
+        ttl: chrono::Duration,
+    ) -> Result<PendingTransaction, AppError> {
+        let sender_account_number = match (
+            &transaction.transaction_type,
+            &transaction.sender_account_number,
+        ) {
+            (TransactionType::TRANSFER, Some(sender_account_number)) => {
+                sender_account_number.clone()
+            }
+            _ => {
+                return Err(AppError::new(
+                    Some("Only transfers can wait for approval".to_string()),
+                    ErrorType::PayloadError,
+                ))
+            }
+        };
+        let now: DateTime<FixedOffset> = Utc::now().into();
+        Ok(PendingTransaction {
+            id: util::generate_alphanumeric(20)?,
+            sender_account_number,
+            recipient_account_number: transaction.recipient_account_number.clone(),
+            amount: transaction.amount.clone(),
+            currency: transaction.currency,
+            fx_quote_id: transaction.fx_quote_id.clone(),
+            status: PendingStatus::PENDING,
+            approvals: Vec::new(),
+            idempotency_scope: idempotency_key.map(|key| key.scope.clone()),
+            idempotency_key: idempotency_key.map(|key| key.key.clone()),
+            request_fingerprint: transaction.fingerprint(),
+            transaction_id: None,
+            failure_reason: None,
+            created_at: now,
+            expires_at: now + ttl,
+            updated_at: now,
+        })
+    }
+
+    /// The transfer to make once every owner has approved.
+    pub fn transaction(&self) -> NewTransaction {
+        NewTransaction {
+            amount: self.amount.clone(),
+            currency: self.currency,
+            sender_account_number: Some(self.sender_account_number.clone()),
+            recipient_account_number: self.recipient_account_number.clone(),
+            transaction_type: TransactionType::TRANSFER,
+            fx_quote_id: self.fx_quote_id.clone(),
+        }
+    }
+
+    /// Key the transfer is posted under, so approvals racing to be the last
+    /// one still move the money once. It is the client's key when there was
+    /// one, in the scope of whoever sent it, so a retry of the original
+    /// request finds the receipt.
+    pub fn execution_key(&self) -> Result<IdempotencyKey, AppError> {
+        let (scope, key) = match (&self.idempotency_scope, &self.idempotency_key) {
+            (Some(scope), Some(key)) => (scope.clone(), key.clone()),
+            _ => (PENDING_SCOPE.to_string(), self.id.clone()),
+        };
+        IdempotencyKey::new(scope, key, self.request_fingerprint.clone())
+    }
+
+    pub fn is_approved_by(&self, customer_id: &str) -> bool {
+        self.approvals
+            .iter()
+            .any(|approval| approval.customer_id == customer_id)
+    }
+
+    /// Marks a pending transaction past its expiry as expired. Returns
+    /// whether it did.
+    pub fn expire(&mut self) -> bool {
+        if self.status != PendingStatus::PENDING || Utc::now() < self.expires_at {
+            return false;
+        }
+        self.status = PendingStatus::EXPIRED;
+        self.updated_at = Utc::now().into();
+        true
+    }
+
+    pub fn ensure_pending(&self) -> Result<(), AppError> {
+        if self.status == PendingStatus::PENDING {
+            return Ok(());
+        }
+        let msg = format!("Pending transaction {} is {}", self.id, self.status);
+        Err(AppError::new(Some(msg), ErrorType::PayloadError))
+    }
+
+    /// The status and outcome fields, for writing back a change.
+    pub fn outcome_params(&self) -> Vec<(&'static str, IonValue)> {
+        let mut values = vec![
+            ("status", IonValue::String(self.status.to_string())),
+            ("updated_at", IonValue::DateTime(self.updated_at)),
+        ];
+        if let Some(transaction_id) = &self.transaction_id {
+            values.push(("transaction_id", IonValue::String(transaction_id.clone())));
+        }
+        if let Some(reason) = &self.failure_reason {
+            values.push(("failure_reason", IonValue::String(reason.clone())));
+        }
+        values
+    }
+
+    pub fn from_documents(docs: Vec<Document>) -> Result<Vec<Self>, AppError> {
+        docs.iter().map(|doc| doc.try_into()).collect()
+    }
+}
+
+impl QldbInsertable for PendingTransaction {
+    fn table_name(&self) -> &str {
+        PENDING_TABLE_NAME
+    }
+
+    fn to_params(&self) -> HashMap<String, IonValue> {
+        let mut params = HashMap::new();
+        params.insert("id".to_string(), IonValue::String(self.id.clone()));
+        params.insert(
+            "sender_account_number".to_string(),
+            IonValue::String(self.sender_account_number.clone()),
+        );
+        params.insert(
+            "recipient_account_number".to_string(),
+            IonValue::String(self.recipient_account_number.clone()),
+        );
+        params.insert("amount".to_string(), IonValue::Decimal(self.amount.clone()));
+        if let Some(currency) = self.currency {
+            params.insert(
+                "currency".to_string(),
+                IonValue::String(currency.to_string()),
+            );
+        }
+        if let Some(fx_quote_id) = &self.fx_quote_id {
+            params.insert(
+                "fx_quote_id".to_string(),
+                IonValue::String(fx_quote_id.clone()),
+            );
+        }
+        if let Some(scope) = &self.idempotency_scope {
+            params.insert(
+                "idempotency_scope".to_string(),
+                IonValue::String(scope.clone()),
+            );
+        }
+        if let Some(key) = &self.idempotency_key {
+            params.insert("idempotency_key".to_string(), IonValue::String(key.clone()));
+        }
+        params.insert(
+            "request_fingerprint".to_string(),
+            IonValue::String(self.request_fingerprint.clone()),
+        );
+        params.insert(
+            "created_at".to_string(),
+            IonValue::DateTime(self.created_at),
+        );
+        params.insert(
+            "expires_at".to_string(),
+            IonValue::DateTime(self.expires_at),
+        );
+        params.extend(
+            self.outcome_params()
+                .into_iter()
+                .map(|(field, value)| (field.to_string(), value)),
+        );
+        params
+    }
+}
+
+impl TryFrom<&Document> for PendingTransaction {
+    type Error = AppError;
+
+    fn try_from(doc: &Document) -> Result<Self, Self::Error> {
+        let currency: Option<String> = optional_value(doc, "currency")?;
+        let currency = match currency {
+            Some(code) => Some(code.parse()?),
+            None => None,
+        };
+        let status: String = doc.get_value("status")?;
+        Ok(PendingTransaction {
+            id: doc.get_value("id")?,
+            sender_account_number: doc.get_value("sender_account_number")?,
+            recipient_account_number: doc.get_value("recipient_account_number")?,
+            amount: doc.get_value("amount")?,
+            currency,
+            fx_quote_id: optional_value(doc, "fx_quote_id")?,
+            status: status.parse()?,
+            approvals: Vec::new(),
+            idempotency_scope: optional_value(doc, "idempotency_scope")?,
+            idempotency_key: optional_value(doc, "idempotency_key")?,
+            request_fingerprint: doc.get_value("request_fingerprint")?,
+            transaction_id: optional_value(doc, "transaction_id")?,
+            failure_reason: optional_value(doc, "failure_reason")?,
+            created_at: doc.get_value("created_at")?,
+            expires_at: doc.get_value("expires_at")?,
+            updated_at: doc.get_value("updated_at")?,
+        })
+    }
+}
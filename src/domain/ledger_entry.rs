@@ -0,0 +1,194 @@
+use bigdecimal::BigDecimal;
+use chrono::prelude::*;
+use ion_binary_rs::IonValue;
+use qldb::Document;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use super::{Currency, QldbInsertable};
+use crate::error::{AppError, ErrorType};
+use crate::util::{self, Config};
+
+pub const TABLE_NAME: &str = "ledger_entries";
+
+/// Double-entry posting: `amount` leaves `debit_account_number` and arrives in
+/// `credit_account_number`, so every entry nets to zero on its own.
+#[derive(Debug, Clone, Serialize)]
+pub struct LedgerEntry {
+    pub id: String,
+    pub transaction_id: String,
+    pub debit_account_number: String,
+    pub credit_account_number: String,
+    pub amount: BigDecimal,
+    pub created_at: DateTime<FixedOffset>,
+}
+
+impl LedgerEntry {
+    pub fn new(
+        transaction_id: String,
+        debit_account_number: String,
+        credit_account_number: String,
+        amount: BigDecimal,
+    ) -> LedgerEntry {
+        LedgerEntry {
+            id: util::generate_alphanumeric(20).unwrap(),
+            transaction_id,
+            debit_account_number,
+            credit_account_number,
+            amount,
+            created_at: Utc::now().into(),
+        }
+    }
+}
+
+impl QldbInsertable for LedgerEntry {
+    fn table_name(&self) -> &str {
+        TABLE_NAME
+    }
+
+    fn to_params(&self) -> HashMap<String, IonValue> {
+        let mut params = HashMap::new();
+        params.insert("id".to_string(), IonValue::String(self.id.clone()));
+        params.insert(
+            "transaction_id".to_string(),
+            IonValue::String(self.transaction_id.clone()),
+        );
+        params.insert(
+            "debit_account_number".to_string(),
+            IonValue::String(self.debit_account_number.clone()),
+        );
+        params.insert(
+            "credit_account_number".to_string(),
+            IonValue::String(self.credit_account_number.clone()),
+        );
+        params.insert("amount".to_string(), IonValue::Decimal(self.amount.clone()));
+        params.insert(
+            "created_at".to_string(),
+            IonValue::DateTime(self.created_at),
+        );
+        params
+    }
+}
+
+impl TryFrom<&Document> for LedgerEntry {
+    type Error = AppError;
+
+    fn try_from(doc: &Document) -> Result<Self, Self::Error> {
+        let entry = LedgerEntry {
+            id: doc.get_value("id")?,
+            transaction_id: doc.get_value("transaction_id")?,
+            debit_account_number: doc.get_value("debit_account_number")?,
+            credit_account_number: doc.get_value("credit_account_number")?,
+            amount: doc.get_value("amount")?,
+            created_at: doc.get_value("created_at")?,
+        };
+        Ok(entry)
+    }
+}
+
+/// Bank-owned accounts on the other side of money entering or leaving the
+/// ledger. Their balances may go negative: the cash-in balance mirrors every
+/// deposit ever made, so all balances in one currency always sum to zero.
+/// Each is opened once per currency, with the currency code appended to the
+/// configured account number (e.g. `CASH-IN-USD`).
+#[derive(Debug, Clone)]
+pub struct SystemAccounts {
+    pub cash_in: String,
+    pub cash_out: String,
+    /// Parks money whose final account is not known yet.
+    pub suspense: String,
+    /// Takes the other side of both legs of a cross-currency transfer.
+    pub fx_position: String,
+}
+
+impl SystemAccounts {
+    fn number(prefix: &str, currency: Currency) -> String {
+        format!("{}-{}", prefix, currency)
+    }
+
+    pub fn cash_in_for(&self, currency: Currency) -> String {
+        SystemAccounts::number(&self.cash_in, currency)
+    }
+
+    pub fn cash_out_for(&self, currency: Currency) -> String {
+        SystemAccounts::number(&self.cash_out, currency)
+    }
+
+    pub fn fx_position_for(&self, currency: Currency) -> String {
+        SystemAccounts::number(&self.fx_position, currency)
+    }
+
+    /// System accounts only take the other side of postings the service
+    /// makes itself; requests cannot name them as sender or recipient.
+    pub fn ensure_not_system(&self, account_number: &str) -> Result<(), AppError> {
+        if self
+            .all()
+            .iter()
+            .any(|(number, _, _)| number == account_number)
+        {
+            let msg = format!(
+                "Account {} is a system account and cannot be used here",
+                account_number
+            );
+            return Err(AppError::from_type(ErrorType::AccountError(msg)));
+        }
+        Ok(())
+    }
+
+    /// Account number, name and currency of every system account.
+    pub fn all(&self) -> Vec<(String, &'static str, Currency)> {
+        let kinds = [
+            (&self.cash_in, "Cash In"),
+            (&self.cash_out, "Cash Out"),
+            (&self.suspense, "Suspense"),
+            (&self.fx_position, "FX Position"),
+        ];
+        let mut accounts = Vec::new();
+        for currency in Currency::ALL.iter() {
+            for (prefix, name) in kinds.iter() {
+                let number = SystemAccounts::number(prefix, *currency);
+                accounts.push((number, *name, *currency));
+            }
+        }
+        accounts
+    }
+}
+
+impl From<&Config> for SystemAccounts {
+    fn from(config: &Config) -> Self {
+        SystemAccounts {
+            cash_in: config.cash_in_account.clone(),
+            cash_out: config.cash_out_account.clone(),
+            suspense: config.suspense_account.clone(),
+            fx_position: config.fx_position_account.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_system_account_numbers_are_refused() {
+        let system_accounts = SystemAccounts {
+            cash_in: "CASH-IN".to_string(),
+            cash_out: "CASH-OUT".to_string(),
+            suspense: "SUSPENSE".to_string(),
+            fx_position: "FX-POSITION".to_string(),
+        };
+        let numbers = [
+            "CASH-IN-USD",
+            "CASH-OUT-NGN",
+            "SUSPENSE-GBP",
+            "FX-POSITION-EUR",
+        ];
+        for number in &numbers {
+            let err = system_accounts.ensure_not_system(number).unwrap_err();
+            assert!(matches!(err.error_type, ErrorType::AccountError(_)));
+        }
+        assert!(system_accounts.ensure_not_system("CASH-IN").is_ok());
+        assert!(system_accounts.ensure_not_system("0123456789").is_ok());
+    }
+}
@@ -0,0 +1,213 @@
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::str::FromStr;
+
+use super::{Account, Currency};
+use crate::error::{AppError, ErrorType};
+
+/// Service level of an account, which picks its default transaction limits.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AccountTier {
+    #[default]
+    STANDARD,
+    PREMIUM,
+    BUSINESS,
+}
+
+impl Display for AccountTier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for AccountTier {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "STANDARD" => Ok(AccountTier::STANDARD),
+            "PREMIUM" => Ok(AccountTier::PREMIUM),
+            "BUSINESS" => Ok(AccountTier::BUSINESS),
+            _ => Err(AppError::from_type(ErrorType::Custom(format!(
+                "Unknown account tier: {}",
+                s
+            )))),
+        }
+    }
+}
+
+/// Caps on the money an account sends. Amounts are in the account's currency
+/// and a missing value means no limit.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransactionLimits {
+    pub max_transaction_amount: Option<BigDecimal>,
+    pub max_daily_debit_total: Option<BigDecimal>,
+    pub max_daily_transfer_count: Option<u32>,
+}
+
+impl TransactionLimits {
+    /// These limits, with any that are not set taken from `fallback`.
+    pub fn or(&self, fallback: &TransactionLimits) -> TransactionLimits {
+        TransactionLimits {
+            max_transaction_amount: self
+                .max_transaction_amount
+                .clone()
+                .or_else(|| fallback.max_transaction_amount.clone()),
+            max_daily_debit_total: self
+                .max_daily_debit_total
+                .clone()
+                .or_else(|| fallback.max_daily_debit_total.clone()),
+            max_daily_transfer_count: self
+                .max_daily_transfer_count
+                .or(fallback.max_daily_transfer_count),
+        }
+    }
+
+    pub fn check(&self, currency: Currency) -> Result<(), AppError> {
+        let amounts = [&self.max_transaction_amount, &self.max_daily_debit_total];
+        for amount in amounts.iter().filter_map(|amount| amount.as_ref()) {
+            if amount < &BigDecimal::default() || !currency.fits(amount) {
+                let msg = format!(
+                    "Limits must be non-negative {} amounts with at most {} decimal places",
+                    currency,
+                    currency.minor_units()
+                );
+                return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Admin request replacing an account's own limits. Limits left out fall back
+/// to the tier's; the tier is kept when it is left out.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccountLimits {
+    pub tier: Option<AccountTier>,
+    pub max_transaction_amount: Option<BigDecimal>,
+    pub max_daily_debit_total: Option<BigDecimal>,
+    pub max_daily_transfer_count: Option<u32>,
+}
+
+impl AccountLimits {
+    pub fn limits(&self) -> TransactionLimits {
+        TransactionLimits {
+            max_transaction_amount: self.max_transaction_amount.clone(),
+            max_daily_debit_total: self.max_daily_debit_total.clone(),
+            max_daily_transfer_count: self.max_daily_transfer_count,
+        }
+    }
+}
+
+/// Limits per account tier, optionally narrowed to one currency.
+#[derive(Debug, Default)]
+pub struct LimitPolicy {
+    limits: HashMap<(AccountTier, Option<Currency>), TransactionLimits>,
+}
+
+impl LimitPolicy {
+    /// Reads a limits file with one `TIER.LIMIT=VALUE` or
+    /// `TIER/CURRENCY.LIMIT=VALUE` setting per line, e.g.
+    /// `STANDARD.max_transaction_amount=1000`. Blank lines and lines starting
+    /// with `#` are ignored.
+    pub fn from_file(path: &str) -> Result<Self, AppError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            let msg = format!("Unable to read limits file {}: {}", path, e);
+            AppError::from_type(ErrorType::Custom(msg))
+        })?;
+        LimitPolicy::parse(&contents)
+    }
+
+    fn parse(contents: &str) -> Result<Self, AppError> {
+        let mut policy = LimitPolicy::default();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || {
+                let msg = format!("Invalid limit on line {}: {}", number + 1, line);
+                AppError::from_type(ErrorType::Custom(msg))
+            };
+            let (key, value) = line.split_once('=').ok_or_else(invalid)?;
+            let (scope, name) = key.trim().split_once('.').ok_or_else(invalid)?;
+            let (tier, currency) = match scope.split_once('/') {
+                Some((tier, currency)) => (tier.parse()?, Some(currency.parse()?)),
+                None => (scope.parse()?, None),
+            };
+            let limits = policy.limits.entry((tier, currency)).or_default();
+            let value = value.trim();
+            match name {
+                "max_transaction_amount" => {
+                    let amount = BigDecimal::from_str(value).map_err(|_| invalid())?;
+                    limits.max_transaction_amount = Some(amount);
+                }
+                "max_daily_debit_total" => {
+                    let amount = BigDecimal::from_str(value).map_err(|_| invalid())?;
+                    limits.max_daily_debit_total = Some(amount);
+                }
+                "max_daily_transfer_count" => {
+                    let count = value.parse().map_err(|_| invalid())?;
+                    limits.max_daily_transfer_count = Some(count);
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(policy)
+    }
+
+    /// The account's own limits, then its tier's limits for its currency,
+    /// then its tier's limits for any currency.
+    pub fn limits_for(&self, account: &Account) -> TransactionLimits {
+        let none = TransactionLimits::default();
+        let tier = self.limits.get(&(account.tier, None)).unwrap_or(&none);
+        let currency = self
+            .limits
+            .get(&(account.tier, Some(account.currency)))
+            .unwrap_or(&none);
+        account.limits.or(&currency.or(tier))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_then_currency_then_tier() {
+        let policy = LimitPolicy::parse(
+            "# limits\n\
+             STANDARD.max_transaction_amount=1000\n\
+             STANDARD.max_daily_transfer_count=5\n\
+             STANDARD/JPY.max_transaction_amount=150000\n",
+        )
+        .unwrap();
+        let mut account = Account::new("Sam".to_string(), String::new(), Currency::JPY);
+
+        let limits = policy.limits_for(&account);
+        assert_eq!(
+            limits.max_transaction_amount,
+            Some(BigDecimal::from(150000))
+        );
+        assert_eq!(limits.max_daily_transfer_count, Some(5));
+        assert_eq!(limits.max_daily_debit_total, None);
+
+        account.limits.max_daily_transfer_count = Some(50);
+        assert_eq!(
+            policy.limits_for(&account).max_daily_transfer_count,
+            Some(50)
+        );
+        account.tier = AccountTier::BUSINESS;
+        assert_eq!(policy.limits_for(&account).max_transaction_amount, None);
+    }
+
+    #[test]
+    fn test_invalid_line_is_rejected() {
+        assert!(LimitPolicy::parse("STANDARD.max_daily_transfer_count=-1").is_err());
+        assert!(LimitPolicy::parse("STANDARD.max_overdraft=1").is_err());
+        assert!(LimitPolicy::parse("GOLD.max_transaction_amount=1").is_err());
+    }
+}
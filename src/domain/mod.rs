@@ -1,16 +1,63 @@
 mod account;
 mod app_state;
-mod response;
+mod auth;
+mod batch;
+mod currency;
+mod customer;
+mod fx_quote;
+mod hold;
+mod idempotency;
+mod import;
+mod joint;
+mod ledger_entry;
+mod limits;
+mod schedule;
+mod statement;
 mod transaction;
+mod validation;
 
-pub use account::{Account, NewAccount};
+pub use account::{
+    Account, AccountClosure, AccountStatus, NewAccount, OverdraftLimit, SigningRule,
+    TABLE_NAME as ACCOUNTS_TABLE,
+};
 pub use app_state::AppState;
-pub use response::Response;
-pub use transaction::{NewTransaction, TransactionType};
+pub use auth::{ApiKey, Principal, Role, TABLE_NAME as API_KEYS_TABLE};
+pub use batch::{BatchItemResult, BatchMode, BatchResult, NewBatch, MAX_BATCH_BYTES};
+pub use currency::{Currency, FxConversion};
+pub use customer::{Customer, CustomerUpdate, NewCustomer, TABLE_NAME as CUSTOMERS_TABLE};
+pub use fx_quote::{FxQuote, NewFxQuote, TABLE_NAME as FX_QUOTES_TABLE};
+pub use hold::{CapturedHold, Hold, HoldCapture, NewHold, TABLE_NAME as HOLDS_TABLE};
+pub use idempotency::{
+    IdempotencyKey, IdempotencyRecord, PENDING_SCOPE, SCHEDULER_SCOPE,
+    TABLE_NAME as IDEMPOTENCY_KEYS_TABLE,
+};
+pub use import::{ImportReport, ImportRow, ImportRowResult, MAX_IMPORT_BYTES};
+pub use joint::{
+    AccountOwner, AccountOwners, Approval, NewAccountOwner, PendingStatus, PendingTransaction,
+    SigningRuleChange, APPROVALS_TABLE_NAME as APPROVALS_TABLE,
+    OWNERS_TABLE_NAME as ACCOUNT_OWNERS_TABLE, PENDING_TABLE_NAME as PENDING_TRANSACTIONS_TABLE,
+};
+pub use ledger_entry::{LedgerEntry, SystemAccounts, TABLE_NAME as LEDGER_ENTRIES_TABLE};
+pub use limits::{AccountLimits, AccountTier, LimitPolicy, TransactionLimits};
+#[cfg(test)]
+pub use schedule::Frequency;
+pub use schedule::{
+    NewSchedule, Schedule, ScheduleQuery, ScheduleRetry, ScheduleRun, ScheduleStatus,
+    RUNS_TABLE_NAME as SCHEDULE_RUNS_TABLE, TABLE_NAME as SCHEDULES_TABLE,
+};
+pub use statement::{Statement, StatementFormat, StatementLine, StatementQuery};
+pub use transaction::{
+    NewTransaction, Posting, Transaction, TransactionPage, TransactionQuery, TransactionReceipt,
+    TransactionType, TABLE_NAME as TRANSACTIONS_TABLE,
+};
+pub use validation::Validator;
 
+use crate::error::AppError;
 use chrono::{DateTime, FixedOffset, Utc};
 use ion_binary_rs::IonValue;
+use qldb::Document;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 
 pub trait QldbInsertable {
     fn table_name(&self) -> &str;
@@ -20,3 +67,16 @@ pub trait QldbInsertable {
 pub fn default_datetime() -> DateTime<FixedOffset> {
     Utc::now().into()
 }
+
+/// Like `Document::get_optional_value`, but a field set to null also reads as
+/// `None`, which is how an optional field is cleared by an update.
+pub fn optional_value<T>(doc: &Document, name: &str) -> Result<Option<T>, AppError>
+where
+    T: TryFrom<IonValue> + Send + Sync + Clone,
+    <T as TryFrom<IonValue>>::Error: std::error::Error + Send + Sync + 'static,
+{
+    match doc.get(name) {
+        None | Some(IonValue::Null(_)) => Ok(None),
+        Some(_) => Ok(doc.get_optional_value(name)?),
+    }
+}
@@ -0,0 +1,649 @@
+use bigdecimal::BigDecimal;
+use chrono::prelude::*;
+use chrono::Duration;
+use ion_binary_rs::{IonValue, NullIonValue};
+use qldb::Document;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::str::FromStr;
+
+use super::{optional_value, Currency, QldbInsertable};
+use crate::error::{AppError, ErrorType};
+use crate::util::{self, CronExpression};
+
+pub const TABLE_NAME: &str = "schedules";
+pub const RUNS_TABLE_NAME: &str = "schedule_runs";
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Frequency {
+    ONCE,
+    DAILY,
+    WEEKLY,
+    MONTHLY,
+    CRON,
+}
+
+impl Display for Frequency {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for Frequency {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ONCE" => Ok(Frequency::ONCE),
+            "DAILY" => Ok(Frequency::DAILY),
+            "WEEKLY" => Ok(Frequency::WEEKLY),
+            "MONTHLY" => Ok(Frequency::MONTHLY),
+            "CRON" => Ok(Frequency::CRON),
+            _ => Err(AppError::from_type(ErrorType::Custom(format!(
+                "Unknown schedule frequency: {}",
+                s
+            )))),
+        }
+    }
+}
+
+/// Active schedules are run by the scheduler. Completed, failed and
+/// cancelled schedules are finished and never run again.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScheduleStatus {
+    ACTIVE,
+    PAUSED,
+    COMPLETED,
+    FAILED,
+    CANCELLED,
+}
+
+impl ScheduleStatus {
+    /// The changes a client can make: pausing, resuming and cancelling.
+    pub fn can_change_to(&self, next: ScheduleStatus) -> bool {
+        matches!(
+            (self, next),
+            (ScheduleStatus::ACTIVE, ScheduleStatus::PAUSED)
+                | (ScheduleStatus::PAUSED, ScheduleStatus::ACTIVE)
+                | (ScheduleStatus::ACTIVE, ScheduleStatus::CANCELLED)
+                | (ScheduleStatus::PAUSED, ScheduleStatus::CANCELLED)
+        )
+    }
+}
+
+impl Display for ScheduleStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for ScheduleStatus {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ACTIVE" => Ok(ScheduleStatus::ACTIVE),
+            "PAUSED" => Ok(ScheduleStatus::PAUSED),
+            "COMPLETED" => Ok(ScheduleStatus::COMPLETED),
+            "FAILED" => Ok(ScheduleStatus::FAILED),
+            "CANCELLED" => Ok(ScheduleStatus::CANCELLED),
+            _ => Err(AppError::from_type(ErrorType::Custom(format!(
+                "Unknown schedule status: {}",
+                s
+            )))),
+        }
+    }
+}
+
+/// How often a failed run is tried again before its occurrence is given up.
+#[derive(Debug, Clone, Copy)]
+pub struct ScheduleRetry {
+    pub max_attempts: u32,
+    pub delay: Duration,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewSchedule {
+    pub sender_account_number: String,
+    pub recipient_account_number: String,
+    pub amount: BigDecimal,
+    pub currency: Option<Currency>,
+    pub frequency: Frequency,
+    pub cron: Option<String>,
+    pub start_at: Option<DateTime<FixedOffset>>,
+    pub end_at: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScheduleQuery {
+    pub account_number: Option<String>,
+}
+
+fn add_months(start: DateTime<Utc>, months: u32) -> DateTime<Utc> {
+    let month0 = start.month0() + months;
+    let year = start.year() + (month0 / 12) as i32;
+    let month = month0 % 12 + 1;
+    // The 31st runs on the last day of shorter months.
+    let mut day = start.day();
+    let date = loop {
+        match NaiveDate::from_ymd_opt(year, month, day) {
+            Some(date) => break date,
+            None => day -= 1,
+        }
+    };
+    DateTime::from_utc(date.and_time(start.time()), Utc)
+}
+
+/// A transfer from one account to another, made once or repeatedly. `due_at`
+/// is the occurrence to be paid next and `next_run_at` when the scheduler
+/// will next try it, which is later than `due_at` while a failed run waits
+/// to be retried.
+#[derive(Debug, Clone, Serialize)]
+pub struct Schedule {
+    pub id: String,
+    pub sender_account_number: String,
+    pub recipient_account_number: String,
+    pub amount: BigDecimal,
+    pub currency: Currency,
+    pub frequency: Frequency,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cron: Option<CronExpression>,
+    pub start_at: DateTime<FixedOffset>,
+    pub end_at: Option<DateTime<FixedOffset>>,
+    pub status: ScheduleStatus,
+    pub due_at: Option<DateTime<FixedOffset>>,
+    pub next_run_at: Option<DateTime<FixedOffset>>,
+    pub failed_attempts: u32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<FixedOffset>,
+    pub updated_at: DateTime<FixedOffset>,
+}
+
+impl Schedule {
+    /// Checks the payload against the sender's currency and works out the
+    /// first occurrence, at or after `start_at` (by default now).
+    pub fn new(new_schedule: NewSchedule, currency: Currency) -> Result<Schedule, AppError> {
+        let invalid = |msg: &str| AppError::new(Some(msg.to_string()), ErrorType::PayloadError);
+        if new_schedule.amount <= BigDecimal::default() || !currency.fits(&new_schedule.amount) {
+            return Err(invalid("Invalid schedule amount"));
+        }
+        if let Some(payload_currency) = new_schedule.currency {
+            if payload_currency != currency {
+                let msg = format!(
+                    "Amount is in {} but account {} holds {}",
+                    payload_currency, new_schedule.sender_account_number, currency
+                );
+                return Err(AppError::new(Some(msg), ErrorType::CurrencyMismatch));
+            }
+        }
+        let cron = match (new_schedule.frequency, new_schedule.cron) {
+            (Frequency::CRON, Some(cron)) => Some(cron.parse()?),
+            (Frequency::CRON, None) => return Err(invalid("cron is required for CRON schedules")),
+            (_, Some(_)) => return Err(invalid("cron is only accepted for CRON schedules")),
+            (_, None) => None,
+        };
+        let now: DateTime<FixedOffset> = Utc::now().into();
+        let mut schedule = Schedule {
+            id: util::generate_alphanumeric(20)?,
+            sender_account_number: new_schedule.sender_account_number,
+            recipient_account_number: new_schedule.recipient_account_number,
+            amount: currency.round(&new_schedule.amount),
+            currency,
+            frequency: new_schedule.frequency,
+            cron,
+            start_at: new_schedule.start_at.unwrap_or(now),
+            end_at: new_schedule.end_at,
+            status: ScheduleStatus::ACTIVE,
+            due_at: None,
+            next_run_at: None,
+            failed_attempts: 0,
+            last_error: None,
+            created_at: now,
+            updated_at: now,
+        };
+        let before_start = schedule.start_at.with_timezone(&Utc) - Duration::seconds(1);
+        match schedule.occurrence_after(before_start) {
+            Some(due_at) => schedule.set_due(Some(due_at)),
+            None => return Err(invalid("Schedule has no occurrence before it ends")),
+        }
+        Ok(schedule)
+    }
+
+    /// The first occurrence strictly after `after`, unless the schedule has
+    /// ended by then.
+    pub fn occurrence_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = self.start_at.with_timezone(&Utc);
+        let occurrence = if after < start && self.frequency != Frequency::CRON {
+            Some(start)
+        } else {
+            let every = |step: Duration| {
+                let steps = (after - start).num_seconds() / step.num_seconds() + 1;
+                Some(start + step * steps as i32)
+            };
+            match self.frequency {
+                Frequency::ONCE => None,
+                Frequency::DAILY => every(Duration::days(1)),
+                Frequency::WEEKLY => every(Duration::weeks(1)),
+                Frequency::MONTHLY => {
+                    let mut months = ((after.year() - start.year()) * 12 + after.month0() as i32
+                        - start.month0() as i32) as u32;
+                    while add_months(start, months) <= after {
+                        months += 1;
+                    }
+                    Some(add_months(start, months))
+                }
+                Frequency::CRON => {
+                    let after = after.max(start - Duration::seconds(1));
+                    self.cron.as_ref().and_then(|cron| cron.next_after(after))
+                }
+            }
+        };
+        occurrence.filter(|time| match self.end_at {
+            Some(end_at) => *time <= end_at,
+            None => true,
+        })
+    }
+
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.status == ScheduleStatus::ACTIVE && matches!(self.next_run_at, Some(at) if at <= now)
+    }
+
+    /// Key the transfer for the current occurrence is made with, so it is
+    /// paid once however many times it is run.
+    pub fn idempotency_key(&self) -> String {
+        let due_at = self.due_at.map(|at| at.timestamp()).unwrap_or_default();
+        format!("schedule-{}-{}", self.id, due_at)
+    }
+
+    fn set_due(&mut self, due_at: Option<DateTime<Utc>>) {
+        self.due_at = due_at.map(|at| at.into());
+        self.next_run_at = self.due_at;
+        self.failed_attempts = 0;
+    }
+
+    /// Moves on to the next occurrence after the current one. Occurrences
+    /// already in the past, missed while the service was down or the schedule
+    /// paused, are skipped. Returns false when there is none left.
+    fn advance(&mut self, now: DateTime<Utc>) -> bool {
+        let after = match self.due_at {
+            Some(due_at) => now.max(due_at.with_timezone(&Utc)),
+            None => now,
+        };
+        let next = self.occurrence_after(after);
+        self.set_due(next);
+        next.is_some()
+    }
+
+    pub fn succeeded(&mut self, now: DateTime<Utc>) {
+        self.last_error = None;
+        if !self.advance(now) && self.status == ScheduleStatus::ACTIVE {
+            self.status = ScheduleStatus::COMPLETED;
+        }
+        self.updated_at = now.into();
+    }
+
+    /// Holds the current attempt for one scheduler instance until `until`:
+    /// the schedule is not due again before then, unless the attempt is
+    /// recorded first.
+    pub fn claim(&mut self, until: DateTime<Utc>) {
+        self.next_run_at = Some(until.into());
+    }
+
+    /// Schedules a retry of the current occurrence, or gives it up once it
+    /// has failed `max_attempts` times. Returns whether it will be retried.
+    pub fn failed(&mut self, error: String, retry: &ScheduleRetry, now: DateTime<Utc>) -> bool {
+        self.last_error = Some(error);
+        self.failed_attempts += 1;
+        self.updated_at = now.into();
+        if self.failed_attempts < retry.max_attempts {
+            self.next_run_at = Some((now + retry.delay).into());
+            return true;
+        }
+        if !self.advance(now) && self.status == ScheduleStatus::ACTIVE {
+            self.status = ScheduleStatus::FAILED;
+        }
+        false
+    }
+
+    /// Ends the schedule for good, e.g. when one of its accounts is closed.
+    pub fn abandon(&mut self, error: String, now: DateTime<Utc>) {
+        self.last_error = Some(error);
+        self.status = ScheduleStatus::FAILED;
+        self.set_due(None);
+        self.updated_at = now.into();
+    }
+
+    pub fn change_status(&mut self, status: ScheduleStatus) -> Result<(), AppError> {
+        if !self.status.can_change_to(status) {
+            let msg = format!(
+                "Schedule {} cannot go from {} to {}",
+                self.id, self.status, status
+            );
+            return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+        }
+        let now = Utc::now();
+        let mut status = status;
+        // A resumed schedule does not pay what fell due while it was paused,
+        // except a one-off payment, which is made straight away.
+        if status == ScheduleStatus::ACTIVE {
+            let due_at = self.due_at;
+            match due_at {
+                None => status = ScheduleStatus::COMPLETED,
+                Some(due_at) if due_at < now && self.frequency == Frequency::ONCE => {
+                    self.next_run_at = Some(now.into());
+                }
+                Some(due_at) if due_at < now && !self.advance(now) => {
+                    status = ScheduleStatus::COMPLETED;
+                }
+                _ => (),
+            }
+        }
+        self.status = status;
+        self.updated_at = now.into();
+        Ok(())
+    }
+
+    /// Fields that change while the schedule runs, for updates.
+    pub fn state_params(&self) -> Vec<(&'static str, IonValue)> {
+        let datetime = |at: &Option<DateTime<FixedOffset>>| match at {
+            Some(at) => IonValue::DateTime(*at),
+            None => IonValue::Null(NullIonValue::DateTime),
+        };
+        let last_error = match &self.last_error {
+            Some(error) => IonValue::String(error.clone()),
+            None => IonValue::Null(NullIonValue::String),
+        };
+        vec![
+            ("status", IonValue::String(self.status.to_string())),
+            ("due_at", datetime(&self.due_at)),
+            ("next_run_at", datetime(&self.next_run_at)),
+            (
+                "failed_attempts",
+                IonValue::Integer(self.failed_attempts.into()),
+            ),
+            ("last_error", last_error),
+            ("updated_at", IonValue::DateTime(self.updated_at)),
+        ]
+    }
+
+    pub fn from_documents(docs: Vec<Document>) -> Result<Vec<Self>, AppError> {
+        docs.iter().map(|doc| doc.try_into()).collect()
+    }
+}
+
+impl QldbInsertable for Schedule {
+    fn table_name(&self) -> &str {
+        TABLE_NAME
+    }
+
+    fn to_params(&self) -> HashMap<String, IonValue> {
+        let mut params = HashMap::new();
+        params.insert("id".to_string(), IonValue::String(self.id.clone()));
+        params.insert(
+            "sender_account_number".to_string(),
+            IonValue::String(self.sender_account_number.clone()),
+        );
+        params.insert(
+            "recipient_account_number".to_string(),
+            IonValue::String(self.recipient_account_number.clone()),
+        );
+        params.insert("amount".to_string(), IonValue::Decimal(self.amount.clone()));
+        params.insert(
+            "currency".to_string(),
+            IonValue::String(self.currency.to_string()),
+        );
+        params.insert(
+            "frequency".to_string(),
+            IonValue::String(self.frequency.to_string()),
+        );
+        if let Some(cron) = &self.cron {
+            params.insert("cron".to_string(), IonValue::String(cron.to_string()));
+        }
+        params.insert("start_at".to_string(), IonValue::DateTime(self.start_at));
+        if let Some(end_at) = self.end_at {
+            params.insert("end_at".to_string(), IonValue::DateTime(end_at));
+        }
+        params.extend(
+            self.state_params()
+                .into_iter()
+                .map(|(field, value)| (field.to_string(), value)),
+        );
+        params.insert(
+            "created_at".to_string(),
+            IonValue::DateTime(self.created_at),
+        );
+        params
+    }
+}
+
+impl TryFrom<&Document> for Schedule {
+    type Error = AppError;
+
+    fn try_from(doc: &Document) -> Result<Self, Self::Error> {
+        let currency: Currency = doc.get_value::<String>("currency")?.parse()?;
+        let frequency: String = doc.get_value("frequency")?;
+        let cron: Option<String> = optional_value(doc, "cron")?;
+        let status: String = doc.get_value("status")?;
+        let amount: BigDecimal = doc.get_value("amount")?;
+        let failed_attempts: i64 = doc.get_value("failed_attempts")?;
+        let schedule = Schedule {
+            id: doc.get_value("id")?,
+            sender_account_number: doc.get_value("sender_account_number")?,
+            recipient_account_number: doc.get_value("recipient_account_number")?,
+            amount: amount.with_scale(currency.minor_units()),
+            currency,
+            frequency: frequency.parse()?,
+            cron: cron.map(|cron| cron.parse()).transpose()?,
+            start_at: doc.get_value("start_at")?,
+            end_at: optional_value(doc, "end_at")?,
+            status: status.parse()?,
+            due_at: optional_value(doc, "due_at")?,
+            next_run_at: optional_value(doc, "next_run_at")?,
+            failed_attempts: failed_attempts.try_into().unwrap_or(u32::MAX),
+            last_error: optional_value(doc, "last_error")?,
+            created_at: doc.get_value("created_at")?,
+            updated_at: doc.get_value("updated_at")?,
+        };
+        Ok(schedule)
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RunStatus {
+    SUCCEEDED,
+    FAILED,
+}
+
+impl Display for RunStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for RunStatus {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "SUCCEEDED" => Ok(RunStatus::SUCCEEDED),
+            "FAILED" => Ok(RunStatus::FAILED),
+            _ => Err(AppError::from_type(ErrorType::Custom(format!(
+                "Unknown run status: {}",
+                s
+            )))),
+        }
+    }
+}
+
+/// One attempt at paying an occurrence of a schedule.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleRun {
+    pub id: String,
+    pub schedule_id: String,
+    pub due_at: DateTime<FixedOffset>,
+    pub attempt: u32,
+    pub status: RunStatus,
+    pub transaction_id: Option<String>,
+    pub error: Option<String>,
+    pub created_at: DateTime<FixedOffset>,
+}
+
+impl ScheduleRun {
+    /// Records the outcome of running `schedule`, before the schedule moves
+    /// on from the occurrence.
+    pub fn new(schedule: &Schedule, outcome: &Result<String, AppError>) -> ScheduleRun {
+        let now: DateTime<FixedOffset> = Utc::now().into();
+        let (status, transaction_id, error) = match outcome {
+            Ok(transaction_id) => (RunStatus::SUCCEEDED, Some(transaction_id.clone()), None),
+            Err(e) => (RunStatus::FAILED, None, Some(e.message())),
+        };
+        ScheduleRun {
+            id: util::generate_alphanumeric(20).unwrap(),
+            schedule_id: schedule.id.clone(),
+            due_at: schedule.due_at.unwrap_or(now),
+            attempt: schedule.failed_attempts + 1,
+            status,
+            transaction_id,
+            error,
+            created_at: now,
+        }
+    }
+
+    pub fn from_documents(docs: Vec<Document>) -> Result<Vec<Self>, AppError> {
+        docs.iter().map(|doc| doc.try_into()).collect()
+    }
+}
+
+impl QldbInsertable for ScheduleRun {
+    fn table_name(&self) -> &str {
+        RUNS_TABLE_NAME
+    }
+
+    fn to_params(&self) -> HashMap<String, IonValue> {
+        let mut params = HashMap::new();
+        params.insert("id".to_string(), IonValue::String(self.id.clone()));
+        params.insert(
+            "schedule_id".to_string(),
+            IonValue::String(self.schedule_id.clone()),
+        );
+        params.insert("due_at".to_string(), IonValue::DateTime(self.due_at));
+        params.insert(
+            "attempt".to_string(),
+            IonValue::Integer(self.attempt.into()),
+        );
+        params.insert(
+            "status".to_string(),
+            IonValue::String(self.status.to_string()),
+        );
+        if let Some(transaction_id) = &self.transaction_id {
+            params.insert(
+                "transaction_id".to_string(),
+                IonValue::String(transaction_id.clone()),
+            );
+        }
+        if let Some(error) = &self.error {
+            params.insert("error".to_string(), IonValue::String(error.clone()));
+        }
+        params.insert(
+            "created_at".to_string(),
+            IonValue::DateTime(self.created_at),
+        );
+        params
+    }
+}
+
+impl TryFrom<&Document> for ScheduleRun {
+    type Error = AppError;
+
+    fn try_from(doc: &Document) -> Result<Self, Self::Error> {
+        let status: String = doc.get_value("status")?;
+        let attempt: i64 = doc.get_value("attempt")?;
+        let run = ScheduleRun {
+            id: doc.get_value("id")?,
+            schedule_id: doc.get_value("schedule_id")?,
+            due_at: doc.get_value("due_at")?,
+            attempt: attempt.try_into().unwrap_or(u32::MAX),
+            status: status.parse()?,
+            transaction_id: optional_value(doc, "transaction_id")?,
+            error: optional_value(doc, "error")?,
+            created_at: doc.get_value("created_at")?,
+        };
+        Ok(run)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    fn schedule(frequency: Frequency, cron: Option<&str>, start_at: &str) -> Schedule {
+        let new_schedule = NewSchedule {
+            sender_account_number: "0123456789".to_string(),
+            recipient_account_number: "9876543210".to_string(),
+            amount: BigDecimal::from(25),
+            currency: None,
+            frequency,
+            cron: cron.map(|cron| cron.to_string()),
+            start_at: Some(at(start_at)),
+            end_at: None,
+        };
+        Schedule::new(new_schedule, Currency::USD).unwrap()
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        at(s).with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_monthly_schedule_keeps_its_day() {
+        let schedule = schedule(Frequency::MONTHLY, None, "2021-01-31T09:00:00Z");
+        assert_eq!(schedule.due_at, Some(at("2021-01-31T09:00:00Z")));
+        assert_eq!(
+            schedule.occurrence_after(utc("2021-01-31T09:00:00Z")),
+            Some(utc("2021-02-28T09:00:00Z"))
+        );
+        assert_eq!(
+            schedule.occurrence_after(utc("2021-03-15T00:00:00Z")),
+            Some(utc("2021-03-31T09:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_cron_schedule_starts_at_first_match() {
+        let schedule = schedule(Frequency::CRON, Some("0 9 * * 1"), "2021-01-29T12:00:00Z");
+        assert_eq!(schedule.due_at, Some(at("2021-02-01T09:00:00Z")));
+    }
+
+    #[test]
+    fn test_failed_run_is_retried_then_skipped() {
+        let retry = ScheduleRetry {
+            max_attempts: 2,
+            delay: Duration::minutes(5),
+        };
+        let mut schedule = schedule(Frequency::DAILY, None, "2021-01-29T09:00:00Z");
+        let now = utc("2021-01-29T09:00:10Z");
+
+        assert!(schedule.failed("Insufficient balance".to_string(), &retry, now));
+        assert_eq!(schedule.due_at, Some(at("2021-01-29T09:00:00Z")));
+        assert_eq!(schedule.next_run_at, Some(at("2021-01-29T09:05:10Z")));
+
+        assert!(!schedule.failed("Insufficient balance".to_string(), &retry, now));
+        assert_eq!(schedule.status, ScheduleStatus::ACTIVE);
+        assert_eq!(schedule.due_at, Some(at("2021-01-30T09:00:00Z")));
+        assert_eq!(schedule.failed_attempts, 0);
+
+        let mut once = self::schedule(Frequency::ONCE, None, "2021-01-29T09:00:00Z");
+        once.succeeded(now);
+        assert_eq!(once.status, ScheduleStatus::COMPLETED);
+        assert_eq!(once.next_run_at, None);
+    }
+}
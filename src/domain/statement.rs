@@ -0,0 +1,193 @@
+use bigdecimal::BigDecimal;
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{Currency, TransactionType};
+use crate::error::{AppError, ErrorType};
+
+/// Query string accepted by the statement endpoint. `from` and `to` are
+/// inclusive calendar days in UTC; they default to the day the account was
+/// opened and today.
+#[derive(Debug, Default, Deserialize)]
+pub struct StatementQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    #[serde(default)]
+    pub format: StatementFormat,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatementFormat {
+    #[default]
+    Json,
+    Csv,
+    Text,
+}
+
+/// One movement of money in or out of the account. `amount` is signed:
+/// negative when money left the account.
+#[derive(Debug, Serialize)]
+pub struct StatementLine {
+    pub transaction_id: String,
+    pub date: DateTime<FixedOffset>,
+    pub transaction_type: Option<TransactionType>,
+    pub description: String,
+    pub amount: BigDecimal,
+    pub balance: BigDecimal,
+}
+
+/// Movements of an account over a period with the balance after each one.
+/// The opening balance plus every amount gives the closing balance, which
+/// is the account balance when the period runs up to today.
+#[derive(Debug, Serialize)]
+pub struct Statement {
+    pub account_number: String,
+    pub name: String,
+    pub currency: Currency,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub opening_balance: BigDecimal,
+    pub total_credits: BigDecimal,
+    pub total_debits: BigDecimal,
+    pub closing_balance: BigDecimal,
+    pub lines: Vec<StatementLine>,
+    pub generated_at: DateTime<Utc>,
+}
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const TEXT_DESCRIPTION_WIDTH: usize = 32;
+const TEXT_AMOUNT_WIDTH: usize = 16;
+
+impl Statement {
+    /// One row per line between an opening and a closing balance row.
+    pub fn to_csv(&self) -> Result<String, AppError> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        let mut rows = vec![vec![
+            "date".to_string(),
+            "transaction_id".to_string(),
+            "transaction_type".to_string(),
+            "description".to_string(),
+            "amount".to_string(),
+            "balance".to_string(),
+        ]];
+        rows.push(vec![
+            self.from.format(DATE_FORMAT).to_string(),
+            String::new(),
+            String::new(),
+            "Opening balance".to_string(),
+            String::new(),
+            self.opening_balance.to_string(),
+        ]);
+        for line in &self.lines {
+            rows.push(vec![
+                line.date.to_rfc3339(),
+                line.transaction_id.clone(),
+                line.transaction_type
+                    .as_ref()
+                    .map(|t| t.to_string())
+                    .unwrap_or_default(),
+                line.description.clone(),
+                line.amount.to_string(),
+                line.balance.to_string(),
+            ]);
+        }
+        rows.push(vec![
+            self.to.format(DATE_FORMAT).to_string(),
+            String::new(),
+            String::new(),
+            "Closing balance".to_string(),
+            String::new(),
+            self.closing_balance.to_string(),
+        ]);
+        for row in rows {
+            writer.write_record(&row).map_err(custom_error)?;
+        }
+        let bytes = writer.into_inner().map_err(custom_error)?;
+        String::from_utf8(bytes).map_err(custom_error)
+    }
+
+    /// Fixed-width layout for printing or plain-text mail. Long descriptions
+    /// are cut to fit their column.
+    pub fn to_text(&self) -> String {
+        let rule = format!(
+            "{:-<10}  {:-<8}  {:-<d$}  {:->a$}  {:->a$}\n",
+            "",
+            "",
+            "",
+            "",
+            "",
+            d = TEXT_DESCRIPTION_WIDTH,
+            a = TEXT_AMOUNT_WIDTH
+        );
+        let row = |date: &str, kind: &str, description: &str, amount: &str, balance: &str| {
+            let description: String = description.chars().take(TEXT_DESCRIPTION_WIDTH).collect();
+            format!(
+                "{:<10}  {:<8}  {:<d$}  {:>a$}  {:>a$}\n",
+                date,
+                kind,
+                description,
+                amount,
+                balance,
+                d = TEXT_DESCRIPTION_WIDTH,
+                a = TEXT_AMOUNT_WIDTH
+            )
+        };
+
+        let mut text = String::from("STATEMENT OF ACCOUNT\n\n");
+        text += &format!("Account:   {} ({})\n", self.account_number, self.name);
+        text += &format!("Currency:  {}\n", self.currency);
+        text += &format!(
+            "Period:    {} to {}\n",
+            self.from.format(DATE_FORMAT),
+            self.to.format(DATE_FORMAT)
+        );
+        text += &format!(
+            "Generated: {}\n\n",
+            self.generated_at.format("%Y-%m-%d %H:%M:%S UTC")
+        );
+        text += &row("Date", "Type", "Description", "Amount", "Balance");
+        text += &rule;
+        text += &row(
+            &self.from.format(DATE_FORMAT).to_string(),
+            "",
+            "Opening balance",
+            "",
+            &self.opening_balance.to_string(),
+        );
+        for line in &self.lines {
+            text += &row(
+                &line
+                    .date
+                    .with_timezone(&Utc)
+                    .format(DATE_FORMAT)
+                    .to_string(),
+                &line
+                    .transaction_type
+                    .as_ref()
+                    .map(|t| t.to_string())
+                    .unwrap_or_default(),
+                &line.description,
+                &line.amount.to_string(),
+                &line.balance.to_string(),
+            );
+        }
+        text += &row(
+            &self.to.format(DATE_FORMAT).to_string(),
+            "",
+            "Closing balance",
+            "",
+            &self.closing_balance.to_string(),
+        );
+        text += &rule;
+        text += &format!(
+            "Total credits: {}  Total debits: {}\n",
+            self.total_credits, self.total_debits
+        );
+        text
+    }
+}
+
+fn custom_error<E: ToString>(e: E) -> AppError {
+    AppError::from_type(ErrorType::Custom(e.to_string()))
+}
@@ -1,17 +1,474 @@
 use bigdecimal::BigDecimal;
-use serde::Deserialize;
+use chrono::prelude::*;
+use ion_binary_rs::IonValue;
+use qldb::Document;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::str::FromStr;
 
-#[derive(Debug, Deserialize, PartialEq)]
+use super::{Currency, FxConversion, QldbInsertable, Validator};
+use crate::error::{AppError, ErrorType};
+
+pub const TABLE_NAME: &str = "transactions";
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum TransactionType {
     CREDIT,
     DEBIT,
     TRANSFER,
 }
 
-#[derive(Debug, Deserialize)]
+impl Display for TransactionType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for TransactionType {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "CREDIT" => Ok(TransactionType::CREDIT),
+            "DEBIT" => Ok(TransactionType::DEBIT),
+            "TRANSFER" => Ok(TransactionType::TRANSFER),
+            _ => Err(AppError::from_type(ErrorType::Custom(format!(
+                "Unknown transaction type: {}",
+                s
+            )))),
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum TransactionStatus {
+    COMPLETED,
+}
+
+impl Display for TransactionStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for TransactionStatus {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "COMPLETED" => Ok(TransactionStatus::COMPLETED),
+            _ => Err(AppError::from_type(ErrorType::Custom(format!(
+                "Unknown transaction status: {}",
+                s
+            )))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct NewTransaction {
     pub amount: BigDecimal,
+    /// Currency of `amount`. When given it must match the account the money
+    /// comes from (the sender for transfers, otherwise the recipient).
+    pub currency: Option<Currency>,
+    pub sender_account_number: Option<String>,
+    pub recipient_account_number: String,
+    pub transaction_type: TransactionType,
+    /// Id of an FX quote to convert a transfer between accounts of different
+    /// currencies at. Without one the current rate is used.
+    pub fx_quote_id: Option<String>,
+}
+
+impl NewTransaction {
+    /// Checks what can be checked without looking at the accounts: the
+    /// amount, and that a transfer has a sender other than its recipient.
+    pub fn validate(&self) -> Result<(), AppError> {
+        let mut validator = Validator::default();
+        validator.amount("amount", &self.amount, self.currency);
+        if self.recipient_account_number.trim().is_empty() {
+            validator.invalid("recipient_account_number", "must not be empty");
+        }
+        if self.transaction_type == TransactionType::TRANSFER {
+            match &self.sender_account_number {
+                Some(sender) if sender.trim().is_empty() => {
+                    validator.invalid("sender_account_number", "must not be empty")
+                }
+                Some(sender) if *sender == self.recipient_account_number => {
+                    validator.invalid("recipient_account_number", "must not be the sender account")
+                }
+                Some(_) => {}
+                None => validator.invalid("sender_account_number", "is required for transfers"),
+            }
+        } else if self.fx_quote_id.is_some() {
+            validator.invalid("fx_quote_id", "is only accepted for transfers");
+        }
+        validator.finish()
+    }
+
+    /// Canonical form of the request, used to recognise a retry of the same
+    /// request under an idempotency key. Amounts are normalised so `10` and
+    /// `10.00` describe the same request.
+    pub fn fingerprint(&self) -> String {
+        format!(
+            "{}|{}|{}|{}|{}|{}",
+            self.transaction_type,
+            self.amount.normalized(),
+            self.currency.map(|c| c.to_string()).unwrap_or_default(),
+            self.sender_account_number.as_deref().unwrap_or(""),
+            self.recipient_account_number,
+            self.fx_quote_id.as_deref().unwrap_or("")
+        )
+    }
+}
+
+/// Money movement that has passed every check and only has to be written to
+/// the ledger. Credits and debits are posted against a system account.
+#[derive(Debug, Clone)]
+pub enum Posting {
+    DebitCredit {
+        account_number: String,
+        contra_account_number: String,
+        amount: BigDecimal,
+        transaction_type: TransactionType,
+    },
+    Transfer {
+        sender_account_number: String,
+        recipient_account_number: String,
+        amount: BigDecimal,
+        conversion: Option<FxConversion>,
+    },
+}
+
+impl Posting {
+    /// Account the posting takes money out of, with how and how much, if it
+    /// sends money. Credits are paid in from a system account.
+    pub fn sender(&self) -> Option<(&str, TransactionType, &BigDecimal)> {
+        match self {
+            Posting::DebitCredit {
+                account_number,
+                amount,
+                transaction_type: TransactionType::DEBIT,
+                ..
+            } => Some((account_number, TransactionType::DEBIT, amount)),
+            Posting::Transfer {
+                sender_account_number,
+                amount,
+                ..
+            } => Some((sender_account_number, TransactionType::TRANSFER, amount)),
+            Posting::DebitCredit { .. } => None,
+        }
+    }
+}
+
+/// Query string accepted by the transaction history endpoint. `from` and `to`
+/// are inclusive calendar days in UTC.
+#[derive(Debug, Default, Deserialize)]
+pub struct TransactionQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub transaction_type: Option<TransactionType>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TransactionPage {
+    pub transactions: Vec<Transaction>,
+    pub next_cursor: Option<String>,
+}
+
+/// Immutable journal entry written in the same ledger transaction as the
+/// balance update it describes. For CREDIT and DEBIT only the recipient side
+/// is set, matching how `NewTransaction` names the affected account.
+#[derive(Debug, Clone, Serialize)]
+pub struct Transaction {
+    pub id: String,
+    pub transaction_type: TransactionType,
+    pub amount: BigDecimal,
+    /// Currency of `amount` and of the sender's balances.
+    pub currency: Currency,
     pub sender_account_number: Option<String>,
     pub recipient_account_number: String,
+    pub sender_balance_before: Option<BigDecimal>,
+    pub sender_balance_after: Option<BigDecimal>,
+    pub recipient_balance_before: BigDecimal,
+    pub recipient_balance_after: BigDecimal,
+    /// Set on cross-currency transfers: the rate applied and the amount, in
+    /// the recipient's currency, that the recipient received.
+    pub fx_rate: Option<BigDecimal>,
+    pub converted_amount: Option<BigDecimal>,
+    pub converted_currency: Option<Currency>,
+    pub status: TransactionStatus,
+    pub created_at: DateTime<FixedOffset>,
+}
+
+impl Transaction {
+    pub fn new(
+        id: String,
+        transaction_type: TransactionType,
+        amount: BigDecimal,
+        currency: Currency,
+        recipient_account_number: String,
+        recipient_balance_before: BigDecimal,
+        recipient_balance_after: BigDecimal,
+    ) -> Transaction {
+        Transaction {
+            id,
+            transaction_type,
+            amount,
+            currency,
+            sender_account_number: None,
+            recipient_account_number,
+            sender_balance_before: None,
+            sender_balance_after: None,
+            recipient_balance_before,
+            recipient_balance_after,
+            fx_rate: None,
+            converted_amount: None,
+            converted_currency: None,
+            status: TransactionStatus::COMPLETED,
+            created_at: Utc::now().into(),
+        }
+    }
+
+    /// Whether this moved money out of `account_number`: a debit of it or a
+    /// transfer it sent.
+    pub fn is_sent_by(&self, account_number: &str) -> bool {
+        match self.transaction_type {
+            TransactionType::DEBIT => self.recipient_account_number == account_number,
+            TransactionType::TRANSFER => {
+                self.sender_account_number.as_deref() == Some(account_number)
+            }
+            TransactionType::CREDIT => false,
+        }
+    }
+
+    pub fn with_sender(
+        mut self,
+        sender_account_number: String,
+        sender_balance_before: BigDecimal,
+        sender_balance_after: BigDecimal,
+    ) -> Transaction {
+        self.sender_account_number = Some(sender_account_number);
+        self.sender_balance_before = Some(sender_balance_before);
+        self.sender_balance_after = Some(sender_balance_after);
+        self
+    }
+
+    pub fn with_conversion(
+        mut self,
+        fx_rate: BigDecimal,
+        converted_amount: BigDecimal,
+        converted_currency: Currency,
+    ) -> Transaction {
+        self.fx_rate = Some(fx_rate);
+        self.converted_amount = Some(converted_amount);
+        self.converted_currency = Some(converted_currency);
+        self
+    }
+
+    /// Position of this entry in the newest-first journal ordering, handed
+    /// to clients as an opaque pagination cursor.
+    pub fn cursor(&self) -> String {
+        format!("{}.{}", self.created_at.timestamp_nanos(), self.id)
+    }
+
+    pub fn from_documents(result: Vec<Document>) -> Result<Vec<Self>, AppError> {
+        result.iter().map(Transaction::try_from).collect()
+    }
+}
+
+/// Machine-readable outcome of a credit, debit or transfer, built from the
+/// journal entry written for it. Balances are the ones left after the
+/// transaction was applied.
+#[derive(Debug, Clone, Serialize)]
+pub struct TransactionReceipt {
+    pub transaction_id: String,
     pub transaction_type: TransactionType,
+    pub amount: BigDecimal,
+    pub currency: Currency,
+    pub sender_account_number: Option<String>,
+    pub sender_balance: Option<BigDecimal>,
+    pub recipient_account_number: String,
+    pub recipient_balance: BigDecimal,
+    pub fx_rate: Option<BigDecimal>,
+    pub converted_amount: Option<BigDecimal>,
+    pub converted_currency: Option<Currency>,
+    pub status: TransactionStatus,
+    pub created_at: DateTime<FixedOffset>,
+}
+
+impl From<Transaction> for TransactionReceipt {
+    fn from(transaction: Transaction) -> Self {
+        TransactionReceipt {
+            transaction_id: transaction.id,
+            transaction_type: transaction.transaction_type,
+            amount: transaction.amount,
+            currency: transaction.currency,
+            sender_account_number: transaction.sender_account_number,
+            sender_balance: transaction.sender_balance_after,
+            recipient_account_number: transaction.recipient_account_number,
+            recipient_balance: transaction.recipient_balance_after,
+            fx_rate: transaction.fx_rate,
+            converted_amount: transaction.converted_amount,
+            converted_currency: transaction.converted_currency,
+            status: transaction.status,
+            created_at: transaction.created_at,
+        }
+    }
+}
+
+impl QldbInsertable for Transaction {
+    fn table_name(&self) -> &str {
+        TABLE_NAME
+    }
+
+    fn to_params(&self) -> HashMap<String, IonValue> {
+        let mut params = HashMap::new();
+        params.insert("id".to_string(), IonValue::String(self.id.clone()));
+        params.insert(
+            "transaction_type".to_string(),
+            IonValue::String(self.transaction_type.to_string()),
+        );
+        params.insert("amount".to_string(), IonValue::Decimal(self.amount.clone()));
+        params.insert(
+            "currency".to_string(),
+            IonValue::String(self.currency.to_string()),
+        );
+        if let Some(sender) = &self.sender_account_number {
+            params.insert(
+                "sender_account_number".to_string(),
+                IonValue::String(sender.clone()),
+            );
+        }
+        params.insert(
+            "recipient_account_number".to_string(),
+            IonValue::String(self.recipient_account_number.clone()),
+        );
+        if let Some(balance) = &self.sender_balance_before {
+            params.insert(
+                "sender_balance_before".to_string(),
+                IonValue::Decimal(balance.clone()),
+            );
+        }
+        if let Some(balance) = &self.sender_balance_after {
+            params.insert(
+                "sender_balance_after".to_string(),
+                IonValue::Decimal(balance.clone()),
+            );
+        }
+        params.insert(
+            "recipient_balance_before".to_string(),
+            IonValue::Decimal(self.recipient_balance_before.clone()),
+        );
+        params.insert(
+            "recipient_balance_after".to_string(),
+            IonValue::Decimal(self.recipient_balance_after.clone()),
+        );
+        if let Some(rate) = &self.fx_rate {
+            params.insert("fx_rate".to_string(), IonValue::Decimal(rate.clone()));
+        }
+        if let Some(amount) = &self.converted_amount {
+            params.insert(
+                "converted_amount".to_string(),
+                IonValue::Decimal(amount.clone()),
+            );
+        }
+        if let Some(currency) = &self.converted_currency {
+            params.insert(
+                "converted_currency".to_string(),
+                IonValue::String(currency.to_string()),
+            );
+        }
+        params.insert(
+            "status".to_string(),
+            IonValue::String(self.status.to_string()),
+        );
+        params.insert(
+            "created_at".to_string(),
+            IonValue::DateTime(self.created_at),
+        );
+        params
+    }
+}
+
+impl TryFrom<&Document> for Transaction {
+    type Error = AppError;
+
+    fn try_from(doc: &Document) -> Result<Self, Self::Error> {
+        let transaction_type: String = doc.get_value("transaction_type")?;
+        let status: String = doc.get_value("status")?;
+        let currency: Option<String> = doc.get_optional_value("currency")?;
+        let currency = match currency {
+            Some(code) => code.parse()?,
+            None => Currency::USD,
+        };
+        let converted_currency: Option<String> = doc.get_optional_value("converted_currency")?;
+        let converted_currency = match converted_currency {
+            Some(code) => Some(code.parse()?),
+            None => None,
+        };
+        let transaction = Transaction {
+            id: doc.get_value("id")?,
+            transaction_type: transaction_type.parse()?,
+            amount: doc.get_value("amount")?,
+            currency,
+            sender_account_number: doc.get_optional_value("sender_account_number")?,
+            recipient_account_number: doc.get_value("recipient_account_number")?,
+            sender_balance_before: doc.get_optional_value("sender_balance_before")?,
+            sender_balance_after: doc.get_optional_value("sender_balance_after")?,
+            recipient_balance_before: doc.get_value("recipient_balance_before")?,
+            recipient_balance_after: doc.get_value("recipient_balance_after")?,
+            fx_rate: doc.get_optional_value("fx_rate")?,
+            converted_amount: doc.get_optional_value("converted_amount")?,
+            converted_currency,
+            status: status.parse()?,
+            created_at: doc.get_value("created_at")?,
+        };
+        Ok(transaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(amount: &str, sender: &str, recipient: &str) -> NewTransaction {
+        NewTransaction {
+            amount: amount.parse().unwrap(),
+            currency: None,
+            sender_account_number: Some(sender.to_string()),
+            recipient_account_number: recipient.to_string(),
+            transaction_type: TransactionType::TRANSFER,
+            fx_quote_id: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_reports_every_field() {
+        assert!(transfer("10.50", "0123456789", "9876543210")
+            .validate()
+            .is_ok());
+
+        let err = transfer("0.0000001", "0123456789", "0123456789")
+            .validate()
+            .unwrap_err();
+        let fields = err.response().fields.unwrap();
+        assert_eq!(fields["amount"], "must not have more than 3 decimal places");
+        assert_eq!(
+            fields["recipient_account_number"],
+            "must not be the sender account"
+        );
+
+        let mut credit = transfer("-5", "", "9876543210");
+        credit.transaction_type = TransactionType::CREDIT;
+        credit.fx_quote_id = Some("quote".to_string());
+        let err = credit.validate().unwrap_err();
+        assert_eq!(err.message(), "Invalid amount, fx_quote_id");
+        assert!(matches!(err.error_type, ErrorType::ValidationError(_)));
+    }
 }
@@ -0,0 +1,187 @@
+use bigdecimal::BigDecimal;
+use std::collections::BTreeMap;
+
+use super::Currency;
+use crate::error::{AppError, ErrorType};
+
+/// Longest name accepted, in characters.
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_EMAIL_LENGTH: usize = 254;
+const MAX_ADDRESS_LENGTH: usize = 500;
+/// E.164 allows at most 15 digits, country code included.
+const MIN_PHONE_DIGITS: usize = 8;
+const MAX_PHONE_DIGITS: usize = 15;
+/// Largest amount one transaction can move, in any currency.
+const MAX_AMOUNT: i64 = 1_000_000_000_000;
+
+/// Collects what is wrong with a request payload, so every invalid field is
+/// reported at once. Fields of nested objects are named by their path, e.g.
+/// `customer.phone`.
+#[derive(Debug, Default)]
+pub struct Validator {
+    fields: BTreeMap<String, String>,
+}
+
+impl Validator {
+    /// Records a problem with `field`. Only the first one found is kept.
+    pub fn invalid(&mut self, field: &str, message: impl Into<String>) {
+        self.fields
+            .entry(field.to_string())
+            .or_insert_with(|| message.into());
+    }
+
+    /// Checks a name and returns it trimmed.
+    pub fn name(&mut self, field: &str, value: &str) -> String {
+        let name = value.trim();
+        if name.is_empty() {
+            self.invalid(field, "must not be empty");
+        } else if name.chars().count() > MAX_NAME_LENGTH {
+            self.invalid(
+                field,
+                format!("must be at most {} characters", MAX_NAME_LENGTH),
+            );
+        } else if name.chars().any(char::is_control) {
+            self.invalid(field, "must not contain control characters");
+        }
+        name.to_string()
+    }
+
+    /// Checks a phone number and returns it in E.164 form, e.g.
+    /// `+2347038657970`. Spaces, dashes, dots and brackets are dropped; the
+    /// number must start with its country code.
+    pub fn phone(&mut self, field: &str, value: &str) -> String {
+        let value = value.trim();
+        let digits: String = value
+            .strip_prefix('+')
+            .unwrap_or(value)
+            .chars()
+            .filter(|c| !matches!(c, ' ' | '-' | '.' | '(' | ')'))
+            .collect();
+        if !digits.chars().all(|c| c.is_ascii_digit()) {
+            self.invalid(field, "must contain only digits");
+        } else if digits.len() < MIN_PHONE_DIGITS || digits.len() > MAX_PHONE_DIGITS {
+            self.invalid(
+                field,
+                format!(
+                    "must have between {} and {} digits",
+                    MIN_PHONE_DIGITS, MAX_PHONE_DIGITS
+                ),
+            );
+        } else if digits.starts_with('0') {
+            self.invalid(field, "must start with a country code");
+        }
+        format!("+{}", digits)
+    }
+
+    /// Checks an email address and returns it trimmed.
+    pub fn email(&mut self, field: &str, value: &str) -> String {
+        let email = value.trim();
+        let valid = match email.split_once('@') {
+            Some((local, domain)) => {
+                !local.is_empty()
+                    && domain.contains('.')
+                    && !domain.starts_with('.')
+                    && !domain.ends_with('.')
+                    && !domain.contains('@')
+                    && !email.chars().any(char::is_whitespace)
+            }
+            None => false,
+        };
+        if !valid {
+            self.invalid(field, "must be an email address");
+        } else if email.len() > MAX_EMAIL_LENGTH {
+            self.invalid(
+                field,
+                format!("must be at most {} characters", MAX_EMAIL_LENGTH),
+            );
+        }
+        email.to_string()
+    }
+
+    /// Checks a postal address and returns it trimmed.
+    pub fn address(&mut self, field: &str, value: &str) -> String {
+        let address = value.trim();
+        if address.is_empty() {
+            self.invalid(field, "must not be empty");
+        } else if address.chars().count() > MAX_ADDRESS_LENGTH {
+            self.invalid(
+                field,
+                format!("must be at most {} characters", MAX_ADDRESS_LENGTH),
+            );
+        }
+        address.to_string()
+    }
+
+    /// Checks that an amount is positive, not over the largest amount a
+    /// transaction can move and not finer than the minor unit of `currency`,
+    /// or of any supported currency when it is not known yet.
+    pub fn amount(&mut self, field: &str, amount: &BigDecimal, currency: Option<Currency>) {
+        let minor_units = match currency {
+            Some(currency) => currency.minor_units(),
+            None => Currency::ALL
+                .iter()
+                .map(Currency::minor_units)
+                .max()
+                .unwrap_or_default(),
+        };
+        let (_, scale) = amount.normalized().as_bigint_and_exponent();
+        if *amount <= BigDecimal::from(0) {
+            self.invalid(field, "must be greater than 0");
+        } else if *amount > BigDecimal::from(MAX_AMOUNT) {
+            self.invalid(field, format!("must be at most {}", MAX_AMOUNT));
+        } else if scale > minor_units {
+            self.invalid(
+                field,
+                format!("must not have more than {} decimal places", minor_units),
+            );
+        }
+    }
+
+    /// Fails with every problem found, if there were any.
+    pub fn finish(self) -> Result<(), AppError> {
+        if self.fields.is_empty() {
+            return Ok(());
+        }
+        Err(AppError::from_type(ErrorType::ValidationError(self.fields)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields_are_normalized_and_reported_together() {
+        let mut validator = Validator::default();
+        assert_eq!(validator.name("name", "  Sam James "), "Sam James");
+        assert_eq!(
+            validator.phone("phone", "+234 (703) 865-7970"),
+            "+2347038657970"
+        );
+        assert_eq!(validator.phone("phone", "2347038657970"), "+2347038657970");
+        validator.amount("amount", &"10.25".parse().unwrap(), Some(Currency::USD));
+        validator.amount("amount", &"0.001".parse().unwrap(), None);
+        assert!(validator.finish().is_ok());
+
+        let mut validator = Validator::default();
+        validator.name("customer.first_name", " ");
+        validator.name("customer.last_name", &"x".repeat(10 * 1024));
+        validator.phone("customer.phone", "call me");
+        validator.phone("customer.mobile", "07038657970");
+        validator.email("customer.email", "sam@localhost");
+        validator.amount("amount", &"0.0000001".parse().unwrap(), None);
+        validator.amount("fee", &"10.001".parse().unwrap(), Some(Currency::USD));
+        validator.amount("total", &"1000000000000.01".parse().unwrap(), None);
+        let err = validator.finish().unwrap_err();
+        assert_eq!(
+            err.message(),
+            "Invalid amount, customer.email, customer.first_name, customer.last_name, \
+             customer.mobile, customer.phone, fee, total"
+        );
+        let fields = err.response().fields.unwrap();
+        assert_eq!(fields["customer.first_name"], "must not be empty");
+        assert_eq!(fields["customer.phone"], "must contain only digits");
+        assert_eq!(fields["customer.mobile"], "must start with a country code");
+        assert_eq!(fields["fee"], "must not have more than 2 decimal places");
+    }
+}
@@ -1,24 +1,40 @@
 use actix_web::{
-    error::{JsonPayloadError, ResponseError},
+    error::{JsonPayloadError, QueryPayloadError, ResponseError},
     http::StatusCode,
     HttpResponse,
 };
 use ion_binary_rs::IonParserError;
 use qldb::{QldbError, QldbExtractError};
 use serde::Serialize;
+use std::collections::BTreeMap;
 use std::fmt;
 
 #[derive(Debug)]
 pub enum ErrorType {
     Custom(String),
     AccountError(String),
-    IonError(IonParserError),
-    QldbError(QldbError),
-    QldbExtractError(QldbExtractError),
+    // Errors from the storage libraries are boxed: they are large and rare,
+    // and inline they would make every `Result<_, AppError>` as big as them.
+    IonError(Box<IonParserError>),
+    QldbError(Box<QldbError>),
+    QldbExtractError(Box<QldbExtractError>),
+    SqlError(Box<rusqlite::Error>),
     InsufficientBalance,
     AccountNotFound(String),
-    NoRowsAffected,
+    HoldNotFound(String),
+    ScheduleNotFound(String),
+    CustomerNotFound(String),
+    PendingTransactionNotFound(String),
     PayloadError,
+    /// Problems with the fields of a request payload, by field name.
+    ValidationError(BTreeMap<String, String>),
+    TransactionConflict,
+    IdempotencyKeyReused,
+    CurrencyMismatch,
+    LimitExceeded(String),
+    Unauthorized,
+    Forbidden,
+    ApiKeyNotFound(String),
 }
 
 impl fmt::Display for ErrorType {
@@ -29,9 +45,34 @@ impl fmt::Display for ErrorType {
             ErrorType::IonError(s) => write!(f, "Ion Parser Error: {}", s),
             ErrorType::QldbError(s) => write!(f, "Qldb Error: {}", s),
             ErrorType::QldbExtractError(s) => write!(f, "Qldb Extract Error: {}", s),
+            ErrorType::SqlError(s) => write!(f, "Sql Error: {}", s),
             ErrorType::InsufficientBalance => write!(f, "Insufficient balance in account"),
+            ErrorType::ValidationError(fields) => {
+                let names: Vec<&str> = fields.keys().map(String::as_str).collect();
+                write!(f, "Invalid {}", names.join(", "))
+            }
             ErrorType::AccountNotFound(s) => write!(f, "Account not found: {}", s),
-            ErrorType::NoRowsAffected => write!(f, "No rows affected"),
+            ErrorType::HoldNotFound(s) => write!(f, "Hold not found: {}", s),
+            ErrorType::ScheduleNotFound(s) => write!(f, "Schedule not found: {}", s),
+            ErrorType::CustomerNotFound(s) => write!(f, "Customer not found: {}", s),
+            ErrorType::PendingTransactionNotFound(s) => {
+                write!(f, "Pending transaction not found: {}", s)
+            }
+            ErrorType::TransactionConflict => {
+                write!(
+                    f,
+                    "Transaction conflicted with another update, please retry"
+                )
+            }
+            ErrorType::IdempotencyKeyReused => write!(
+                f,
+                "Idempotency-Key has already been used for a different request"
+            ),
+            ErrorType::CurrencyMismatch => write!(f, "Account currencies do not match"),
+            ErrorType::LimitExceeded(limit) => write!(f, "Transaction limit exceeded: {}", limit),
+            ErrorType::Unauthorized => write!(f, "Authentication required"),
+            ErrorType::Forbidden => write!(f, "Not allowed to perform this operation"),
+            ErrorType::ApiKeyNotFound(s) => write!(f, "API key not found: {}", s),
             _ => write!(f, "Unable to process request"),
         }
     }
@@ -44,8 +85,8 @@ pub struct AppError {
 }
 
 impl AppError {
-    fn message(&self) -> String {
-        match &*self {
+    pub fn message(&self) -> String {
+        match self {
             AppError {
                 message: Some(message),
                 error_type: _,
@@ -58,9 +99,19 @@ impl AppError {
     }
     fn error_type(&self) -> String {
         let error = match self.error_type {
-            ErrorType::InsufficientBalance | ErrorType::AccountNotFound(_) => "Transaction Error",
-            ErrorType::PayloadError => "Payload Error",
-            ErrorType::AccountError(_) => "Account Error",
+            ErrorType::InsufficientBalance
+            | ErrorType::AccountNotFound(_)
+            | ErrorType::HoldNotFound(_)
+            | ErrorType::ScheduleNotFound(_)
+            | ErrorType::PendingTransactionNotFound(_)
+            | ErrorType::TransactionConflict
+            | ErrorType::IdempotencyKeyReused
+            | ErrorType::CurrencyMismatch
+            | ErrorType::LimitExceeded(_) => "Transaction Error",
+            ErrorType::PayloadError | ErrorType::ValidationError(_) => "Payload Error",
+            ErrorType::AccountError(_) | ErrorType::CustomerNotFound(_) => "Account Error",
+            ErrorType::Unauthorized | ErrorType::ApiKeyNotFound(_) => "Authentication Error",
+            ErrorType::Forbidden => "Authorization Error",
             _ => "Platform Error",
         };
         error.to_string()
@@ -77,6 +128,26 @@ impl AppError {
             error_type,
         }
     }
+    pub fn response(&self) -> AppErrorResponse {
+        let fields = match &self.error_type {
+            ErrorType::ValidationError(fields) => Some(fields.clone()),
+            _ => None,
+        };
+        AppErrorResponse {
+            message: self.message(),
+            error: self.error_type(),
+            fields,
+        }
+    }
+    /// The same error, with its message saying which item of a batch (counted
+    /// from 0) it is about.
+    pub fn for_item(self, index: usize) -> Self {
+        let message = format!("Transaction {}: {}", index, self.message());
+        AppError::new(Some(message), self.error_type)
+    }
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.error_type, ErrorType::TransactionConflict)
+    }
 }
 
 impl fmt::Display for AppError {
@@ -93,19 +164,33 @@ impl From<std::str::Utf8Error> for AppError {
 
 impl From<IonParserError> for AppError {
     fn from(err: IonParserError) -> Self {
-        AppError::new(None, ErrorType::IonError(err))
+        AppError::new(None, ErrorType::IonError(Box::new(err)))
     }
 }
 
 impl From<QldbError> for AppError {
     fn from(err: QldbError) -> Self {
-        AppError::new(None, ErrorType::QldbError(err))
+        AppError::new(None, ErrorType::QldbError(Box::new(err)))
     }
 }
 
 impl From<QldbExtractError> for AppError {
     fn from(err: QldbExtractError) -> Self {
-        AppError::new(None, ErrorType::QldbExtractError(err))
+        AppError::new(None, ErrorType::QldbExtractError(Box::new(err)))
+    }
+}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(err: rusqlite::Error) -> Self {
+        match err {
+            // Also covers extended codes such as SQLITE_BUSY_SNAPSHOT, which
+            // a read transaction gets when it starts writing after another
+            // connection has committed.
+            rusqlite::Error::SqliteFailure(e, _) if e.code == rusqlite::ErrorCode::DatabaseBusy => {
+                AppError::new(None, ErrorType::TransactionConflict)
+            }
+            err => AppError::new(None, ErrorType::SqlError(Box::new(err))),
+        }
     }
 }
 
@@ -128,10 +213,23 @@ impl From<JsonPayloadError> for AppError {
     }
 }
 
-#[derive(Serialize)]
+impl From<QueryPayloadError> for AppError {
+    fn from(error: QueryPayloadError) -> Self {
+        match error {
+            QueryPayloadError::Deserialize(e) => AppError {
+                message: Some(e.to_string()),
+                error_type: ErrorType::PayloadError,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub struct AppErrorResponse {
     pub message: String,
     pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<BTreeMap<String, String>>,
 }
 
 impl ResponseError for AppError {
@@ -139,16 +237,25 @@ impl ResponseError for AppError {
         match self.error_type {
             ErrorType::InsufficientBalance => StatusCode::BAD_REQUEST,
             ErrorType::PayloadError => StatusCode::BAD_REQUEST,
+            ErrorType::ValidationError(_) => StatusCode::BAD_REQUEST,
             ErrorType::AccountNotFound(_) => StatusCode::NOT_FOUND,
+            ErrorType::HoldNotFound(_) => StatusCode::NOT_FOUND,
+            ErrorType::ScheduleNotFound(_) => StatusCode::NOT_FOUND,
+            ErrorType::CustomerNotFound(_) => StatusCode::NOT_FOUND,
+            ErrorType::PendingTransactionNotFound(_) => StatusCode::NOT_FOUND,
             ErrorType::AccountError(_) => StatusCode::BAD_REQUEST,
+            ErrorType::TransactionConflict => StatusCode::CONFLICT,
+            ErrorType::IdempotencyKeyReused => StatusCode::CONFLICT,
+            ErrorType::CurrencyMismatch => StatusCode::BAD_REQUEST,
+            ErrorType::LimitExceeded(_) => StatusCode::BAD_REQUEST,
+            ErrorType::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorType::Forbidden => StatusCode::FORBIDDEN,
+            ErrorType::ApiKeyNotFound(_) => StatusCode::NOT_FOUND,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code()).json(AppErrorResponse {
-            message: self.message(),
-            error: self.error_type(),
-        })
+        HttpResponse::build(self.status_code()).json(self.response())
     }
 }
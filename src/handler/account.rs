@@ -1,40 +1,236 @@
 use super::HandlerResult;
-use crate::domain::{AppState, NewAccount, Response};
-use crate::service::AccountService;
+use crate::domain::{
+    AccountClosure, AccountLimits, AppState, NewAccount, NewAccountOwner, OverdraftLimit,
+    Principal, Role, SigningRuleChange,
+};
+use crate::error::{AppError, ErrorType};
+use crate::service::{AccountService, ImportService, JointAccountService};
 use actix_web::{
     web::{self, Json},
     HttpResponse,
 };
 
-pub async fn get_accounts(app_state: web::Data<AppState>) -> HandlerResult {
+/// Roles that run the bank's day-to-day operations on any account.
+pub(super) const STAFF: &[Role] = &[Role::Teller, Role::Admin];
+pub(super) const ADMIN: &[Role] = &[Role::Admin];
+
+/// Customers can only look at their own accounts. Anyone else's account is
+/// reported as not found, like one that does not exist.
+pub(super) fn authorize_read(principal: &Principal, account_number: &str) -> Result<(), AppError> {
+    let not_found = ErrorType::AccountNotFound(account_number.to_string());
+    authorize_read_or(principal, account_number, not_found)
+}
+
+/// Like `authorize_read`, for a record of the account: a record of an account
+/// the customer does not hold fails with `not_found`.
+pub(super) fn authorize_read_or(
+    principal: &Principal,
+    account_number: &str,
+    not_found: ErrorType,
+) -> Result<(), AppError> {
+    if principal.can_read(account_number) {
+        Ok(())
+    } else {
+        Err(AppError::from_type(not_found))
+    }
+}
+
+/// Customers get only their own accounts.
+pub async fn get_accounts(app_state: web::Data<AppState>, principal: Principal) -> HandlerResult {
     let account_service = AccountService::new(app_state.processor.clone());
-    let accounts = account_service.find_accounts().await?;
+    let mut accounts = account_service.find_accounts().await?;
+    accounts.retain(|account| principal.can_read(&account.account_number));
     Ok(HttpResponse::Ok().json(accounts))
 }
 
-pub async fn get_account(app_state: web::Data<AppState>, path: web::Path<String>) -> HandlerResult {
+pub async fn get_account(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+) -> HandlerResult {
     let account_number = path.into_inner();
+    authorize_read(&principal, &account_number)?;
     let account_service = AccountService::new(app_state.processor.clone());
     let account = account_service.find_account(account_number).await?;
     Ok(HttpResponse::Ok().json(account))
 }
 
-pub async fn delete_account(
+/// Opens the accounts of a CSV file sent as the request body.
+pub async fn import_accounts(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    body: web::Bytes,
+) -> HandlerResult {
+    principal.require(ADMIN)?;
+    let import_service = ImportService::new(
+        app_state.processor.clone(),
+        app_state.system_accounts.clone(),
+    );
+    let report = import_service.import_accounts(body.as_ref()).await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+pub async fn freeze_account(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+) -> HandlerResult {
+    principal.require(STAFF)?;
+    let account_service = AccountService::new(app_state.processor.clone());
+    let account = account_service.freeze_account(path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(account))
+}
+
+pub async fn mark_dormant(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+) -> HandlerResult {
+    principal.require(STAFF)?;
+    let account_service = AccountService::new(app_state.processor.clone());
+    let account = account_service.mark_dormant(path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(account))
+}
+
+pub async fn unfreeze_account(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+) -> HandlerResult {
+    principal.require(STAFF)?;
+    let account_service = AccountService::new(app_state.processor.clone());
+    let account = account_service.unfreeze_account(path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(account))
+}
+
+pub async fn set_overdraft_limit(
     app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+    payload: Json<OverdraftLimit>,
+) -> HandlerResult {
+    principal.require(ADMIN)?;
+    let account_service = AccountService::new(app_state.processor.clone());
+    let account = account_service
+        .set_overdraft_limit(path.into_inner(), payload.into_inner().overdraft_limit)
+        .await?;
+    Ok(HttpResponse::Ok().json(account))
+}
+
+/// Limits that apply to the account once its tier's defaults are filled in.
+pub async fn get_limits(
+    app_state: web::Data<AppState>,
+    principal: Principal,
     path: web::Path<String>,
 ) -> HandlerResult {
     let account_number = path.into_inner();
+    authorize_read(&principal, &account_number)?;
     let account_service = AccountService::new(app_state.processor.clone());
-    account_service.delete_account(account_number).await?;
-    Ok(HttpResponse::Ok().json(Response::new("Successfully deleted accoun")))
+    let account = account_service.find_account(account_number).await?;
+    Ok(HttpResponse::Ok().json(app_state.limits.limits_for(&account)))
 }
+
+pub async fn set_limits(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+    payload: Json<AccountLimits>,
+) -> HandlerResult {
+    principal.require(ADMIN)?;
+    let account_service = AccountService::new(app_state.processor.clone());
+    let account = account_service
+        .set_limits(path.into_inner(), payload.into_inner())
+        .await?;
+    Ok(HttpResponse::Ok().json(account))
+}
+
+pub async fn close_account(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+    closure: Json<AccountClosure>,
+) -> HandlerResult {
+    principal.require(ADMIN)?;
+    let account_number = path.into_inner();
+    let system_accounts = &app_state.system_accounts;
+    system_accounts.ensure_not_system(&account_number)?;
+    if let Some(settlement_account_number) = &closure.settlement_account_number {
+        system_accounts.ensure_not_system(settlement_account_number)?;
+    }
+    let account_service = AccountService::new(app_state.processor.clone());
+    let account = account_service
+        .close_account(account_number, closure.into_inner())
+        .await?;
+    Ok(HttpResponse::Ok().json(account))
+}
+
 pub async fn create_account(
     app_state: web::Data<AppState>,
+    principal: Principal,
     new_account: Json<NewAccount>,
 ) -> HandlerResult {
+    principal.require(STAFF)?;
     let account_service = AccountService::new(app_state.processor.clone());
     let (_, account) = account_service
         .create_account(new_account.into_inner())
         .await?;
     Ok(HttpResponse::Created().json(account))
 }
+
+fn joint_service(app_state: &AppState) -> JointAccountService {
+    JointAccountService::new(app_state.processor.clone(), app_state.approval_ttl)
+}
+
+pub async fn get_owners(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+) -> HandlerResult {
+    let account_number = path.into_inner();
+    authorize_read(&principal, &account_number)?;
+    let owners = joint_service(&app_state)
+        .find_owners(account_number)
+        .await?;
+    Ok(HttpResponse::Ok().json(owners))
+}
+
+pub async fn add_owner(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+    new_owner: Json<NewAccountOwner>,
+) -> HandlerResult {
+    principal.require(STAFF)?;
+    let owners = joint_service(&app_state)
+        .add_owner(path.into_inner(), new_owner.into_inner().customer_id)
+        .await?;
+    Ok(HttpResponse::Created().json(owners))
+}
+
+pub async fn set_signing_rule(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+    payload: Json<SigningRuleChange>,
+) -> HandlerResult {
+    principal.require(STAFF)?;
+    let account_service = AccountService::new(app_state.processor.clone());
+    let account = account_service
+        .set_signing_rule(path.into_inner(), payload.into_inner().signing_rule)
+        .await?;
+    Ok(HttpResponse::Ok().json(account))
+}
+
+/// Transfers out of the account that wait, or waited, for its owners.
+pub async fn get_pending_transactions(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+) -> HandlerResult {
+    let account_number = path.into_inner();
+    authorize_read(&principal, &account_number)?;
+    let pending = joint_service(&app_state)
+        .find_pending_for(account_number)
+        .await?;
+    Ok(HttpResponse::Ok().json(pending))
+}
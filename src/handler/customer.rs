@@ -0,0 +1,90 @@
+use super::account::{ADMIN, STAFF};
+use super::HandlerResult;
+use crate::domain::{AppState, CustomerUpdate, NewCustomer, Principal, Role};
+use crate::error::{AppError, ErrorType};
+use crate::service::CustomerService;
+use actix_web::{
+    web::{self, Json},
+    HttpResponse,
+};
+
+/// Customers can only look at their own record; anyone else's is reported as
+/// not found, like one that does not exist.
+fn authorize_read(principal: &Principal, customer_id: &str) -> Result<(), AppError> {
+    if principal.role() != Role::Customer || principal.is_customer(customer_id) {
+        Ok(())
+    } else {
+        Err(AppError::from_type(ErrorType::CustomerNotFound(
+            customer_id.to_string(),
+        )))
+    }
+}
+
+pub async fn create_customer(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    new_customer: Json<NewCustomer>,
+) -> HandlerResult {
+    principal.require(STAFF)?;
+    let customer_service = CustomerService::new(app_state.processor.clone());
+    let customer = customer_service
+        .create_customer(new_customer.into_inner())
+        .await?;
+    Ok(HttpResponse::Created().json(customer))
+}
+
+pub async fn get_customers(app_state: web::Data<AppState>, principal: Principal) -> HandlerResult {
+    principal.require(&[Role::Teller, Role::Admin, Role::Auditor])?;
+    let customer_service = CustomerService::new(app_state.processor.clone());
+    let customers = customer_service.find_customers().await?;
+    Ok(HttpResponse::Ok().json(customers))
+}
+
+pub async fn get_customer(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+) -> HandlerResult {
+    let customer_id = path.into_inner();
+    authorize_read(&principal, &customer_id)?;
+    let customer_service = CustomerService::new(app_state.processor.clone());
+    let customer = customer_service.find_customer(customer_id).await?;
+    Ok(HttpResponse::Ok().json(customer))
+}
+
+pub async fn update_customer(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+    update: Json<CustomerUpdate>,
+) -> HandlerResult {
+    principal.require(STAFF)?;
+    let customer_service = CustomerService::new(app_state.processor.clone());
+    let customer = customer_service
+        .update_customer(path.into_inner(), update.into_inner())
+        .await?;
+    Ok(HttpResponse::Ok().json(customer))
+}
+
+pub async fn delete_customer(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+) -> HandlerResult {
+    principal.require(ADMIN)?;
+    let customer_service = CustomerService::new(app_state.processor.clone());
+    let customer = customer_service.delete_customer(path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(customer))
+}
+
+pub async fn get_customer_accounts(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+) -> HandlerResult {
+    let customer_id = path.into_inner();
+    authorize_read(&principal, &customer_id)?;
+    let customer_service = CustomerService::new(app_state.processor.clone());
+    let accounts = customer_service.find_accounts(customer_id).await?;
+    Ok(HttpResponse::Ok().json(accounts))
+}
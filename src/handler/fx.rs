@@ -0,0 +1,23 @@
+use super::HandlerResult;
+use crate::domain::{AppState, NewFxQuote, Principal, Role};
+use crate::service::FxService;
+use actix_web::{
+    web::{self, Json},
+    HttpResponse,
+};
+
+pub async fn create_quote(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    new_quote: Json<NewFxQuote>,
+) -> HandlerResult {
+    // Auditors only read; anyone who can send a transfer can get a quote.
+    principal.require(&[Role::Customer, Role::Teller, Role::Admin])?;
+    let fx_service = FxService::new(
+        app_state.processor.clone(),
+        app_state.rates.clone(),
+        app_state.fx_quote_ttl,
+    );
+    let quote = fx_service.create_quote(new_quote.into_inner()).await?;
+    Ok(HttpResponse::Created().json(quote))
+}
@@ -0,0 +1,68 @@
+use super::account::{authorize_read, STAFF};
+use super::HandlerResult;
+use crate::domain::{AppState, HoldCapture, NewHold, Principal};
+use crate::service::HoldService;
+use actix_web::{
+    web::{self, Json},
+    HttpResponse,
+};
+
+fn hold_service(app_state: &AppState) -> HoldService {
+    HoldService::new(
+        app_state.processor.clone(),
+        app_state.system_accounts.clone(),
+        app_state.hold_ttl,
+        app_state.limits.clone(),
+    )
+}
+
+pub async fn place_hold(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+    new_hold: Json<NewHold>,
+) -> HandlerResult {
+    principal.require(STAFF)?;
+    let hold = hold_service(&app_state)
+        .place_hold(path.into_inner(), new_hold.into_inner())
+        .await?;
+    Ok(HttpResponse::Created().json(hold))
+}
+
+pub async fn get_holds(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+) -> HandlerResult {
+    let account_number = path.into_inner();
+    authorize_read(&principal, &account_number)?;
+    let holds = hold_service(&app_state).find_holds(account_number).await?;
+    Ok(HttpResponse::Ok().json(holds))
+}
+
+pub async fn capture_hold(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<(String, String)>,
+    capture: Json<HoldCapture>,
+) -> HandlerResult {
+    principal.require(STAFF)?;
+    let (account_number, hold_id) = path.into_inner();
+    let captured = hold_service(&app_state)
+        .capture_hold(account_number, hold_id, capture.into_inner())
+        .await?;
+    Ok(HttpResponse::Ok().json(captured))
+}
+
+pub async fn release_hold(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<(String, String)>,
+) -> HandlerResult {
+    principal.require(STAFF)?;
+    let (account_number, hold_id) = path.into_inner();
+    let hold = hold_service(&app_state)
+        .release_hold(account_number, hold_id)
+        .await?;
+    Ok(HttpResponse::Ok().json(hold))
+}
@@ -1,5 +1,20 @@
 pub mod account;
+pub mod customer;
+pub mod fx;
+pub mod hold;
+pub mod schedule;
 pub mod transaction;
-use crate::error::AppError;
+use crate::error::{AppError, ErrorType};
 use actix_web::HttpResponse;
 pub type HandlerResult = Result<HttpResponse, AppError>;
+
+/// Reports a record the caller may not see as not found, so the answer does
+/// not tell the caller whether someone else's record exists.
+fn hide_forbidden<T>(result: Result<T, AppError>, not_found: ErrorType) -> Result<T, AppError> {
+    match result {
+        Err(e) if matches!(e.error_type, ErrorType::Forbidden) => {
+            Err(AppError::from_type(not_found))
+        }
+        result => result,
+    }
+}
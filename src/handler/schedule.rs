@@ -0,0 +1,125 @@
+use super::account::authorize_read_or;
+use super::transaction::{authorize_transfer_from, needs_approval};
+use super::HandlerResult;
+use crate::domain::{AppState, NewSchedule, Principal, Schedule, ScheduleQuery};
+use crate::error::{AppError, ErrorType};
+use crate::service::ScheduleService;
+use actix_web::{
+    web::{self, Json},
+    HttpResponse,
+};
+
+pub async fn create_schedule(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    new_schedule: Json<NewSchedule>,
+) -> HandlerResult {
+    authorize_transfer_from(&principal, &new_schedule.sender_account_number)?;
+    let system_accounts = &app_state.system_accounts;
+    system_accounts.ensure_not_system(&new_schedule.sender_account_number)?;
+    system_accounts.ensure_not_system(&new_schedule.recipient_account_number)?;
+    if needs_approval(&app_state, &new_schedule.sender_account_number).await? {
+        let msg = format!(
+            "Transfers from account {} need the approval of all its owners and cannot be scheduled",
+            new_schedule.sender_account_number
+        );
+        return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+    }
+    let schedule_service = ScheduleService::new(app_state.processor.clone());
+    let schedule = schedule_service
+        .create_schedule(new_schedule.into_inner())
+        .await?;
+    Ok(HttpResponse::Created().json(schedule))
+}
+
+/// Customers get only the schedules sent from their own accounts.
+pub async fn get_schedules(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    query: web::Query<ScheduleQuery>,
+) -> HandlerResult {
+    let schedule_service = ScheduleService::new(app_state.processor.clone());
+    let mut schedules = schedule_service.find_schedules(query.into_inner()).await?;
+    schedules.retain(|schedule| principal.can_read(&schedule.sender_account_number));
+    Ok(HttpResponse::Ok().json(schedules))
+}
+
+/// Loads a schedule the caller may read. Other customers' schedules are
+/// reported as not found, like schedules that do not exist.
+async fn find_readable(
+    schedule_service: &ScheduleService,
+    principal: &Principal,
+    id: &str,
+) -> Result<Schedule, AppError> {
+    let schedule = schedule_service.find_schedule(id.to_string()).await?;
+    authorize_read_or(
+        principal,
+        &schedule.sender_account_number,
+        ErrorType::ScheduleNotFound(id.to_string()),
+    )?;
+    Ok(schedule)
+}
+
+pub async fn get_schedule(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+) -> HandlerResult {
+    let schedule_service = ScheduleService::new(app_state.processor.clone());
+    let schedule = find_readable(&schedule_service, &principal, &path).await?;
+    Ok(HttpResponse::Ok().json(schedule))
+}
+
+pub async fn get_schedule_runs(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+) -> HandlerResult {
+    let schedule_service = ScheduleService::new(app_state.processor.clone());
+    let schedule = find_readable(&schedule_service, &principal, &path).await?;
+    let runs = schedule_service.find_runs(schedule.id).await?;
+    Ok(HttpResponse::Ok().json(runs))
+}
+
+/// Changing a schedule needs the same rights as sending its transfer.
+async fn authorize_change(
+    schedule_service: &ScheduleService,
+    principal: &Principal,
+    id: &str,
+) -> Result<(), AppError> {
+    let schedule = find_readable(schedule_service, principal, id).await?;
+    authorize_transfer_from(principal, &schedule.sender_account_number)
+}
+
+pub async fn pause_schedule(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+) -> HandlerResult {
+    let schedule_service = ScheduleService::new(app_state.processor.clone());
+    authorize_change(&schedule_service, &principal, &path).await?;
+    let schedule = schedule_service.pause_schedule(path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(schedule))
+}
+
+pub async fn resume_schedule(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+) -> HandlerResult {
+    let schedule_service = ScheduleService::new(app_state.processor.clone());
+    authorize_change(&schedule_service, &principal, &path).await?;
+    let schedule = schedule_service.resume_schedule(path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(schedule))
+}
+
+pub async fn cancel_schedule(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+) -> HandlerResult {
+    let schedule_service = ScheduleService::new(app_state.processor.clone());
+    authorize_change(&schedule_service, &principal, &path).await?;
+    let schedule = schedule_service.cancel_schedule(path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(schedule))
+}
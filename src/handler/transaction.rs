@@ -1,40 +1,200 @@
-use super::HandlerResult;
-use crate::domain::NewTransaction;
-use crate::domain::{AppState, Response, TransactionType};
+use super::account::{authorize_read, authorize_read_or, STAFF};
+use super::{hide_forbidden, HandlerResult};
+use crate::domain::{Account, AppState, Currency, IdempotencyKey};
+use crate::domain::{BatchItemResult, BatchMode, BatchResult, NewBatch, Principal, Role};
+use crate::domain::{NewTransaction, Posting, TransactionQuery, TransactionReceipt};
+use crate::domain::{PendingTransaction, TransactionType};
+use crate::domain::{StatementFormat, StatementQuery};
 use crate::error::{AppError, ErrorType};
-use crate::service::{AccountService, TransferService};
+use crate::service::{
+    AccountService, FxService, JointAccountService, TransactionService, TransferService,
+};
 use actix_web::{
     web::{self, Json},
-    HttpResponse,
+    HttpRequest, HttpResponse,
 };
 use bigdecimal::BigDecimal;
 
-pub async fn handle_transaction(
-    app_state: web::Data<AppState>,
-    new_transaction: Json<NewTransaction>,
-) -> HandlerResult {
-    let transaction = new_transaction.into_inner();
-    let amount = transaction.amount;
-    let zero: BigDecimal = 0u32.into();
-    if amount <= zero {
-        return Err(AppError::new(
-            Some("Invalid transaction amount".to_string()),
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Reads the optional `Idempotency-Key` header.
+fn idempotency_header(req: &HttpRequest) -> Result<Option<String>, AppError> {
+    let header = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+    let key = header.to_str().map_err(|_| {
+        AppError::new(
+            Some("Idempotency-Key must be visible ASCII".to_string()),
             ErrorType::PayloadError,
-        ));
+        )
+    })?;
+    Ok(Some(key.to_string()))
+}
+
+/// Ties the header key to the principal that sent it and the request payload.
+fn idempotency_key(
+    principal: &Principal,
+    header: Option<String>,
+    transaction: &NewTransaction,
+) -> Result<Option<IdempotencyKey>, AppError> {
+    match header {
+        Some(key) => IdempotencyKey::new(
+            principal.idempotency_scope(),
+            key,
+            transaction.fingerprint(),
+        )
+        .map(Some),
+        None => Ok(None),
+    }
+}
+
+/// The amount must be in the currency of the account it is taken from (or
+/// paid into) and must not be finer than that currency's minor unit.
+fn check_currency(
+    account: &Account,
+    amount: &BigDecimal,
+    currency: Option<Currency>,
+) -> Result<(), AppError> {
+    if let Some(currency) = currency {
+        if currency != account.currency {
+            let msg = format!(
+                "Amount is in {} but account {} holds {}",
+                currency, account.account_number, account.currency
+            );
+            return Err(AppError::new(Some(msg), ErrorType::CurrencyMismatch));
+        }
     }
+    if !account.currency.fits(amount) {
+        let msg = format!(
+            "{} amounts cannot have more than {} decimal places",
+            account.currency,
+            account.currency.minor_units()
+        );
+        return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+    }
+    Ok(())
+}
+
+/// Tellers and admins move money in and out of any account; customers can
+/// only send transfers from their own.
+pub(super) fn authorize_transfer_from(
+    principal: &Principal,
+    sender_account_number: &str,
+) -> Result<(), AppError> {
+    if principal.role() == Role::Customer {
+        authorize_read(principal, sender_account_number)
+    } else {
+        principal.require(STAFF)
+    }
+}
+
+/// Checked before anything else, so a caller cannot replay a receipt it
+/// would not be allowed to create.
+fn authorize_transaction(
+    principal: &Principal,
+    transaction: &NewTransaction,
+) -> Result<(), AppError> {
+    match (
+        &transaction.transaction_type,
+        &transaction.sender_account_number,
+    ) {
+        (TransactionType::TRANSFER, Some(sender_account_number)) => {
+            authorize_transfer_from(principal, sender_account_number)
+        }
+        _ => principal.require(STAFF),
+    }
+}
+
+fn transfer_service(app_state: &AppState) -> TransferService {
+    let fx_service = FxService::new(
+        app_state.processor.clone(),
+        app_state.rates.clone(),
+        app_state.fx_quote_ttl,
+    );
+    TransferService::new(
+        app_state.processor.clone(),
+        app_state.system_accounts.clone(),
+        fx_service,
+        app_state.limits.clone(),
+    )
+}
 
-    if transaction.transaction_type == TransactionType::TRANSFER
-        && transaction.sender_account_number.clone().is_none()
+fn joint_service(app_state: &AppState) -> JointAccountService {
+    JointAccountService::new(app_state.processor.clone(), app_state.approval_ttl)
+}
+
+/// Whether transfers from the account wait for all of its owners. A missing
+/// account is left for the transaction checks to report.
+pub(super) async fn needs_approval(
+    app_state: &AppState,
+    sender_account_number: &str,
+) -> Result<bool, AppError> {
+    match joint_service(app_state)
+        .needs_approval(sender_account_number.to_string())
+        .await
     {
-        return Err(AppError::new(
-            Some("sender_account_number cannot be empty for transfer".to_string()),
+        Err(AppError {
+            error_type: ErrorType::AccountNotFound(_),
+            ..
+        }) => Ok(false),
+        result => result,
+    }
+}
+
+/// The sender of a transfer that waits for approval, if this is one.
+async fn approval_sender(
+    app_state: &AppState,
+    transaction: &NewTransaction,
+) -> Result<Option<String>, AppError> {
+    match (
+        &transaction.transaction_type,
+        &transaction.sender_account_number,
+    ) {
+        (TransactionType::TRANSFER, Some(sender_account_number))
+            if needs_approval(app_state, sender_account_number).await? =>
+        {
+            Ok(Some(sender_account_number.clone()))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Transfers that wait for approval are made one request at a time.
+async fn refuse_in_batch(
+    app_state: &AppState,
+    transaction: &NewTransaction,
+) -> Result<(), AppError> {
+    match approval_sender(app_state, transaction).await? {
+        Some(sender_account_number) => Err(AppError::new(
+            Some(format!(
+                "Transfers from account {} need the approval of all its owners and must be sent on their own",
+                sender_account_number
+            )),
             ErrorType::PayloadError,
-        ));
+        )),
+        None => Ok(()),
     }
+}
 
+/// Checks a transaction request and returns the posting that carries it out,
+/// without writing anything. Limits are checked when the posting is made.
+/// System accounts can be on neither side of a request.
+async fn check_transaction(
+    app_state: &AppState,
+    transfer_service: &TransferService,
+    transaction: NewTransaction,
+) -> Result<Posting, AppError> {
+    transaction.validate()?;
+    let system_accounts = &app_state.system_accounts;
+    system_accounts.ensure_not_system(&transaction.recipient_account_number)?;
+    if let Some(sender_account_number) = &transaction.sender_account_number {
+        system_accounts.ensure_not_system(sender_account_number)?;
+    }
+    let amount = transaction.amount;
     let recipient_account_number = transaction.recipient_account_number;
     let account_service = AccountService::new(app_state.processor.clone());
-    account_service
+    let recipient = account_service
         .find_account(recipient_account_number.clone())
         .await
         .map_err(|e| match e.error_type {
@@ -45,21 +205,20 @@ pub async fn handle_transaction(
             _ => e,
         })?;
 
-    let transfer_service = TransferService::new(app_state.processor.clone());
-    let message = match transaction.transaction_type {
+    match transaction.transaction_type {
         crate::domain::TransactionType::CREDIT => {
-            transfer_service
-                .credit(recipient_account_number.clone(), amount)
-                .await?
+            check_currency(&recipient, &amount, transaction.currency)?;
+            recipient.ensure_can_receive()?;
+            Ok(transfer_service.credit_posting(&recipient, amount))
         }
         crate::domain::TransactionType::DEBIT => {
-            transfer_service
-                .debit(recipient_account_number.clone(), amount)
-                .await?
+            check_currency(&recipient, &amount, transaction.currency)?;
+            recipient.ensure_can_send()?;
+            Ok(transfer_service.debit_posting(&recipient, amount))
         }
         crate::domain::TransactionType::TRANSFER => {
             let sender_account_number = transaction.sender_account_number.unwrap();
-            account_service
+            let sender = account_service
                 .find_account(sender_account_number.clone())
                 .await
                 .map_err(|e| match e.error_type {
@@ -69,11 +228,260 @@ pub async fn handle_transaction(
                     ),
                     _ => e,
                 })?;
+            check_currency(&sender, &amount, transaction.currency)?;
+            sender.ensure_can_send()?;
+            recipient.ensure_can_receive()?;
             transfer_service
-                .transfer(sender_account_number, recipient_account_number, amount)
-                .await?
+                .transfer_posting(&sender, &recipient, amount, transaction.fx_quote_id)
+                .await
+        }
+    }
+}
+
+pub async fn handle_transaction(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    new_transaction: Json<NewTransaction>,
+) -> HandlerResult {
+    let transaction = new_transaction.into_inner();
+    authorize_transaction(&principal, &transaction)?;
+    let idempotency_key = idempotency_key(&principal, idempotency_header(&req)?, &transaction)?;
+
+    // A retried request gets its original receipt, even if it would now be
+    // over a daily limit because of itself.
+    if let Some(key) = &idempotency_key {
+        if let Some(receipt) = app_state.processor.replay(key.clone()).await? {
+            return Ok(HttpResponse::Ok().json(receipt));
+        }
+    }
+
+    let transfer_service = transfer_service(&app_state);
+    // Transfers from an account all of whose owners must sign are checked
+    // now and made once they have approved.
+    if approval_sender(&app_state, &transaction).await?.is_some() {
+        check_transaction(&app_state, &transfer_service, transaction.clone()).await?;
+        let requested_by = principal.customer_id().map(str::to_string);
+        let pending = joint_service(&app_state)
+            .request(&transaction, idempotency_key, requested_by)
+            .await?;
+        return Ok(HttpResponse::Accepted().json(pending));
+    }
+    let posting = check_transaction(&app_state, &transfer_service, transaction).await?;
+    let receipt = transfer_service.post(posting, idempotency_key).await?;
+    Ok(HttpResponse::Ok().json(receipt))
+}
+
+pub async fn get_pending_transaction(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+) -> HandlerResult {
+    let id = path.into_inner();
+    let pending = joint_service(&app_state).find_pending(id.clone()).await?;
+    authorize_read_or(
+        &principal,
+        &pending.sender_account_number,
+        ErrorType::PendingTransactionNotFound(id),
+    )?;
+    Ok(HttpResponse::Ok().json(pending))
+}
+
+/// Records an owner's approval of a pending transfer and makes the transfer
+/// once every owner has approved. Owners approve as themselves, with a
+/// customer token; staff cannot approve on an owner's behalf.
+pub async fn approve_pending_transaction(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+) -> HandlerResult {
+    let customer_id = match principal.customer_id() {
+        Some(customer_id) => customer_id.to_string(),
+        None => {
+            return Err(AppError::new(
+                Some("Pending transfers are approved by the account's holders".to_string()),
+                ErrorType::Forbidden,
+            ))
         }
     };
+    let id = path.into_inner();
+    let joint_service = joint_service(&app_state);
+    let approval = joint_service.approve(id.clone(), customer_id).await;
+    // A customer who does not hold the account cannot see the transfer.
+    let (pending, approved) = hide_forbidden(approval, ErrorType::PendingTransactionNotFound(id))?;
+    if !approved {
+        return Ok(HttpResponse::Ok().json(pending));
+    }
+    match execute_pending(&app_state, &pending).await {
+        // A conflict leaves the transfer pending; approving again retries it.
+        Err(e) if e.is_retryable() => Err(e),
+        outcome => {
+            let pending = joint_service.record_outcome(pending.id, &outcome).await?;
+            Ok(HttpResponse::Ok().json(pending))
+        }
+    }
+}
+
+/// Makes an approved transfer with the checks of a new one. It is posted
+/// under the pending transaction's key, so of approvals racing to be the
+/// last only one moves the money.
+async fn execute_pending(
+    app_state: &AppState,
+    pending: &PendingTransaction,
+) -> Result<TransactionReceipt, AppError> {
+    let key = pending.execution_key()?;
+    if let Some(receipt) = app_state.processor.replay(key.clone()).await? {
+        return Ok(receipt);
+    }
+    let transfer_service = transfer_service(app_state);
+    let posting = check_transaction(app_state, &transfer_service, pending.transaction()).await?;
+    transfer_service.post(posting, Some(key)).await
+}
 
-    Ok(HttpResponse::Ok().json(Response::new(message)))
+/// Posts many transactions at once. Items get the checks of a single
+/// transaction; with an `Idempotency-Key` header, item `i` is keyed as
+/// `<key>:<i>`.
+pub async fn handle_batch(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    new_batch: Json<NewBatch>,
+) -> HandlerResult {
+    let batch = new_batch.into_inner();
+    batch.check()?;
+    let header = idempotency_header(&req)?;
+    let mut items = Vec::new();
+    for (index, transaction) in batch.transactions.into_iter().enumerate() {
+        let key = header.as_ref().map(|key| format!("{}:{}", key, index));
+        let idempotency_key =
+            idempotency_key(&principal, key, &transaction).map_err(|e| e.for_item(index))?;
+        items.push((transaction, idempotency_key));
+    }
+
+    let transfer_service = transfer_service(&app_state);
+    let results = match batch.mode {
+        BatchMode::Atomic => atomic_batch(&app_state, &transfer_service, &principal, items).await?,
+        BatchMode::BestEffort => {
+            let mut results = Vec::new();
+            for (index, (transaction, idempotency_key)) in items.into_iter().enumerate() {
+                let result = best_effort_item(
+                    &app_state,
+                    &transfer_service,
+                    &principal,
+                    transaction,
+                    idempotency_key,
+                )
+                .await;
+                results.push(BatchItemResult::new(index, result));
+            }
+            results
+        }
+    };
+    Ok(HttpResponse::Ok().json(BatchResult::new(batch.mode, results)))
+}
+
+async fn best_effort_item(
+    app_state: &AppState,
+    transfer_service: &TransferService,
+    principal: &Principal,
+    transaction: NewTransaction,
+    idempotency_key: Option<IdempotencyKey>,
+) -> Result<TransactionReceipt, AppError> {
+    authorize_transaction(principal, &transaction)?;
+    if let Some(key) = &idempotency_key {
+        if let Some(receipt) = app_state.processor.replay(key.clone()).await? {
+            return Ok(receipt);
+        }
+    }
+    refuse_in_batch(app_state, &transaction).await?;
+    let posting = check_transaction(app_state, transfer_service, transaction).await?;
+    transfer_service.post(posting, idempotency_key).await
+}
+
+/// Checks every item, then posts them all in one ledger transaction, each
+/// against the limits left by the ones before it. A retried batch that was
+/// committed gets its original receipts.
+async fn atomic_batch(
+    app_state: &AppState,
+    transfer_service: &TransferService,
+    principal: &Principal,
+    items: Vec<(NewTransaction, Option<IdempotencyKey>)>,
+) -> Result<Vec<BatchItemResult>, AppError> {
+    for (index, (transaction, _)) in items.iter().enumerate() {
+        authorize_transaction(principal, transaction).map_err(|e| e.for_item(index))?;
+    }
+    let mut replayed = Vec::new();
+    for (_, idempotency_key) in &items {
+        if let Some(key) = idempotency_key {
+            if let Some(receipt) = app_state.processor.replay(key.clone()).await? {
+                replayed.push(receipt);
+            }
+        }
+    }
+    // An atomic batch is committed as a whole, so only some of its items
+    // having been seen means the key was used for a different batch.
+    if !replayed.is_empty() && replayed.len() < items.len() {
+        return Err(AppError::from_type(ErrorType::IdempotencyKeyReused));
+    }
+    let receipts = if replayed.is_empty() {
+        let mut postings: Vec<(Posting, Option<IdempotencyKey>)> = Vec::new();
+        for (index, (transaction, idempotency_key)) in items.into_iter().enumerate() {
+            refuse_in_batch(app_state, &transaction)
+                .await
+                .map_err(|e| e.for_item(index))?;
+            let posting = check_transaction(app_state, transfer_service, transaction)
+                .await
+                .map_err(|e| e.for_item(index))?;
+            postings.push((posting, idempotency_key));
+        }
+        transfer_service.post_all(postings).await?
+    } else {
+        replayed
+    };
+    Ok(receipts
+        .into_iter()
+        .enumerate()
+        .map(|(index, receipt)| BatchItemResult::new(index, Ok(receipt)))
+        .collect())
+}
+
+pub async fn get_account_transactions(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+    query: web::Query<TransactionQuery>,
+) -> HandlerResult {
+    let account_number = path.into_inner();
+    authorize_read(&principal, &account_number)?;
+    let account_service = AccountService::new(app_state.processor.clone());
+    account_service.find_account(account_number.clone()).await?;
+
+    let transaction_service = TransactionService::new(app_state.processor.clone());
+    let page = transaction_service
+        .find_transactions(account_number, query.into_inner())
+        .await?;
+    Ok(HttpResponse::Ok().json(page))
+}
+
+pub async fn get_account_statement(
+    app_state: web::Data<AppState>,
+    principal: Principal,
+    path: web::Path<String>,
+    query: web::Query<StatementQuery>,
+) -> HandlerResult {
+    let account_number = path.into_inner();
+    authorize_read(&principal, &account_number)?;
+    let transaction_service = TransactionService::new(app_state.processor.clone());
+    let statement = transaction_service
+        .statement(account_number, &query)
+        .await?;
+    match query.format {
+        StatementFormat::Json => Ok(HttpResponse::Ok().json(statement)),
+        StatementFormat::Csv => Ok(HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .body(statement.to_csv()?)),
+        StatementFormat::Text => Ok(HttpResponse::Ok()
+            .content_type("text/plain; charset=utf-8")
+            .body(statement.to_text())),
+    }
 }
@@ -1,19 +1,28 @@
 #[macro_use]
 extern crate log;
 
+mod cli;
 mod core;
 mod domain;
 mod error;
 mod handler;
+mod middleware;
 mod service;
 mod util;
 
-use domain::{AppState, NewAccount, NewTransaction};
+use domain::{
+    AccountClosure, AccountLimits, AppState, CustomerUpdate, HoldCapture, NewAccount,
+    NewAccountOwner, NewBatch, NewCustomer, NewFxQuote, NewHold, NewSchedule, NewTransaction,
+    OverdraftLimit, ScheduleQuery, SigningRuleChange, StatementQuery, TransactionQuery,
+    MAX_BATCH_BYTES, MAX_IMPORT_BYTES,
+};
 use error::AppError;
 use util::Config;
 
 use actix_web::{web, App, FromRequest, HttpServer};
 use dotenv::dotenv;
+use middleware::Authentication;
+use service::{AuthService, FxService, JointAccountService, Scheduler, TransferService};
 use std::process;
 
 #[actix_web::main]
@@ -29,8 +38,39 @@ async fn main() -> std::io::Result<()> {
     let app_state = AppState::new(config.clone()).await.unwrap();
     let server_port = config.server_port;
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        process::exit(cli::run(&app_state, &args).await);
+    }
+
+    let fx_service = FxService::new(
+        app_state.processor.clone(),
+        app_state.rates.clone(),
+        app_state.fx_quote_ttl,
+    );
+    let scheduler = Scheduler::new(
+        app_state.processor.clone(),
+        TransferService::new(
+            app_state.processor.clone(),
+            app_state.system_accounts.clone(),
+            fx_service,
+            app_state.limits.clone(),
+        ),
+        JointAccountService::new(app_state.processor.clone(), app_state.approval_ttl),
+        app_state.schedule_retry,
+    );
+    let schedule_poll = std::time::Duration::from_secs(config.schedule_poll_seconds.max(1));
+    actix_web::rt::spawn(scheduler.run(schedule_poll));
+
+    let auth_service = AuthService::new(app_state.processor.clone(), app_state.jwt.clone());
+    let auth_enabled = app_state.auth_enabled;
+
     let server = HttpServer::new(move || {
         App::new()
+            .wrap(actix_web::middleware::Condition::new(
+                auth_enabled,
+                Authentication::new(auth_service.clone()),
+            ))
             .wrap(actix_web::middleware::Logger::new(
                 r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %D"#,
             ))
@@ -48,10 +88,110 @@ async fn main() -> std::io::Result<()> {
                                     .route(web::get().to(handler::account::get_accounts))
                                     .route(web::post().to(handler::account::create_account)),
                             )
+                            .service(
+                                web::resource("/import")
+                                    .app_data(web::PayloadConfig::new(MAX_IMPORT_BYTES))
+                                    .route(web::post().to(handler::account::import_accounts)),
+                            )
                             .service(
                                 web::resource("/{account_number}")
+                                    .app_data(web::Json::<AccountClosure>::configure(|cfg| {
+                                        cfg.error_handler(|err, _req| AppError::from(err).into())
+                                    }))
                                     .route(web::get().to(handler::account::get_account))
-                                    .route(web::delete().to(handler::account::delete_account)),
+                                    .route(web::delete().to(handler::account::close_account)),
+                            )
+                            .service(
+                                web::resource("/{account_number}/freeze")
+                                    .route(web::post().to(handler::account::freeze_account)),
+                            )
+                            .service(
+                                web::resource("/{account_number}/dormant")
+                                    .route(web::post().to(handler::account::mark_dormant)),
+                            )
+                            .service(
+                                web::resource("/{account_number}/unfreeze")
+                                    .route(web::post().to(handler::account::unfreeze_account)),
+                            )
+                            .service(
+                                web::resource("/{account_number}/holds")
+                                    .app_data(web::Json::<NewHold>::configure(|cfg| {
+                                        cfg.error_handler(|err, _req| AppError::from(err).into())
+                                    }))
+                                    .route(web::get().to(handler::hold::get_holds))
+                                    .route(web::post().to(handler::hold::place_hold)),
+                            )
+                            .service(
+                                web::resource("/{account_number}/holds/{hold_id}/capture")
+                                    .app_data(web::Json::<HoldCapture>::configure(|cfg| {
+                                        cfg.error_handler(|err, _req| AppError::from(err).into())
+                                    }))
+                                    .route(web::post().to(handler::hold::capture_hold)),
+                            )
+                            .service(
+                                web::resource("/{account_number}/holds/{hold_id}/release")
+                                    .route(web::post().to(handler::hold::release_hold)),
+                            )
+                            .service(
+                                web::resource("/{account_number}/overdraft")
+                                    .app_data(web::Json::<OverdraftLimit>::configure(|cfg| {
+                                        cfg.error_handler(|err, _req| AppError::from(err).into())
+                                    }))
+                                    .route(web::put().to(handler::account::set_overdraft_limit)),
+                            )
+                            .service(
+                                web::resource("/{account_number}/limits")
+                                    .app_data(web::Json::<AccountLimits>::configure(|cfg| {
+                                        cfg.error_handler(|err, _req| AppError::from(err).into())
+                                    }))
+                                    .route(web::get().to(handler::account::get_limits))
+                                    .route(web::put().to(handler::account::set_limits)),
+                            )
+                            .service(
+                                web::resource("/{account_number}/close")
+                                    .app_data(web::Json::<AccountClosure>::configure(|cfg| {
+                                        cfg.error_handler(|err, _req| AppError::from(err).into())
+                                    }))
+                                    .route(web::post().to(handler::account::close_account)),
+                            )
+                            .service(
+                                web::resource("/{account_number}/owners")
+                                    .app_data(web::Json::<NewAccountOwner>::configure(|cfg| {
+                                        cfg.error_handler(|err, _req| AppError::from(err).into())
+                                    }))
+                                    .route(web::get().to(handler::account::get_owners))
+                                    .route(web::post().to(handler::account::add_owner)),
+                            )
+                            .service(
+                                web::resource("/{account_number}/signing_rule")
+                                    .app_data(web::Json::<SigningRuleChange>::configure(|cfg| {
+                                        cfg.error_handler(|err, _req| AppError::from(err).into())
+                                    }))
+                                    .route(web::put().to(handler::account::set_signing_rule)),
+                            )
+                            .service(
+                                web::resource("/{account_number}/pending").route(
+                                    web::get().to(handler::account::get_pending_transactions),
+                                ),
+                            )
+                            .service(
+                                web::resource("/{account_number}/statement")
+                                    .app_data(web::Query::<StatementQuery>::configure(|cfg| {
+                                        cfg.error_handler(|err, _req| AppError::from(err).into())
+                                    }))
+                                    .route(
+                                        web::get().to(handler::transaction::get_account_statement),
+                                    ),
+                            )
+                            .service(
+                                web::resource("/{account_number}/transactions")
+                                    .app_data(web::Query::<TransactionQuery>::configure(|cfg| {
+                                        cfg.error_handler(|err, _req| AppError::from(err).into())
+                                    }))
+                                    .route(
+                                        web::get()
+                                            .to(handler::transaction::get_account_transactions),
+                                    ),
                             ),
                     )
                     .service(
@@ -62,6 +202,90 @@ async fn main() -> std::io::Result<()> {
                                 }))
                                 .route(web::post().to(handler::transaction::handle_transaction)),
                         ),
+                    )
+                    .service(
+                        web::scope("/transactions")
+                            .service(
+                                web::resource("/batch")
+                                    .app_data(web::Json::<NewBatch>::configure(|cfg| {
+                                        cfg.limit(MAX_BATCH_BYTES)
+                                            .error_handler(|err, _req| AppError::from(err).into())
+                                    }))
+                                    .route(web::post().to(handler::transaction::handle_batch)),
+                            )
+                            .service(web::resource("/pending/{pending_id}").route(
+                                web::get().to(handler::transaction::get_pending_transaction),
+                            ))
+                            .service(web::resource("/pending/{pending_id}/approve").route(
+                                web::post().to(handler::transaction::approve_pending_transaction),
+                            )),
+                    )
+                    .service(
+                        web::scope("/schedules")
+                            .service(
+                                web::resource("")
+                                    .app_data(web::Json::<NewSchedule>::configure(|cfg| {
+                                        cfg.error_handler(|err, _req| AppError::from(err).into())
+                                    }))
+                                    .app_data(web::Query::<ScheduleQuery>::configure(|cfg| {
+                                        cfg.error_handler(|err, _req| AppError::from(err).into())
+                                    }))
+                                    .route(web::get().to(handler::schedule::get_schedules))
+                                    .route(web::post().to(handler::schedule::create_schedule)),
+                            )
+                            .service(
+                                web::resource("/{schedule_id}")
+                                    .route(web::get().to(handler::schedule::get_schedule)),
+                            )
+                            .service(
+                                web::resource("/{schedule_id}/runs")
+                                    .route(web::get().to(handler::schedule::get_schedule_runs)),
+                            )
+                            .service(
+                                web::resource("/{schedule_id}/pause")
+                                    .route(web::post().to(handler::schedule::pause_schedule)),
+                            )
+                            .service(
+                                web::resource("/{schedule_id}/resume")
+                                    .route(web::post().to(handler::schedule::resume_schedule)),
+                            )
+                            .service(
+                                web::resource("/{schedule_id}/cancel")
+                                    .route(web::post().to(handler::schedule::cancel_schedule)),
+                            ),
+                    )
+                    .service(
+                        web::scope("/customer")
+                            .service(
+                                web::resource("")
+                                    .app_data(web::Json::<NewCustomer>::configure(|cfg| {
+                                        cfg.error_handler(|err, _req| AppError::from(err).into())
+                                    }))
+                                    .route(web::get().to(handler::customer::get_customers))
+                                    .route(web::post().to(handler::customer::create_customer)),
+                            )
+                            .service(
+                                web::resource("/{customer_id}")
+                                    .app_data(web::Json::<CustomerUpdate>::configure(|cfg| {
+                                        cfg.error_handler(|err, _req| AppError::from(err).into())
+                                    }))
+                                    .route(web::get().to(handler::customer::get_customer))
+                                    .route(web::put().to(handler::customer::update_customer))
+                                    .route(web::delete().to(handler::customer::delete_customer)),
+                            )
+                            .service(
+                                web::resource("/{customer_id}/accounts")
+                                    .route(web::get().to(handler::customer::get_customer_accounts)),
+                            ),
+                    )
+                    .service(
+                        web::scope("/fx").service(
+                            web::resource("/quote")
+                                .app_data(web::Json::<NewFxQuote>::configure(|cfg| {
+                                    cfg.error_handler(|err, _req| AppError::from(err).into())
+                                }))
+                                .route(web::post().to(handler::fx::create_quote)),
+                        ),
                     ),
             )
     })
@@ -0,0 +1,150 @@
+use crate::domain::{AppState, Principal};
+use crate::error::{AppError, ErrorType};
+use crate::service::AuthService;
+use actix_web::dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{web, Error, FromRequest, HttpMessage, HttpRequest};
+use std::cell::RefCell;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+const API_KEY_HEADER: &str = "X-API-Key";
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// Lets a request through only with a valid `X-API-Key` header or
+/// `Authorization: Bearer` token. The `Principal` it was authenticated as is
+/// put in the request extensions.
+pub struct Authentication {
+    auth_service: AuthService,
+}
+
+impl Authentication {
+    pub fn new(auth_service: AuthService) -> Authentication {
+        Authentication { auth_service }
+    }
+}
+
+impl<S, B> Transform<S> for Authentication
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AuthenticationMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthenticationMiddleware {
+            service: Rc::new(RefCell::new(service)),
+            auth_service: self.auth_service.clone(),
+        }))
+    }
+}
+
+pub struct AuthenticationMiddleware<S> {
+    service: Rc<RefCell<S>>,
+    auth_service: AuthService,
+}
+
+impl<S, B> Service for AuthenticationMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.borrow_mut().poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let auth_service = self.auth_service.clone();
+        Box::pin(async move {
+            let header = |name| {
+                req.headers()
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string)
+            };
+            let api_key = header(API_KEY_HEADER);
+            let bearer_token = header(AUTHORIZATION.as_str())
+                .and_then(|value| value.strip_prefix(BEARER_PREFIX).map(str::to_string));
+            let principal = auth_service
+                .authenticate(api_key.as_deref(), bearer_token.as_deref())
+                .await?;
+            req.extensions_mut().insert(principal);
+            let future = service.borrow_mut().call(req);
+            future.await
+        })
+    }
+}
+
+/// Handlers take the `Principal` a request was authenticated as from here.
+/// With authentication disabled every request is `Principal::Anonymous`.
+impl FromRequest for Principal {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let auth_enabled = req
+            .app_data::<web::Data<AppState>>()
+            .is_none_or(|app_state| app_state.auth_enabled);
+        ready(match req.extensions().get::<Principal>() {
+            Some(principal) => Ok(principal.clone()),
+            None if !auth_enabled => Ok(Principal::Anonymous),
+            None => Err(AppError::from_type(ErrorType::Unauthorized)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::MemoryProcessor;
+    use crate::domain::Role;
+    use actix_web::{http::StatusCode, test, App, HttpResponse};
+    use std::sync::Arc;
+
+    #[actix_rt::test]
+    async fn test_requests_without_credentials_are_rejected() {
+        let auth_service = AuthService::new(Arc::new(MemoryProcessor::new()), None);
+        let (_, key) = auth_service
+            .create_api_key("teller".to_string(), Role::Teller)
+            .await
+            .unwrap();
+        let mut app = test::init_service(
+            App::new()
+                .wrap(Authentication::new(auth_service))
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        // The server turns the error into the response.
+        let req = test::TestRequest::get().uri("/").to_request();
+        let err = app.call(req).await.err().unwrap();
+        assert_eq!(
+            err.as_response_error().status_code(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/")
+            .header(API_KEY_HEADER, key)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
@@ -1,72 +1,624 @@
-use crate::core::QldbProcessor;
-use crate::domain::{Account, NewAccount};
+use super::customer_service::find_customer;
+use crate::core::{transaction_within, Filter, Storage};
+use crate::domain::{
+    Account, AccountClosure, AccountLimits, AccountOwner, AccountStatus, Customer, NewAccount,
+    SigningRule, SystemAccounts, ACCOUNTS_TABLE,
+};
 use crate::error::{AppError, ErrorType};
+use bigdecimal::BigDecimal;
+use ion_binary_rs::IonValue;
 use std::convert::TryInto;
 
 pub struct AccountService {
-    processor: QldbProcessor,
+    processor: Storage,
 }
 
 impl AccountService {
-    pub fn new(processor: QldbProcessor) -> AccountService {
+    pub fn new(processor: Storage) -> AccountService {
         AccountService { processor }
     }
 
+    /// Opens an account for an existing customer or creates the customer
+    /// along with it, in one ledger transaction. Joint holders must be
+    /// existing customers.
     pub async fn create_account(
         &self,
-        new_account: NewAccount,
+        mut new_account: NewAccount,
     ) -> Result<(String, Account), AppError> {
-        let account: Account = new_account.into();
-        let document_id = self.processor.insert(&account).await?;
+        new_account.validate()?;
+        let currency = new_account.currency;
+        let signing_rule = new_account.signing_rule;
+        let (document_id, account) = transaction_within(self.processor.as_ref(), |tx| {
+            let customer_id = new_account.customer_id.clone();
+            let new_customer = new_account.customer.clone();
+            let joint_customer_ids = new_account.joint_customer_ids.clone();
+            Box::pin(async move {
+                let customer = match (customer_id, new_customer) {
+                    (Some(customer_id), None) => find_customer(tx, &customer_id).await?,
+                    (None, Some(new_customer)) => {
+                        let customer = Customer::new(new_customer)?;
+                        tx.insert(&customer).await?;
+                        customer
+                    }
+                    _ => {
+                        return Err(AppError::new(
+                            Some("Give either customer_id or customer".to_string()),
+                            ErrorType::PayloadError,
+                        ))
+                    }
+                };
+                let mut account = Account::for_customer(&customer, currency);
+                account.signing_rule = signing_rule;
+                let document_id = tx.insert(&account).await?;
+                let mut owners = vec![customer.id];
+                for customer_id in joint_customer_ids {
+                    if owners.contains(&customer_id) {
+                        let msg = format!("Customer {} is given twice", customer_id);
+                        return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+                    }
+                    find_customer(tx, &customer_id).await?;
+                    let owner = AccountOwner::new(account.account_number.clone(), customer_id);
+                    tx.insert(&owner).await?;
+                    owners.push(owner.customer_id);
+                }
+                Ok((document_id, account))
+            })
+        })
+        .await?;
+        info!(
+            "Opened account {} for customer {}",
+            account.account_number,
+            account.customer_id.as_deref().unwrap_or_default()
+        );
         Ok((document_id, account))
     }
 
     pub async fn find_account(&self, account_number: String) -> Result<Account, AppError> {
-        let query_str = format!(
-            "SELECT * FROM accounts b WHERE b.account_number = '{}'",
-            account_number
-        );
-        let results = self.processor.query(&query_str).await?;
-        let docs = results.into_inner();
-        if docs.len() == 0 {
-            Err(AppError::from_type(ErrorType::AccountNotFound(
-                account_number,
-            )))
-        } else {
-            let result = docs.get(0).unwrap();
-            let account: Account = result.try_into().unwrap();
-            Ok(account)
-        }
+        self.processor.find_account(account_number).await
     }
 
     pub async fn find_accounts(&self) -> Result<Vec<Account>, AppError> {
-        let results = self.processor.query("SELECT * FROM accounts").await?;
-        // let points: u64 = document.get_value("points")?;
-        // let result: u64 = results
-        //     .into_iter()
-        //     .map(|doc| doc.get_value::<u64>("points"))
-        //     .collect::<Result<Vec<u64>, _>>()?
-        //     .into_iter()
-        //     .fold(0, |acc, val| acc + val);
-        let accounts = Account::from_documents(results.into_inner());
-        Ok(accounts)
-    }
-
-    pub async fn delete_account(&self, account_number: String) -> Result<String, AppError> {
-        let query_str = format!(
-            "DELETE FROM accounts b WHERE b.account_number = '{}'",
-            account_number
+        self.processor.find_accounts(Filter::all()).await
+    }
+
+    pub async fn freeze_account(&self, account_number: String) -> Result<Account, AppError> {
+        self.change_status(account_number, AccountStatus::FROZEN)
+            .await
+    }
+
+    /// Marks an active account that is no longer used as dormant, so it
+    /// cannot send money until it is reactivated.
+    pub async fn mark_dormant(&self, account_number: String) -> Result<Account, AppError> {
+        self.change_status(account_number, AccountStatus::DORMANT)
+            .await
+    }
+
+    pub async fn set_overdraft_limit(
+        &self,
+        account_number: String,
+        limit: BigDecimal,
+    ) -> Result<Account, AppError> {
+        let account = self
+            .update_account(account_number, move |account| {
+                account.set_overdraft_limit(limit.clone())?;
+                let limit = IonValue::Decimal(account.overdraft_limit.clone());
+                Ok(vec![("overdraft_limit", limit)])
+            })
+            .await?;
+        info!(
+            "Account {} overdraft limit is now {} {}",
+            account.account_number, account.overdraft_limit, account.currency
         );
-        match self.processor.delete(&query_str).await {
-            Ok(doc_ids) => Ok(doc_ids[0].clone()),
-            Err(AppError {
-                message: None,
-                error_type: ErrorType::NoRowsAffected,
-            }) => {
-                let msg = format!("Unable to delete account: {}", account_number);
-                Err(AppError::from_type(ErrorType::AccountError(msg)))
+        Ok(account)
+    }
+
+    /// Replaces the limits set on the account and, when given, its tier.
+    pub async fn set_limits(
+        &self,
+        account_number: String,
+        account_limits: AccountLimits,
+    ) -> Result<Account, AppError> {
+        let account = self
+            .update_account(account_number, move |account| {
+                account.set_limits(account_limits.tier, account_limits.limits())?;
+                let mut values = vec![("tier", IonValue::String(account.tier.to_string()))];
+                values.extend(account.limit_params());
+                Ok(values)
+            })
+            .await?;
+        info!(
+            "Account {} is now {} with limits {:?}",
+            account.account_number, account.tier, account.limits
+        );
+        Ok(account)
+    }
+
+    /// Sets what it takes to send money from the account: any one of its
+    /// owners, or all of them.
+    pub async fn set_signing_rule(
+        &self,
+        account_number: String,
+        signing_rule: SigningRule,
+    ) -> Result<Account, AppError> {
+        let account = self
+            .update_account(account_number, move |account| {
+                account.set_signing_rule(signing_rule)?;
+                Ok(vec![(
+                    "signing_rule",
+                    IonValue::String(signing_rule.to_string()),
+                )])
+            })
+            .await?;
+        info!(
+            "Account {} signing rule is now {}",
+            account.account_number, account.signing_rule
+        );
+        Ok(account)
+    }
+
+    /// Reactivates a frozen or dormant account.
+    pub async fn unfreeze_account(&self, account_number: String) -> Result<Account, AppError> {
+        self.change_status(account_number, AccountStatus::ACTIVE)
+            .await
+    }
+
+    /// Settles the remaining balance and closes the account. Closed accounts
+    /// are never deleted.
+    pub async fn close_account(
+        &self,
+        account_number: String,
+        closure: AccountClosure,
+    ) -> Result<Account, AppError> {
+        let account = self
+            .processor
+            .close_account(account_number, closure)
+            .await?;
+        info!(
+            "Closed account {}: {}",
+            account.account_number,
+            account.closure_reason.as_deref().unwrap_or_default()
+        );
+        Ok(account)
+    }
+
+    async fn change_status(
+        &self,
+        account_number: String,
+        status: AccountStatus,
+    ) -> Result<Account, AppError> {
+        let account = self
+            .update_account(account_number, move |account| {
+                account.change_status(status)?;
+                Ok(vec![("status", IonValue::String(status.to_string()))])
+            })
+            .await?;
+        info!(
+            "Account {} is now {}",
+            account.account_number, account.status
+        );
+        Ok(account)
+    }
+
+    /// Reads the account, applies `change` and writes back the fields it
+    /// returns in one ledger transaction, so the change is checked against
+    /// the balance it is applied to.
+    async fn update_account<F>(
+        &self,
+        account_number: String,
+        change: F,
+    ) -> Result<Account, AppError>
+    where
+        F: Fn(&mut Account) -> Result<Vec<(&'static str, IonValue)>, AppError> + Clone + 'static,
+    {
+        transaction_within(self.processor.as_ref(), |tx| {
+            let account_number = account_number.clone();
+            let change = change.clone();
+            Box::pin(async move {
+                let filter =
+                    Filter::all().eq("account_number", IonValue::String(account_number.clone()));
+                let docs = tx.find(ACCOUNTS_TABLE, &filter).await?;
+                let mut account: Account = match docs.first() {
+                    Some(doc) => doc.try_into()?,
+                    None => {
+                        return Err(AppError::from_type(ErrorType::AccountNotFound(
+                            account_number,
+                        )))
+                    }
+                };
+                let mut values = change(&mut account)?;
+                values.push(("updated_at", IonValue::DateTime(account.updated_at)));
+                tx.update(ACCOUNTS_TABLE, &filter, values).await?;
+                Ok(account)
+            })
+        })
+        .await
+    }
+
+    /// Opens any configured system account that does not exist yet.
+    pub async fn open_system_accounts(
+        &self,
+        system_accounts: &SystemAccounts,
+    ) -> Result<(), AppError> {
+        for (account_number, name, currency) in system_accounts.all() {
+            match self.find_account(account_number.clone()).await {
+                Ok(_) => {}
+                Err(AppError {
+                    error_type: ErrorType::AccountNotFound(_),
+                    ..
+                }) => {
+                    let account = Account::with_number(
+                        account_number.clone(),
+                        name.to_string(),
+                        String::new(),
+                        currency,
+                    );
+                    self.processor.insert(&account).await?;
+                    info!("Opened system account {} ({})", account_number, name);
+                }
+                Err(e) => return Err(e),
             }
-            Err(e) => Err(e),
         }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{MemoryProcessor, HOSTILE_ACCOUNT_NUMBERS};
+    use crate::domain::{Currency, NewCustomer, TransactionType};
+    use crate::service::CustomerService;
+    use bigdecimal::BigDecimal;
+    use std::sync::Arc;
+
+    fn new_account() -> NewAccount {
+        NewAccount {
+            customer_id: None,
+            customer: Some(NewCustomer {
+                first_name: "Sam".to_string(),
+                last_name: "James".to_string(),
+                phone: "2347038657970".to_string(),
+                email: None,
+                address: None,
+                date_of_birth: None,
+            }),
+            currency: Currency::USD,
+            ..NewAccount::default()
+        }
+    }
+
+    fn closure(settlement_account_number: Option<String>) -> AccountClosure {
+        AccountClosure {
+            settlement_account_number,
+            reason: "Customer request".to_string(),
+        }
+    }
+
+    /// Opens a customer account holding `balance` and the cash-in account it
+    /// was funded from.
+    async fn setup(balance: u32) -> (AccountService, Storage, Account, Account) {
+        let processor: Storage = Arc::new(MemoryProcessor::new());
+        let account_service = AccountService::new(processor.clone());
+        let (_, account) = account_service.create_account(new_account()).await.unwrap();
+        let cash_in = Account::new("Cash In".to_string(), String::new(), Currency::USD);
+        processor.insert(&cash_in).await.unwrap();
+        if balance > 0 {
+            processor
+                .debit_credit(
+                    account.account_number.clone(),
+                    cash_in.account_number.clone(),
+                    BigDecimal::from(balance),
+                    TransactionType::CREDIT,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        (account_service, processor, account, cash_in)
+    }
+
+    #[actix_rt::test]
+    async fn test_accounts_are_linked_to_customers() {
+        let (account_service, processor, account, _) = setup(0).await;
+        let customer_id = account.customer_id.clone().unwrap();
+        assert_eq!(account.name, "Sam James");
+        assert_eq!(account.phone, "+2347038657970");
+
+        let (_, second) = account_service
+            .create_account(NewAccount {
+                customer_id: Some(customer_id.clone()),
+                customer: None,
+                currency: Currency::EUR,
+                ..NewAccount::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(second.customer_id, Some(customer_id.clone()));
+
+        let customer_service = CustomerService::new(processor);
+        let mut accounts: Vec<String> = customer_service
+            .find_accounts(customer_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|account| account.account_number)
+            .collect();
+        accounts.sort();
+        let mut expected = vec![account.account_number, second.account_number];
+        expected.sort();
+        assert_eq!(accounts, expected);
+
+        for new_account in [
+            NewAccount {
+                customer_id: Some("missing".to_string()),
+                ..NewAccount::default()
+            },
+            NewAccount::default(),
+            NewAccount {
+                customer: Some(NewCustomer {
+                    first_name: String::new(),
+                    last_name: "x".repeat(10 * 1024),
+                    phone: "not a phone".to_string(),
+                    email: None,
+                    address: None,
+                    date_of_birth: None,
+                }),
+                ..NewAccount::default()
+            },
+        ] {
+            assert!(account_service.create_account(new_account).await.is_err());
+        }
+        assert_eq!(customer_service.find_customers().await.unwrap().len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_customer_with_open_accounts_cannot_be_deleted() {
+        let (account_service, processor, account, _) = setup(0).await;
+        let customer_id = account.customer_id.clone().unwrap();
+        let customer_service = CustomerService::new(processor);
+
+        let err = customer_service
+            .delete_customer(customer_id.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::AccountError(_)));
+
+        account_service
+            .close_account(account.account_number, closure(None))
+            .await
+            .unwrap();
+        let deleted = customer_service
+            .delete_customer(customer_id.clone())
+            .await
+            .unwrap();
+        assert!(deleted.deleted_at.is_some());
+
+        let err = customer_service
+            .find_customer(customer_id.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::CustomerNotFound(_)));
+        assert!(customer_service.find_customers().await.unwrap().is_empty());
+        let new_account = NewAccount {
+            customer_id: Some(customer_id),
+            ..NewAccount::default()
+        };
+        assert!(account_service.create_account(new_account).await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_freeze_and_unfreeze() {
+        let (account_service, _, account, _) = setup(0).await;
+        let number = account.account_number;
+
+        let frozen = account_service
+            .freeze_account(number.clone())
+            .await
+            .unwrap();
+        assert_eq!(frozen.status, AccountStatus::FROZEN);
+        let err = account_service
+            .freeze_account(number.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::AccountError(_)));
+
+        account_service
+            .unfreeze_account(number.clone())
+            .await
+            .unwrap();
+        let account = account_service.find_account(number).await.unwrap();
+        assert_eq!(account.status, AccountStatus::ACTIVE);
+    }
+
+    #[actix_rt::test]
+    async fn test_only_active_accounts_become_dormant() {
+        let (account_service, _, account, _) = setup(0).await;
+        let number = account.account_number;
+
+        let dormant = account_service.mark_dormant(number.clone()).await.unwrap();
+        assert_eq!(dormant.status, AccountStatus::DORMANT);
+        account_service
+            .unfreeze_account(number.clone())
+            .await
+            .unwrap();
+
+        account_service
+            .freeze_account(number.clone())
+            .await
+            .unwrap();
+        let err = account_service
+            .mark_dormant(number.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::AccountError(_)));
+        let account = account_service.find_account(number).await.unwrap();
+        assert_eq!(account.status, AccountStatus::FROZEN);
+    }
+
+    #[actix_rt::test]
+    async fn test_set_overdraft_limit() {
+        let (account_service, _, account, _) = setup(10).await;
+        let number = account.account_number;
+
+        let account = account_service
+            .set_overdraft_limit(number.clone(), BigDecimal::from(25))
+            .await
+            .unwrap();
+        assert_eq!(account.overdraft_limit, BigDecimal::from(25));
+        let account = account_service.find_account(number.clone()).await.unwrap();
+        assert_eq!(account.available_balance(), &BigDecimal::from(35));
+
+        let err = account_service
+            .set_overdraft_limit(number, BigDecimal::from(-1))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::PayloadError));
+    }
+
+    #[actix_rt::test]
+    async fn test_close_without_settlement_account_is_refused() {
+        let (account_service, _, account, _) = setup(10).await;
+
+        let err = account_service
+            .close_account(account.account_number.clone(), closure(None))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::PayloadError));
+        let account = account_service
+            .find_account(account.account_number)
+            .await
+            .unwrap();
+        assert_eq!(account.status, AccountStatus::ACTIVE);
+        assert_eq!(account.balance, BigDecimal::from(10));
+    }
+
+    #[actix_rt::test]
+    async fn test_close_settles_balance_and_keeps_record() {
+        let (account_service, _, account, _) = setup(10).await;
+        let (_, settlement) = account_service.create_account(new_account()).await.unwrap();
+
+        let closed = account_service
+            .close_account(
+                account.account_number.clone(),
+                closure(Some(settlement.account_number.clone())),
+            )
+            .await
+            .unwrap();
+        assert_eq!(closed.status, AccountStatus::CLOSED);
+        assert_eq!(closed.balance, BigDecimal::from(0));
+
+        let account = account_service
+            .find_account(account.account_number)
+            .await
+            .unwrap();
+        assert_eq!(account.status, AccountStatus::CLOSED);
+        assert_eq!(account.closure_reason.as_deref(), Some("Customer request"));
+        assert!(account.closed_at.is_some());
+        let settlement = account_service
+            .find_account(settlement.account_number)
+            .await
+            .unwrap();
+        assert_eq!(settlement.balance, BigDecimal::from(10));
+    }
+
+    #[actix_rt::test]
+    async fn test_failed_settlement_leaves_account_open() {
+        let (account_service, processor, account, _) = setup(10).await;
+        let euros = Account::new("Euro".to_string(), String::new(), Currency::EUR);
+        processor.insert(&euros).await.unwrap();
+
+        let err = account_service
+            .close_account(
+                account.account_number.clone(),
+                closure(Some(euros.account_number)),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::CurrencyMismatch));
+        let account = account_service
+            .find_account(account.account_number)
+            .await
+            .unwrap();
+        assert_eq!(account.status, AccountStatus::ACTIVE);
+        assert_eq!(account.balance, BigDecimal::from(10));
+    }
+
+    #[actix_rt::test]
+    async fn test_frozen_account_can_receive_but_not_send() {
+        let (account_service, processor, account, cash_in) = setup(10).await;
+        let number = account.account_number;
+        account_service
+            .freeze_account(number.clone())
+            .await
+            .unwrap();
+
+        let err = processor
+            .transfer(
+                number.clone(),
+                cash_in.account_number.clone(),
+                BigDecimal::from(5),
+                None,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::AccountError(_)));
+
+        let receipt = processor
+            .debit_credit(
+                number,
+                cash_in.account_number,
+                BigDecimal::from(5),
+                TransactionType::CREDIT,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(receipt.recipient_balance, BigDecimal::from(15));
+    }
+
+    #[actix_rt::test]
+    async fn test_closed_account_cannot_transact() {
+        let (account_service, processor, account, cash_in) = setup(0).await;
+        let number = account.account_number;
+        account_service
+            .close_account(number.clone(), closure(None))
+            .await
+            .unwrap();
+
+        let err = processor
+            .debit_credit(
+                number.clone(),
+                cash_in.account_number,
+                BigDecimal::from(5),
+                TransactionType::CREDIT,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::AccountError(_)));
+        let err = account_service.unfreeze_account(number).await.unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::AccountError(_)));
+    }
+
+    #[actix_rt::test]
+    async fn test_hostile_account_numbers_are_not_found() {
+        let account_service = AccountService::new(Arc::new(MemoryProcessor::new()));
+        account_service.create_account(new_account()).await.unwrap();
+
+        for hostile in HOSTILE_ACCOUNT_NUMBERS {
+            let err = account_service
+                .find_account(hostile.to_string())
+                .await
+                .unwrap_err();
+            assert!(matches!(err.error_type, ErrorType::AccountNotFound(_)));
+
+            let err = account_service
+                .close_account(hostile.to_string(), closure(None))
+                .await
+                .unwrap_err();
+            assert!(matches!(err.error_type, ErrorType::AccountNotFound(_)));
+        }
+        assert_eq!(account_service.find_accounts().await.unwrap().len(), 1);
     }
 }
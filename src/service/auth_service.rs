@@ -0,0 +1,184 @@
+use crate::core::{transaction_within, Filter, Storage};
+use crate::domain::{
+    Account, AccountOwner, ApiKey, Principal, Role, ACCOUNTS_TABLE, ACCOUNT_OWNERS_TABLE,
+    API_KEYS_TABLE,
+};
+use crate::error::{AppError, ErrorType};
+use crate::util::JwtVerifier;
+use chrono::prelude::*;
+use ion_binary_rs::IonValue;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct AuthService {
+    processor: Storage,
+    jwt: Option<Arc<JwtVerifier>>,
+}
+
+impl AuthService {
+    pub fn new(processor: Storage, jwt: Option<Arc<JwtVerifier>>) -> AuthService {
+        AuthService { processor, jwt }
+    }
+
+    /// Checks the API key or, failing that, the bearer token a request came
+    /// with. A request with neither is unauthorized.
+    pub async fn authenticate(
+        &self,
+        api_key: Option<&str>,
+        bearer_token: Option<&str>,
+    ) -> Result<Principal, AppError> {
+        if let Some(key) = api_key {
+            return self.authenticate_api_key(key).await;
+        }
+        match (bearer_token, &self.jwt) {
+            (Some(token), Some(jwt)) => {
+                let claims = jwt.verify(token)?;
+                let mut account_numbers = claims.accounts;
+                if claims.role == Role::Customer {
+                    account_numbers.extend(self.customer_accounts(&claims.sub).await?);
+                }
+                Ok(Principal::Token {
+                    subject: claims.sub,
+                    role: claims.role,
+                    account_numbers,
+                })
+            }
+            (Some(_), None) => Err(AppError::new(
+                Some("Bearer tokens are not accepted".to_string()),
+                ErrorType::Unauthorized,
+            )),
+            (None, _) => Err(AppError::from_type(ErrorType::Unauthorized)),
+        }
+    }
+
+    /// Numbers of the accounts held by the customer a token was issued to,
+    /// alone or jointly.
+    async fn customer_accounts(&self, customer_id: &str) -> Result<Vec<String>, AppError> {
+        let filter = Filter::all().eq("customer_id", IonValue::String(customer_id.to_string()));
+        let docs = self.processor.query(ACCOUNTS_TABLE, &filter).await?;
+        let mut account_numbers: Vec<String> = Account::from_documents(docs)
+            .into_iter()
+            .map(|account| account.account_number)
+            .collect();
+        let docs = self.processor.query(ACCOUNT_OWNERS_TABLE, &filter).await?;
+        account_numbers.extend(
+            AccountOwner::from_documents(docs)?
+                .into_iter()
+                .map(|owner| owner.account_number),
+        );
+        Ok(account_numbers)
+    }
+
+    async fn authenticate_api_key(&self, key: &str) -> Result<Principal, AppError> {
+        let filter = Filter::all().eq("key_hash", IonValue::String(ApiKey::hash(key)));
+        let docs = self.processor.query(API_KEYS_TABLE, &filter).await?;
+        match ApiKey::from_documents(docs)?.pop() {
+            Some(api_key) if api_key.revoked_at.is_none() => Ok(Principal::ApiKey {
+                id: api_key.id,
+                name: api_key.name,
+                role: api_key.role,
+            }),
+            _ => Err(AppError::new(
+                Some("Invalid or revoked API key".to_string()),
+                ErrorType::Unauthorized,
+            )),
+        }
+    }
+
+    /// Stores a new key for `name` and returns it with the secret, which is
+    /// not kept anywhere.
+    pub async fn create_api_key(
+        &self,
+        name: String,
+        role: Role,
+    ) -> Result<(ApiKey, String), AppError> {
+        let (api_key, key) = ApiKey::generate(name, role)?;
+        self.processor.insert(&api_key).await?;
+        info!(
+            "Created {} API key {} for {}",
+            api_key.role, api_key.id, api_key.name
+        );
+        Ok((api_key, key))
+    }
+
+    /// All keys, oldest first.
+    pub async fn find_api_keys(&self) -> Result<Vec<ApiKey>, AppError> {
+        let docs = self.processor.query(API_KEYS_TABLE, &Filter::all()).await?;
+        let mut api_keys = ApiKey::from_documents(docs)?;
+        api_keys.sort_by(|a, b| (a.created_at, a.id.as_str()).cmp(&(b.created_at, b.id.as_str())));
+        Ok(api_keys)
+    }
+
+    pub async fn revoke_api_key(&self, id: String) -> Result<ApiKey, AppError> {
+        let api_key = transaction_within(self.processor.as_ref(), |tx| {
+            let id = id.clone();
+            Box::pin(async move {
+                let filter = Filter::all().eq("id", IonValue::String(id.clone()));
+                let docs = tx.find(API_KEYS_TABLE, &filter).await?;
+                let mut api_key = match ApiKey::from_documents(docs)?.pop() {
+                    Some(api_key) => api_key,
+                    None => return Err(AppError::from_type(ErrorType::ApiKeyNotFound(id))),
+                };
+                if api_key.revoked_at.is_none() {
+                    let now: DateTime<FixedOffset> = Utc::now().into();
+                    api_key.revoked_at = Some(now);
+                    tx.update(
+                        API_KEYS_TABLE,
+                        &filter,
+                        vec![("revoked_at", IonValue::DateTime(now))],
+                    )
+                    .await?;
+                }
+                Ok(api_key)
+            })
+        })
+        .await?;
+        info!("Revoked API key {} of {}", api_key.id, api_key.name);
+        Ok(api_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::MemoryProcessor;
+
+    #[actix_rt::test]
+    async fn test_api_keys_authenticate_until_revoked() {
+        let auth_service = AuthService::new(Arc::new(MemoryProcessor::new()), None);
+        let (api_key, key) = auth_service
+            .create_api_key("reconciliation".to_string(), Role::Auditor)
+            .await
+            .unwrap();
+        assert_ne!(api_key.key_hash, key);
+
+        let principal = auth_service.authenticate(Some(&key), None).await.unwrap();
+        assert_eq!(
+            principal,
+            Principal::ApiKey {
+                id: api_key.id.clone(),
+                name: "reconciliation".to_string(),
+                role: Role::Auditor,
+            }
+        );
+        for (api_key, bearer_token) in &[
+            (Some("bk_guess"), None),
+            (None, Some("a.b.c")),
+            (None, None),
+        ] {
+            let err = auth_service
+                .authenticate(*api_key, *bearer_token)
+                .await
+                .unwrap_err();
+            assert!(matches!(err.error_type, ErrorType::Unauthorized));
+        }
+
+        assert!(auth_service
+            .create_api_key("app".to_string(), Role::Customer)
+            .await
+            .is_err());
+
+        auth_service.revoke_api_key(api_key.id).await.unwrap();
+        assert!(auth_service.authenticate(Some(&key), None).await.is_err());
+    }
+}
@@ -0,0 +1,153 @@
+use crate::core::{read_within, transaction_within, Filter, LedgerTransaction, Storage};
+use crate::domain::{
+    Account, AccountOwner, AccountStatus, Customer, CustomerUpdate, NewCustomer, ACCOUNTS_TABLE,
+    ACCOUNT_OWNERS_TABLE, CUSTOMERS_TABLE,
+};
+use crate::error::{AppError, ErrorType};
+use ion_binary_rs::IonValue;
+
+pub struct CustomerService {
+    processor: Storage,
+}
+
+impl CustomerService {
+    pub fn new(processor: Storage) -> CustomerService {
+        CustomerService { processor }
+    }
+
+    pub async fn create_customer(
+        &self,
+        mut new_customer: NewCustomer,
+    ) -> Result<Customer, AppError> {
+        new_customer.validate()?;
+        let customer = Customer::new(new_customer)?;
+        self.processor.insert(&customer).await?;
+        info!("Created customer {}", customer.id);
+        Ok(customer)
+    }
+
+    pub async fn find_customer(&self, id: String) -> Result<Customer, AppError> {
+        read_within(self.processor.as_ref(), |tx| {
+            let id = id.clone();
+            Box::pin(async move { find_customer(tx, &id).await })
+        })
+        .await
+    }
+
+    /// All customers that have not been deleted, oldest first.
+    pub async fn find_customers(&self) -> Result<Vec<Customer>, AppError> {
+        let docs = self
+            .processor
+            .query(CUSTOMERS_TABLE, &Filter::all())
+            .await?;
+        let mut customers = Customer::from_documents(docs)?;
+        customers.retain(|customer| customer.deleted_at.is_none());
+        customers.sort_by(|a, b| (a.created_at, a.id.as_str()).cmp(&(b.created_at, b.id.as_str())));
+        Ok(customers)
+    }
+
+    pub async fn update_customer(
+        &self,
+        id: String,
+        mut update: CustomerUpdate,
+    ) -> Result<Customer, AppError> {
+        update.validate()?;
+        let customer = transaction_within(self.processor.as_ref(), |tx| {
+            let id = id.clone();
+            let update = update.clone();
+            Box::pin(async move {
+                let mut customer = find_customer(tx, &id).await?;
+                let values = customer.apply(update);
+                let filter = Filter::all().eq("id", IonValue::String(id));
+                tx.update(CUSTOMERS_TABLE, &filter, values).await?;
+                Ok(customer)
+            })
+        })
+        .await?;
+        info!(
+            "Updated customer {}, KYC {}",
+            customer.id, customer.kyc_status
+        );
+        Ok(customer)
+    }
+
+    /// Soft-deletes the customer. A customer who still holds an account that
+    /// is not closed, alone or jointly, cannot be deleted.
+    pub async fn delete_customer(&self, id: String) -> Result<Customer, AppError> {
+        let customer = transaction_within(self.processor.as_ref(), |tx| {
+            let id = id.clone();
+            Box::pin(async move {
+                let mut customer = find_customer(tx, &id).await?;
+                let open = open_accounts(tx, &id).await?;
+                if !open.is_empty() {
+                    let msg = format!(
+                        "Customer {} still holds open accounts: {}",
+                        id,
+                        open.join(", ")
+                    );
+                    return Err(AppError::from_type(ErrorType::AccountError(msg)));
+                }
+                let values = customer.delete();
+                let filter = Filter::all().eq("id", IonValue::String(id));
+                tx.update(CUSTOMERS_TABLE, &filter, values).await?;
+                Ok(customer)
+            })
+        })
+        .await?;
+        info!("Deleted customer {}", customer.id);
+        Ok(customer)
+    }
+
+    /// Accounts held by the customer.
+    pub async fn find_accounts(&self, id: String) -> Result<Vec<Account>, AppError> {
+        self.find_customer(id.clone()).await?;
+        let filter = Filter::all().eq("customer_id", IonValue::String(id));
+        self.processor.find_accounts(filter).await
+    }
+}
+
+/// Reads a customer within a ledger transaction. Deleted customers are not
+/// found.
+pub async fn find_customer(tx: &mut dyn LedgerTransaction, id: &str) -> Result<Customer, AppError> {
+    let filter = Filter::all().eq("id", IonValue::String(id.to_string()));
+    let docs = tx.find(CUSTOMERS_TABLE, &filter).await?;
+    match Customer::from_documents(docs)?.pop() {
+        Some(customer) if customer.deleted_at.is_none() => Ok(customer),
+        _ => Err(AppError::from_type(ErrorType::CustomerNotFound(
+            id.to_string(),
+        ))),
+    }
+}
+
+/// Numbers of the accounts the customer holds, alone or jointly, that are not
+/// closed.
+async fn open_accounts(
+    tx: &mut dyn LedgerTransaction,
+    customer_id: &str,
+) -> Result<Vec<String>, AppError> {
+    let customer = IonValue::String(customer_id.to_string());
+    let docs = tx
+        .find(
+            ACCOUNTS_TABLE,
+            &Filter::all().eq("customer_id", customer.clone()),
+        )
+        .await?;
+    let mut accounts = Account::from_documents(docs);
+    let docs = tx
+        .find(
+            ACCOUNT_OWNERS_TABLE,
+            &Filter::all().eq("customer_id", customer),
+        )
+        .await?;
+    for owner in AccountOwner::from_documents(docs)? {
+        let filter = Filter::all().eq("account_number", IonValue::String(owner.account_number));
+        accounts.extend(Account::from_documents(
+            tx.find(ACCOUNTS_TABLE, &filter).await?,
+        ));
+    }
+    Ok(accounts
+        .into_iter()
+        .filter(|account| account.status != AccountStatus::CLOSED)
+        .map(|account| account.account_number)
+        .collect())
+}
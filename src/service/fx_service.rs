@@ -0,0 +1,138 @@
+use crate::core::{Filter, Rates, Storage};
+use crate::domain::{Currency, FxQuote, NewFxQuote, FX_QUOTES_TABLE};
+use crate::error::{AppError, ErrorType};
+use bigdecimal::BigDecimal;
+use chrono::Duration;
+use ion_binary_rs::IonValue;
+use std::convert::TryInto;
+
+pub struct FxService {
+    processor: Storage,
+    rates: Rates,
+    quote_ttl: Duration,
+}
+
+impl FxService {
+    pub fn new(processor: Storage, rates: Rates, quote_ttl: Duration) -> FxService {
+        FxService {
+            processor,
+            rates,
+            quote_ttl,
+        }
+    }
+
+    pub async fn create_quote(&self, new_quote: NewFxQuote) -> Result<FxQuote, AppError> {
+        if new_quote.from == new_quote.to {
+            return Err(AppError::new(
+                Some("Cannot quote a currency against itself".to_string()),
+                ErrorType::PayloadError,
+            ));
+        }
+        let rate = self.rates.rate(new_quote.from, new_quote.to).await?;
+        let quote = FxQuote::new(new_quote.from, new_quote.to, rate, self.quote_ttl);
+        self.processor.insert(&quote).await?;
+        info!(
+            "Quoted {}/{} at {} until {}",
+            quote.from, quote.to, quote.rate, quote.expires_at
+        );
+        Ok(quote)
+    }
+
+    pub async fn find_quote(&self, id: String) -> Result<FxQuote, AppError> {
+        let filter = Filter::all().eq("id", IonValue::String(id.clone()));
+        let docs = self.processor.query(FX_QUOTES_TABLE, &filter).await?;
+        match docs.first() {
+            None => Err(AppError::new(
+                Some(format!("FX quote not found: {}", id)),
+                ErrorType::PayloadError,
+            )),
+            Some(doc) => doc.try_into(),
+        }
+    }
+
+    /// Rate for converting `from` into `to`: the rate of the given quote when
+    /// it is still valid for that pair, otherwise the provider's current rate.
+    pub async fn rate(
+        &self,
+        from: Currency,
+        to: Currency,
+        quote_id: Option<String>,
+    ) -> Result<BigDecimal, AppError> {
+        let quote_id = match quote_id {
+            Some(quote_id) => quote_id,
+            None => return self.rates.rate(from, to).await,
+        };
+        let quote = self.find_quote(quote_id).await?;
+        if quote.from != from || quote.to != to {
+            let msg = format!(
+                "FX quote {} is for {}/{}, not {}/{}",
+                quote.id, quote.from, quote.to, from, to
+            );
+            return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+        }
+        if quote.is_expired() {
+            let msg = format!("FX quote {} has expired", quote.id);
+            return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+        }
+        Ok(quote.rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{MemoryProcessor, StaticRateProvider};
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    fn fx_service(quote_ttl: Duration) -> FxService {
+        let mut rates = HashMap::new();
+        rates.insert(
+            (Currency::USD, Currency::EUR),
+            BigDecimal::from_str("0.9").unwrap(),
+        );
+        FxService::new(
+            Arc::new(MemoryProcessor::new()),
+            Arc::new(StaticRateProvider::new(rates)),
+            quote_ttl,
+        )
+    }
+
+    fn usd_to_eur() -> NewFxQuote {
+        NewFxQuote {
+            from: Currency::USD,
+            to: Currency::EUR,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_quote_is_honoured_for_its_pair() {
+        let fx_service = fx_service(Duration::seconds(60));
+        let quote = fx_service.create_quote(usd_to_eur()).await.unwrap();
+
+        let rate = fx_service
+            .rate(Currency::USD, Currency::EUR, Some(quote.id.clone()))
+            .await
+            .unwrap();
+        assert_eq!(rate, quote.rate);
+
+        let err = fx_service
+            .rate(Currency::EUR, Currency::USD, Some(quote.id))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::PayloadError));
+    }
+
+    #[actix_rt::test]
+    async fn test_expired_quote_is_rejected() {
+        let fx_service = fx_service(Duration::seconds(0));
+        let quote = fx_service.create_quote(usd_to_eur()).await.unwrap();
+
+        let err = fx_service
+            .rate(Currency::USD, Currency::EUR, Some(quote.id))
+            .await
+            .unwrap_err();
+        assert!(err.message.unwrap().contains("expired"));
+    }
+}
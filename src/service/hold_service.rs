@@ -0,0 +1,332 @@
+use crate::core::Storage;
+use crate::domain::{
+    CapturedHold, Hold, HoldCapture, LimitPolicy, NewHold, SystemAccounts, TransactionType,
+};
+use crate::error::AppError;
+use chrono::Duration;
+use std::sync::Arc;
+
+pub struct HoldService {
+    processor: Storage,
+    system_accounts: SystemAccounts,
+    hold_ttl: Duration,
+    limits: Arc<LimitPolicy>,
+}
+
+impl HoldService {
+    pub fn new(
+        processor: Storage,
+        system_accounts: SystemAccounts,
+        hold_ttl: Duration,
+        limits: Arc<LimitPolicy>,
+    ) -> HoldService {
+        HoldService {
+            processor,
+            system_accounts,
+            hold_ttl,
+            limits,
+        }
+    }
+
+    pub async fn place_hold(
+        &self,
+        account_number: String,
+        new_hold: NewHold,
+    ) -> Result<Hold, AppError> {
+        self.system_accounts.ensure_not_system(&account_number)?;
+        let hold = self
+            .processor
+            .place_hold(
+                account_number,
+                new_hold.amount,
+                new_hold.currency,
+                self.hold_ttl,
+            )
+            .await?;
+        info!(
+            "Held {} {} on {} until {} ({})",
+            hold.amount, hold.currency, hold.account_number, hold.expires_at, hold.id
+        );
+        Ok(hold)
+    }
+
+    pub async fn find_holds(&self, account_number: String) -> Result<Vec<Hold>, AppError> {
+        self.processor.find_account(account_number.clone()).await?;
+        self.processor.find_holds(account_number).await
+    }
+
+    /// Captures into a transfer when a recipient is given, otherwise into a
+    /// debit paid out through the cash-out account. The captured amount counts
+    /// against the account's limits like any other debit or transfer.
+    pub async fn capture_hold(
+        &self,
+        account_number: String,
+        hold_id: String,
+        capture: HoldCapture,
+    ) -> Result<CapturedHold, AppError> {
+        let (counterparty, transaction_type) = match capture.recipient_account_number {
+            Some(recipient) => {
+                self.system_accounts.ensure_not_system(&recipient)?;
+                (recipient, TransactionType::TRANSFER)
+            }
+            None => {
+                let account = self.processor.find_account(account_number.clone()).await?;
+                let cash_out = self.system_accounts.cash_out_for(account.currency);
+                (cash_out, TransactionType::DEBIT)
+            }
+        };
+        let captured = self
+            .processor
+            .capture_hold(
+                account_number,
+                hold_id,
+                capture.amount,
+                counterparty,
+                transaction_type,
+                self.limits.clone(),
+            )
+            .await?;
+        info!(
+            "Captured {} {} of hold {} ({})",
+            captured.transaction.amount,
+            captured.transaction.currency,
+            captured.hold.id,
+            captured.transaction.transaction_id
+        );
+        Ok(captured)
+    }
+
+    pub async fn release_hold(
+        &self,
+        account_number: String,
+        hold_id: String,
+    ) -> Result<Hold, AppError> {
+        let hold = self.processor.release_hold(account_number, hold_id).await?;
+        info!("Released hold {} on {}", hold.id, hold.account_number);
+        Ok(hold)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::MemoryProcessor;
+    use crate::domain::{Account, AccountLimits, Currency};
+    use crate::error::ErrorType;
+    use crate::service::AccountService;
+    use bigdecimal::BigDecimal;
+
+    fn system_accounts() -> SystemAccounts {
+        SystemAccounts {
+            cash_in: "CASH-IN".to_string(),
+            cash_out: "CASH-OUT".to_string(),
+            suspense: "SUSPENSE".to_string(),
+            fx_position: "FX-POSITION".to_string(),
+        }
+    }
+
+    fn new_hold(amount: u32) -> NewHold {
+        NewHold {
+            amount: BigDecimal::from(amount),
+            currency: None,
+        }
+    }
+
+    /// Opens the system accounts and a customer account holding 100 USD.
+    async fn setup(hold_ttl: Duration) -> (HoldService, Storage, String) {
+        let processor: Storage = Arc::new(MemoryProcessor::new());
+        let system_accounts = system_accounts();
+        AccountService::new(processor.clone())
+            .open_system_accounts(&system_accounts)
+            .await
+            .unwrap();
+        let account = Account::new("Sam James".to_string(), String::new(), Currency::USD);
+        processor.insert(&account).await.unwrap();
+        processor
+            .debit_credit(
+                account.account_number.clone(),
+                system_accounts.cash_in_for(Currency::USD),
+                BigDecimal::from(100),
+                TransactionType::CREDIT,
+                None,
+            )
+            .await
+            .unwrap();
+        let hold_service = HoldService::new(
+            processor.clone(),
+            system_accounts,
+            hold_ttl,
+            Arc::new(LimitPolicy::default()),
+        );
+        (hold_service, processor, account.account_number)
+    }
+
+    async fn balances(processor: &Storage, account_number: &str) -> (BigDecimal, BigDecimal) {
+        let account = processor
+            .find_account(account_number.to_string())
+            .await
+            .unwrap();
+        (account.balance.clone(), account.available_balance().clone())
+    }
+
+    #[actix_rt::test]
+    async fn test_hold_reserves_available_balance() {
+        let (hold_service, processor, number) = setup(Duration::minutes(5)).await;
+
+        hold_service
+            .place_hold(number.clone(), new_hold(60))
+            .await
+            .unwrap();
+        assert_eq!(
+            balances(&processor, &number).await,
+            (BigDecimal::from(100), BigDecimal::from(40))
+        );
+
+        let err = hold_service
+            .place_hold(number.clone(), new_hold(41))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::InsufficientBalance));
+        let err = processor
+            .debit_credit(
+                number,
+                "CASH-OUT-USD".to_string(),
+                BigDecimal::from(41),
+                TransactionType::DEBIT,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::InsufficientBalance));
+    }
+
+    #[actix_rt::test]
+    async fn test_partial_capture_then_release() {
+        let (hold_service, processor, number) = setup(Duration::minutes(5)).await;
+        let hold = hold_service
+            .place_hold(number.clone(), new_hold(60))
+            .await
+            .unwrap();
+
+        let capture = HoldCapture {
+            amount: Some(BigDecimal::from(25)),
+            recipient_account_number: None,
+        };
+        let captured = hold_service
+            .capture_hold(number.clone(), hold.id.clone(), capture)
+            .await
+            .unwrap();
+        assert_eq!(
+            captured.transaction.transaction_type,
+            TransactionType::DEBIT
+        );
+        assert_eq!(captured.hold.remaining(), BigDecimal::from(35));
+        assert_eq!(
+            balances(&processor, &number).await,
+            (BigDecimal::from(75), BigDecimal::from(40))
+        );
+
+        let released = hold_service
+            .release_hold(number.clone(), hold.id.clone())
+            .await
+            .unwrap();
+        assert_eq!(released.status.to_string(), "RELEASED");
+        assert_eq!(
+            balances(&processor, &number).await,
+            (BigDecimal::from(75), BigDecimal::from(75))
+        );
+        let err = hold_service
+            .release_hold(number, hold.id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::PayloadError));
+    }
+
+    #[actix_rt::test]
+    async fn test_capture_to_recipient_is_a_transfer() {
+        let (hold_service, processor, number) = setup(Duration::minutes(5)).await;
+        let recipient = Account::new("Shop".to_string(), String::new(), Currency::USD);
+        processor.insert(&recipient).await.unwrap();
+        let hold = hold_service
+            .place_hold(number.clone(), new_hold(60))
+            .await
+            .unwrap();
+
+        let capture = HoldCapture {
+            amount: None,
+            recipient_account_number: Some(recipient.account_number.clone()),
+        };
+        let captured = hold_service
+            .capture_hold(number.clone(), hold.id, capture)
+            .await
+            .unwrap();
+        assert_eq!(captured.hold.status.to_string(), "CAPTURED");
+        assert_eq!(captured.transaction.recipient_balance, BigDecimal::from(60));
+        assert_eq!(
+            balances(&processor, &number).await,
+            (BigDecimal::from(40), BigDecimal::from(40))
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_capture_is_checked_against_limits() {
+        let (hold_service, processor, number) = setup(Duration::minutes(5)).await;
+        let limits = AccountLimits {
+            tier: None,
+            max_transaction_amount: Some(BigDecimal::from(50)),
+            max_daily_debit_total: None,
+            max_daily_transfer_count: None,
+        };
+        AccountService::new(processor.clone())
+            .set_limits(number.clone(), limits)
+            .await
+            .unwrap();
+        let hold = hold_service
+            .place_hold(number.clone(), new_hold(60))
+            .await
+            .unwrap();
+
+        let capture = |amount: u32| HoldCapture {
+            amount: Some(BigDecimal::from(amount)),
+            recipient_account_number: None,
+        };
+        let err = hold_service
+            .capture_hold(number.clone(), hold.id.clone(), capture(60))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::LimitExceeded(_)));
+        assert_eq!(
+            balances(&processor, &number).await,
+            (BigDecimal::from(100), BigDecimal::from(40))
+        );
+        hold_service
+            .capture_hold(number, hold.id, capture(50))
+            .await
+            .unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_expired_hold_frees_funds() {
+        let (hold_service, processor, number) = setup(Duration::zero()).await;
+        let hold = hold_service
+            .place_hold(number.clone(), new_hold(60))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            balances(&processor, &number).await,
+            (BigDecimal::from(100), BigDecimal::from(100))
+        );
+        let holds = hold_service.find_holds(number.clone()).await.unwrap();
+        assert_eq!(holds[0].status.to_string(), "EXPIRED");
+        let capture = HoldCapture {
+            amount: None,
+            recipient_account_number: None,
+        };
+        let err = hold_service
+            .capture_hold(number, hold.id, capture)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::PayloadError));
+    }
+}
@@ -0,0 +1,215 @@
+use crate::core::Storage;
+use crate::domain::{
+    Account, ImportReport, ImportRow, ImportRowResult, Posting, SystemAccounts, TransactionType,
+};
+use crate::error::{AppError, ErrorType};
+use bigdecimal::BigDecimal;
+use std::collections::HashSet;
+use std::io::Read;
+
+/// Accounts opened per ledger transaction.
+const CHUNK_SIZE: usize = 100;
+
+pub struct ImportService {
+    processor: Storage,
+    system_accounts: SystemAccounts,
+}
+
+impl ImportService {
+    pub fn new(processor: Storage, system_accounts: SystemAccounts) -> ImportService {
+        ImportService {
+            processor,
+            system_accounts,
+        }
+    }
+
+    /// Opens an account for every valid row of a CSV file with the columns
+    /// `name`, `phone`, `account_number`, `opening_balance` and `currency`,
+    /// crediting opening balances from the cash-in account. Rows are written
+    /// in chunks, each chunk in one ledger transaction, so a chunk that
+    /// cannot be written fails as a whole and the rest go on.
+    pub async fn import_accounts<R: Read>(&self, csv: R) -> Result<ImportReport, AppError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(csv);
+        let headers = reader
+            .headers()
+            .map_err(|e| invalid(format!("Unable to read CSV header: {}", e)))?
+            .clone();
+
+        let mut results = Vec::new();
+        let mut accounts = Vec::new();
+        let mut seen = HashSet::new();
+        for (index, record) in reader.records().enumerate() {
+            // The header is line 1; a record that cannot be read has no
+            // position, so count it instead.
+            let mut row = index as u64 + 2;
+            let account = record
+                .map_err(|e| invalid(format!("Unable to read CSV row: {}", e)))
+                .and_then(|record| {
+                    if let Some(position) = record.position() {
+                        row = position.line();
+                    }
+                    record
+                        .deserialize::<ImportRow>(Some(&headers))
+                        .map_err(|e| invalid(e.to_string()))
+                })
+                .and_then(ImportRow::into_account);
+            match account {
+                Ok((account, _)) if !seen.insert(account.account_number.clone()) => {
+                    let msg = format!(
+                        "Account {} appears more than once in the file",
+                        account.account_number
+                    );
+                    results.push(ImportRowResult::failed(row, &invalid(msg)));
+                }
+                Ok((account, balance)) => accounts.push((row, account, balance)),
+                Err(e) => results.push(ImportRowResult::failed(row, &e)),
+            }
+        }
+
+        let mut new_accounts = Vec::new();
+        for (row, account, balance) in accounts {
+            match self.check_new(&account).await {
+                Ok(()) => new_accounts.push((row, account, balance)),
+                Err(e) => results.push(ImportRowResult::failed(row, &e)),
+            }
+        }
+
+        for chunk in new_accounts.chunks(CHUNK_SIZE) {
+            let openings = chunk
+                .iter()
+                .map(|(_, account, balance)| {
+                    (account.clone(), self.opening_balance(account, balance))
+                })
+                .collect();
+            match self.processor.open_accounts(openings).await {
+                Ok(receipts) => {
+                    for ((row, account, _), receipt) in chunk.iter().zip(receipts) {
+                        let transaction_id = receipt.map(|receipt| receipt.transaction_id);
+                        results.push(ImportRowResult::imported(
+                            *row,
+                            account.account_number.clone(),
+                            transaction_id,
+                        ));
+                    }
+                }
+                Err(e) => {
+                    error!("Unable to import {} accounts: {}", chunk.len(), e.message());
+                    for (row, _, _) in chunk {
+                        results.push(ImportRowResult::failed(*row, &e));
+                    }
+                }
+            }
+        }
+
+        let report = ImportReport::new(results);
+        info!(
+            "Imported {} accounts, {} rows failed",
+            report.imported, report.failed
+        );
+        Ok(report)
+    }
+
+    /// An imported account number must not be taken already.
+    async fn check_new(&self, account: &Account) -> Result<(), AppError> {
+        self.system_accounts
+            .ensure_not_system(&account.account_number)?;
+        match self
+            .processor
+            .find_account(account.account_number.clone())
+            .await
+        {
+            Ok(_) => Err(AppError::from_type(ErrorType::AccountError(format!(
+                "Account {} already exists",
+                account.account_number
+            )))),
+            Err(AppError {
+                error_type: ErrorType::AccountNotFound(_),
+                ..
+            }) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn opening_balance(&self, account: &Account, balance: &BigDecimal) -> Option<Posting> {
+        if *balance == BigDecimal::from(0) {
+            return None;
+        }
+        Some(Posting::DebitCredit {
+            account_number: account.account_number.clone(),
+            contra_account_number: self.system_accounts.cash_in_for(account.currency),
+            amount: balance.clone(),
+            transaction_type: TransactionType::CREDIT,
+        })
+    }
+}
+
+fn invalid(msg: String) -> AppError {
+    AppError::new(Some(msg), ErrorType::PayloadError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::MemoryProcessor;
+    use crate::service::AccountService;
+    use std::sync::Arc;
+
+    #[actix_rt::test]
+    async fn test_import_reports_every_row() {
+        let processor: Storage = Arc::new(MemoryProcessor::new());
+        let system_accounts = SystemAccounts {
+            cash_in: "CASH-IN".to_string(),
+            cash_out: "CASH-OUT".to_string(),
+            suspense: "SUSPENSE".to_string(),
+            fx_position: "FX-POSITION".to_string(),
+        };
+        AccountService::new(processor.clone())
+            .open_system_accounts(&system_accounts)
+            .await
+            .unwrap();
+        let import_service = ImportService::new(processor.clone(), system_accounts);
+        let csv = "name,phone,account_number,opening_balance,currency
+Sam James,2347038657970,0123456789,150.25,USD
+Ada Obi,2348012345678,,0,
+No Phone,,,10,USD
+Sam Again,2347038657970,0123456789,5,USD
+Bad Amount,2348000000000,,ten,USD
+";
+
+        let report = import_service
+            .import_accounts(csv.as_bytes())
+            .await
+            .unwrap();
+        assert_eq!((report.imported, report.failed), (2, 3));
+        let rows: Vec<(u64, bool)> = report
+            .results
+            .iter()
+            .map(|result| (result.row, result.error.is_none()))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![(2, true), (3, true), (4, false), (5, false), (6, false)]
+        );
+        assert!(report.results[0].transaction_id.is_some());
+        assert!(report.results[1].transaction_id.is_none());
+
+        let account = processor
+            .find_account("0123456789".to_string())
+            .await
+            .unwrap();
+        assert_eq!(account.balance, "150.25".parse().unwrap());
+
+        // The same file again only finds accounts that exist already.
+        let report = import_service
+            .import_accounts(csv.as_bytes())
+            .await
+            .unwrap();
+        assert_eq!(
+            report.results[0].error.as_ref().unwrap().message,
+            "Account 0123456789 already exists"
+        );
+        assert_eq!(report.imported, 1);
+    }
+}
@@ -0,0 +1,499 @@
+use super::customer_service::find_customer;
+use crate::core::{read_within, transaction_within, Filter, LedgerTransaction, Storage};
+use crate::domain::{
+    Account, AccountOwner, AccountOwners, Approval, IdempotencyKey, NewTransaction, PendingStatus,
+    PendingTransaction, SigningRule, TransactionReceipt, ACCOUNTS_TABLE, ACCOUNT_OWNERS_TABLE,
+    APPROVALS_TABLE, PENDING_TRANSACTIONS_TABLE,
+};
+use crate::error::{AppError, ErrorType};
+use chrono::Duration;
+use ion_binary_rs::IonValue;
+use std::convert::TryFrom;
+
+/// Accounts held by more than one customer, and the transfers out of them
+/// that wait for their owners' approval.
+pub struct JointAccountService {
+    processor: Storage,
+    approval_ttl: Duration,
+}
+
+impl JointAccountService {
+    pub fn new(processor: Storage, approval_ttl: Duration) -> JointAccountService {
+        JointAccountService {
+            processor,
+            approval_ttl,
+        }
+    }
+
+    pub async fn find_owners(&self, account_number: String) -> Result<AccountOwners, AppError> {
+        read_within(self.processor.as_ref(), |tx| {
+            let account_number = account_number.clone();
+            Box::pin(async move {
+                let account = find_account(tx, &account_number).await?;
+                Ok(AccountOwners {
+                    account_number,
+                    signing_rule: account.signing_rule,
+                    customer_ids: owners(tx, &account).await?,
+                })
+            })
+        })
+        .await
+    }
+
+    /// Adds an existing customer as a holder of the account.
+    pub async fn add_owner(
+        &self,
+        account_number: String,
+        customer_id: String,
+    ) -> Result<AccountOwners, AppError> {
+        let account_owners = transaction_within(self.processor.as_ref(), |tx| {
+            let account_number = account_number.clone();
+            let customer_id = customer_id.clone();
+            Box::pin(async move {
+                let account = find_account(tx, &account_number).await?;
+                if account.customer_id.is_none() {
+                    let msg = format!("Account {} has no customer to share it", account_number);
+                    return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+                }
+                find_customer(tx, &customer_id).await?;
+                let mut customer_ids = owners(tx, &account).await?;
+                if customer_ids.contains(&customer_id) {
+                    let msg = format!(
+                        "Customer {} already holds account {}",
+                        customer_id, account_number
+                    );
+                    return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+                }
+                tx.insert(&AccountOwner::new(
+                    account_number.clone(),
+                    customer_id.clone(),
+                ))
+                .await?;
+                customer_ids.push(customer_id);
+                Ok(AccountOwners {
+                    account_number,
+                    signing_rule: account.signing_rule,
+                    customer_ids,
+                })
+            })
+        })
+        .await?;
+        info!(
+            "Customer {} now holds account {}",
+            customer_id, account_owners.account_number
+        );
+        Ok(account_owners)
+    }
+
+    /// Whether money leaving the account waits for all of its owners.
+    pub async fn needs_approval(&self, account_number: String) -> Result<bool, AppError> {
+        let account = self.processor.find_account(account_number).await?;
+        if account.signing_rule != SigningRule::ALL {
+            return Ok(false);
+        }
+        let customer_ids = read_within(self.processor.as_ref(), |tx| {
+            let account = account.clone();
+            Box::pin(async move { owners(tx, &account).await })
+        })
+        .await?;
+        Ok(customer_ids.len() > 1)
+    }
+
+    /// Holds back a transfer until every owner of the sending account has
+    /// approved it. The owner asking for it, if any, approves it right away.
+    /// A request repeated with the same idempotency key gets the transfer
+    /// already waiting.
+    pub async fn request(
+        &self,
+        transaction: &NewTransaction,
+        idempotency_key: Option<IdempotencyKey>,
+        requested_by: Option<String>,
+    ) -> Result<PendingTransaction, AppError> {
+        let pending =
+            PendingTransaction::new(transaction, idempotency_key.as_ref(), self.approval_ttl)?;
+        let (pending, created) = transaction_within(self.processor.as_ref(), |tx| {
+            let mut pending = pending.clone();
+            let requested_by = requested_by.clone();
+            Box::pin(async move {
+                if let (Some(scope), Some(key)) =
+                    (&pending.idempotency_scope, &pending.idempotency_key)
+                {
+                    let filter = Filter::all()
+                        .eq("idempotency_scope", IonValue::String(scope.clone()))
+                        .eq("idempotency_key", IonValue::String(key.clone()));
+                    if let Some(existing) = find_pending_where(tx, &filter).await?.pop() {
+                        if existing.request_fingerprint != pending.request_fingerprint {
+                            return Err(AppError::from_type(ErrorType::IdempotencyKeyReused));
+                        }
+                        return Ok((existing, false));
+                    }
+                }
+                tx.insert(&pending).await?;
+                if let Some(customer_id) = requested_by {
+                    let account = find_account(tx, &pending.sender_account_number).await?;
+                    if owners(tx, &account).await?.contains(&customer_id) {
+                        let approval = Approval::new(pending.id.clone(), customer_id);
+                        tx.insert(&approval).await?;
+                        pending.approvals.push(approval);
+                    }
+                }
+                Ok((pending, true))
+            })
+        })
+        .await?;
+        if created {
+            info!(
+                "Transfer of {} from {} waits for approval until {} ({})",
+                pending.amount, pending.sender_account_number, pending.expires_at, pending.id
+            );
+        }
+        Ok(pending)
+    }
+
+    /// Reading pending transactions expires those whose time has run out,
+    /// so it takes the write lock like any other write.
+    pub async fn find_pending(&self, id: String) -> Result<PendingTransaction, AppError> {
+        transaction_within(self.processor.as_ref(), |tx| {
+            let id = id.clone();
+            Box::pin(async move { find_pending(tx, &id).await })
+        })
+        .await
+    }
+
+    /// Transfers out of the account that waited or still wait for approval,
+    /// oldest first.
+    pub async fn find_pending_for(
+        &self,
+        account_number: String,
+    ) -> Result<Vec<PendingTransaction>, AppError> {
+        self.processor.find_account(account_number.clone()).await?;
+        let mut pending = transaction_within(self.processor.as_ref(), |tx| {
+            let filter = Filter::all().eq(
+                "sender_account_number",
+                IonValue::String(account_number.clone()),
+            );
+            Box::pin(async move { find_pending_where(tx, &filter).await })
+        })
+        .await?;
+        pending.sort_by(|a, b| (a.created_at, a.id.as_str()).cmp(&(b.created_at, b.id.as_str())));
+        Ok(pending)
+    }
+
+    /// Records an owner's approval. Returns the transaction and whether every
+    /// owner has now approved it. Approving twice is not an error, so an
+    /// approval whose transfer failed to post can be sent again.
+    pub async fn approve(
+        &self,
+        id: String,
+        customer_id: String,
+    ) -> Result<(PendingTransaction, bool), AppError> {
+        let (pending, approved) = transaction_within(self.processor.as_ref(), |tx| {
+            let id = id.clone();
+            let customer_id = customer_id.clone();
+            Box::pin(async move {
+                let mut pending = find_pending(tx, &id).await?;
+                let account = find_account(tx, &pending.sender_account_number).await?;
+                let customer_ids = owners(tx, &account).await?;
+                if !customer_ids.contains(&customer_id) {
+                    let msg = format!(
+                        "Customer {} does not hold account {}",
+                        customer_id, account.account_number
+                    );
+                    return Err(AppError::new(Some(msg), ErrorType::Forbidden));
+                }
+                pending.ensure_pending()?;
+                if !pending.is_approved_by(&customer_id) {
+                    let approval = Approval::new(pending.id.clone(), customer_id);
+                    tx.insert(&approval).await?;
+                    pending.approvals.push(approval);
+                }
+                let approved = customer_ids.iter().all(|id| pending.is_approved_by(id));
+                Ok((pending, approved))
+            })
+        })
+        .await?;
+        info!(
+            "Customer {} approved pending transaction {} ({}/{})",
+            customer_id,
+            pending.id,
+            pending.approvals.len(),
+            if approved { "all" } else { "waiting" }
+        );
+        Ok((pending, approved))
+    }
+
+    /// Records what came of posting an approved transfer. An executed
+    /// transfer is recorded even over a failure written by a racing approval
+    /// that lost; a failure is only recorded while the transfer is pending.
+    pub async fn record_outcome(
+        &self,
+        id: String,
+        outcome: &Result<TransactionReceipt, AppError>,
+    ) -> Result<PendingTransaction, AppError> {
+        let (transaction_id, failure_reason) = match outcome {
+            Ok(receipt) => (Some(receipt.transaction_id.clone()), None),
+            Err(e) => (None, Some(e.message())),
+        };
+        let pending = transaction_within(self.processor.as_ref(), |tx| {
+            let id = id.clone();
+            let transaction_id = transaction_id.clone();
+            let failure_reason = failure_reason.clone();
+            Box::pin(async move {
+                let mut pending = find_pending(tx, &id).await?;
+                match transaction_id {
+                    Some(transaction_id) if pending.status != PendingStatus::EXECUTED => {
+                        pending.status = PendingStatus::EXECUTED;
+                        pending.transaction_id = Some(transaction_id);
+                        pending.failure_reason = None;
+                    }
+                    None if pending.status == PendingStatus::PENDING => {
+                        pending.status = PendingStatus::FAILED;
+                        pending.failure_reason = failure_reason;
+                    }
+                    _ => return Ok(pending),
+                }
+                pending.updated_at = chrono::Utc::now().into();
+                save(tx, &pending).await?;
+                Ok(pending)
+            })
+        })
+        .await?;
+        info!("Pending transaction {} is {}", pending.id, pending.status);
+        Ok(pending)
+    }
+}
+
+/// Customer ids of everyone holding the account, the primary holder first.
+pub async fn owners(
+    tx: &mut dyn LedgerTransaction,
+    account: &Account,
+) -> Result<Vec<String>, AppError> {
+    let mut customer_ids: Vec<String> = account.customer_id.iter().cloned().collect();
+    let filter = Filter::all().eq(
+        "account_number",
+        IonValue::String(account.account_number.clone()),
+    );
+    let docs = tx.find(ACCOUNT_OWNERS_TABLE, &filter).await?;
+    let mut joint = AccountOwner::from_documents(docs)?;
+    joint.sort_by_key(|owner| owner.created_at);
+    customer_ids.extend(joint.into_iter().map(|owner| owner.customer_id));
+    Ok(customer_ids)
+}
+
+async fn find_account(
+    tx: &mut dyn LedgerTransaction,
+    account_number: &str,
+) -> Result<Account, AppError> {
+    let filter = Filter::all().eq(
+        "account_number",
+        IonValue::String(account_number.to_string()),
+    );
+    match tx.find(ACCOUNTS_TABLE, &filter).await?.first() {
+        Some(doc) => Account::try_from(doc),
+        None => Err(AppError::from_type(ErrorType::AccountNotFound(
+            account_number.to_string(),
+        ))),
+    }
+}
+
+async fn find_pending(
+    tx: &mut dyn LedgerTransaction,
+    id: &str,
+) -> Result<PendingTransaction, AppError> {
+    let filter = Filter::all().eq("id", IonValue::String(id.to_string()));
+    match find_pending_where(tx, &filter).await?.pop() {
+        Some(pending) => Ok(pending),
+        None => Err(AppError::from_type(ErrorType::PendingTransactionNotFound(
+            id.to_string(),
+        ))),
+    }
+}
+
+/// Loads pending transactions with their approvals, expiring those whose
+/// time has run out on the way.
+async fn find_pending_where(
+    tx: &mut dyn LedgerTransaction,
+    filter: &Filter,
+) -> Result<Vec<PendingTransaction>, AppError> {
+    let docs = tx.find(PENDING_TRANSACTIONS_TABLE, filter).await?;
+    let mut found = PendingTransaction::from_documents(docs)?;
+    for pending in found.iter_mut() {
+        let filter = Filter::all().eq(
+            "pending_transaction_id",
+            IonValue::String(pending.id.clone()),
+        );
+        let mut approvals = Approval::from_documents(tx.find(APPROVALS_TABLE, &filter).await?)?;
+        approvals.sort_by_key(|approval| approval.approved_at);
+        pending.approvals = approvals;
+        if pending.expire() {
+            save(tx, pending).await?;
+            info!("Pending transaction {} expired", pending.id);
+        }
+    }
+    Ok(found)
+}
+
+async fn save(
+    tx: &mut dyn LedgerTransaction,
+    pending: &PendingTransaction,
+) -> Result<(), AppError> {
+    let filter = Filter::all().eq("id", IonValue::String(pending.id.clone()));
+    tx.update(
+        PENDING_TRANSACTIONS_TABLE,
+        &filter,
+        pending.outcome_params(),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::MemoryProcessor;
+    use crate::domain::{NewAccount, NewCustomer, TransactionType};
+    use crate::service::{AccountService, CustomerService};
+    use bigdecimal::BigDecimal;
+    use std::sync::Arc;
+
+    fn new_customer(first_name: &str) -> NewCustomer {
+        NewCustomer {
+            first_name: first_name.to_string(),
+            last_name: "James".to_string(),
+            phone: "2347038657970".to_string(),
+            email: None,
+            address: None,
+            date_of_birth: None,
+        }
+    }
+
+    fn transfer(sender: &str, recipient: &str, amount: u32) -> NewTransaction {
+        NewTransaction {
+            amount: BigDecimal::from(amount),
+            currency: None,
+            sender_account_number: Some(sender.to_string()),
+            recipient_account_number: recipient.to_string(),
+            transaction_type: TransactionType::TRANSFER,
+            fx_quote_id: None,
+        }
+    }
+
+    /// Opens an account held by Sam and Ada that needs both to sign, and
+    /// returns it with their customer ids.
+    async fn setup(approval_ttl: Duration) -> (JointAccountService, Storage, Account, Vec<String>) {
+        let processor: Storage = Arc::new(MemoryProcessor::new());
+        let customer_service = CustomerService::new(processor.clone());
+        let sam = customer_service
+            .create_customer(new_customer("Sam"))
+            .await
+            .unwrap();
+        let ada = customer_service
+            .create_customer(new_customer("Ada"))
+            .await
+            .unwrap();
+        let (_, account) = AccountService::new(processor.clone())
+            .create_account(NewAccount {
+                customer_id: Some(sam.id.clone()),
+                joint_customer_ids: vec![ada.id.clone()],
+                signing_rule: SigningRule::ALL,
+                ..NewAccount::default()
+            })
+            .await
+            .unwrap();
+        let joint_service = JointAccountService::new(processor.clone(), approval_ttl);
+        (joint_service, processor, account, vec![sam.id, ada.id])
+    }
+
+    #[actix_rt::test]
+    async fn test_transfer_waits_for_every_owner() {
+        let (joint_service, processor, account, owners) = setup(Duration::hours(1)).await;
+        let number = account.account_number.clone();
+        let found = joint_service.find_owners(number.clone()).await.unwrap();
+        assert_eq!(found.customer_ids, owners);
+        assert!(joint_service.needs_approval(number.clone()).await.unwrap());
+
+        let key = IdempotencyKey::new(
+            "token:sam".to_string(),
+            "pay-rent".to_string(),
+            "fp".to_string(),
+        )
+        .unwrap();
+        let transaction = transfer(&number, "0123456789", 10);
+        let pending = joint_service
+            .request(&transaction, Some(key.clone()), Some(owners[0].clone()))
+            .await
+            .unwrap();
+        assert_eq!(pending.status, PendingStatus::PENDING);
+        assert!(pending.is_approved_by(&owners[0]));
+
+        // A retry with the same key gets the same pending transaction.
+        let retried = joint_service
+            .request(&transaction, Some(key.clone()), None)
+            .await
+            .unwrap();
+        assert_eq!(retried.id, pending.id);
+        let err = joint_service
+            .request(&transfer(&number, "0123456789", 11), Some(key), None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::IdempotencyKeyReused));
+
+        let err = joint_service
+            .approve(pending.id.clone(), "stranger".to_string())
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::Forbidden));
+        let (_, approved) = joint_service
+            .approve(pending.id.clone(), owners[0].clone())
+            .await
+            .unwrap();
+        assert!(!approved);
+        let (pending, approved) = joint_service
+            .approve(pending.id.clone(), owners[1].clone())
+            .await
+            .unwrap();
+        assert!(approved);
+        assert_eq!(pending.approvals.len(), 2);
+
+        let failed: Result<TransactionReceipt, AppError> =
+            Err(AppError::from_type(ErrorType::InsufficientBalance));
+        let pending = joint_service
+            .record_outcome(pending.id, &failed)
+            .await
+            .unwrap();
+        assert_eq!(pending.status, PendingStatus::FAILED);
+        assert!(pending.failure_reason.is_some());
+        let listed = joint_service
+            .find_pending_for(number.clone())
+            .await
+            .unwrap();
+        assert_eq!(listed.len(), 1);
+
+        AccountService::new(processor)
+            .set_signing_rule(number.clone(), SigningRule::ANY_ONE)
+            .await
+            .unwrap();
+        assert!(!joint_service.needs_approval(number).await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_unapproved_transfer_expires() {
+        let (joint_service, _, account, owners) = setup(Duration::seconds(-1)).await;
+        let transaction = transfer(&account.account_number, "0123456789", 10);
+        let pending = joint_service
+            .request(&transaction, None, Some(owners[0].clone()))
+            .await
+            .unwrap();
+
+        let found = joint_service
+            .find_pending(pending.id.clone())
+            .await
+            .unwrap();
+        assert_eq!(found.status, PendingStatus::EXPIRED);
+        let err = joint_service
+            .approve(pending.id, owners[1].clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::PayloadError));
+    }
+}
@@ -1,4 +1,22 @@
 mod account_service;
+mod auth_service;
+mod customer_service;
+mod fx_service;
+mod hold_service;
+mod import_service;
+mod joint_service;
+mod schedule_service;
+mod scheduler;
+mod transaction_service;
 mod transfer_service;
 pub use account_service::AccountService;
+pub use auth_service::AuthService;
+pub use customer_service::CustomerService;
+pub use fx_service::FxService;
+pub use hold_service::HoldService;
+pub use import_service::ImportService;
+pub use joint_service::JointAccountService;
+pub use schedule_service::ScheduleService;
+pub use scheduler::Scheduler;
+pub use transaction_service::TransactionService;
 pub use transfer_service::TransferService;
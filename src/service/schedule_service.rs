@@ -0,0 +1,129 @@
+use crate::core::{transaction_within, Filter, Storage};
+use crate::domain::{
+    NewSchedule, Schedule, ScheduleQuery, ScheduleRun, ScheduleStatus, SCHEDULES_TABLE,
+    SCHEDULE_RUNS_TABLE,
+};
+use crate::error::{AppError, ErrorType};
+use ion_binary_rs::IonValue;
+
+pub struct ScheduleService {
+    processor: Storage,
+}
+
+impl ScheduleService {
+    pub fn new(processor: Storage) -> ScheduleService {
+        ScheduleService { processor }
+    }
+
+    /// Schedules transfers between two accounts of the same currency. The
+    /// accounts are checked now and again before every run.
+    pub async fn create_schedule(&self, new_schedule: NewSchedule) -> Result<Schedule, AppError> {
+        if new_schedule.sender_account_number == new_schedule.recipient_account_number {
+            return Err(AppError::new(
+                Some("Cannot schedule a transfer to the same account".to_string()),
+                ErrorType::PayloadError,
+            ));
+        }
+        let sender = self
+            .processor
+            .find_account(new_schedule.sender_account_number.clone())
+            .await?;
+        let recipient = self
+            .processor
+            .find_account(new_schedule.recipient_account_number.clone())
+            .await?;
+        sender.ensure_can_send()?;
+        recipient.ensure_can_receive()?;
+        if sender.currency != recipient.currency {
+            return Err(AppError::new(
+                Some("Scheduled transfers must be between accounts of one currency".to_string()),
+                ErrorType::CurrencyMismatch,
+            ));
+        }
+        let schedule = Schedule::new(new_schedule, sender.currency)?;
+        self.processor.insert(&schedule).await?;
+        info!(
+            "Scheduled {} {} {} from {} to {} starting {} ({})",
+            schedule.frequency,
+            schedule.amount,
+            schedule.currency,
+            schedule.sender_account_number,
+            schedule.recipient_account_number,
+            schedule.start_at,
+            schedule.id
+        );
+        Ok(schedule)
+    }
+
+    /// All schedules, or those sending from `account_number`, newest first.
+    pub async fn find_schedules(&self, query: ScheduleQuery) -> Result<Vec<Schedule>, AppError> {
+        let filter = match query.account_number {
+            Some(account_number) => {
+                Filter::all().eq("sender_account_number", IonValue::String(account_number))
+            }
+            None => Filter::all(),
+        };
+        let docs = self.processor.query(SCHEDULES_TABLE, &filter).await?;
+        let mut schedules = Schedule::from_documents(docs)?;
+        schedules.sort_by(|a, b| (b.created_at, b.id.as_str()).cmp(&(a.created_at, a.id.as_str())));
+        Ok(schedules)
+    }
+
+    pub async fn find_schedule(&self, id: String) -> Result<Schedule, AppError> {
+        let filter = Filter::all().eq("id", IonValue::String(id.clone()));
+        let docs = self.processor.query(SCHEDULES_TABLE, &filter).await?;
+        match Schedule::from_documents(docs)?.pop() {
+            Some(schedule) => Ok(schedule),
+            None => Err(AppError::from_type(ErrorType::ScheduleNotFound(id))),
+        }
+    }
+
+    /// Runs of the schedule, newest first.
+    pub async fn find_runs(&self, id: String) -> Result<Vec<ScheduleRun>, AppError> {
+        self.find_schedule(id.clone()).await?;
+        let filter = Filter::all().eq("schedule_id", IonValue::String(id));
+        let docs = self.processor.query(SCHEDULE_RUNS_TABLE, &filter).await?;
+        let mut runs = ScheduleRun::from_documents(docs)?;
+        runs.sort_by(|a, b| {
+            (b.created_at, b.due_at, b.attempt).cmp(&(a.created_at, a.due_at, a.attempt))
+        });
+        Ok(runs)
+    }
+
+    pub async fn pause_schedule(&self, id: String) -> Result<Schedule, AppError> {
+        self.change_status(id, ScheduleStatus::PAUSED).await
+    }
+
+    pub async fn resume_schedule(&self, id: String) -> Result<Schedule, AppError> {
+        self.change_status(id, ScheduleStatus::ACTIVE).await
+    }
+
+    pub async fn cancel_schedule(&self, id: String) -> Result<Schedule, AppError> {
+        self.change_status(id, ScheduleStatus::CANCELLED).await
+    }
+
+    async fn change_status(
+        &self,
+        id: String,
+        status: ScheduleStatus,
+    ) -> Result<Schedule, AppError> {
+        let schedule = transaction_within(self.processor.as_ref(), |tx| {
+            let id = id.clone();
+            Box::pin(async move {
+                let filter = Filter::all().eq("id", IonValue::String(id.clone()));
+                let docs = tx.find(SCHEDULES_TABLE, &filter).await?;
+                let mut schedule = match Schedule::from_documents(docs)?.pop() {
+                    Some(schedule) => schedule,
+                    None => return Err(AppError::from_type(ErrorType::ScheduleNotFound(id))),
+                };
+                schedule.change_status(status)?;
+                tx.update(SCHEDULES_TABLE, &filter, schedule.state_params())
+                    .await?;
+                Ok(schedule)
+            })
+        })
+        .await?;
+        info!("Schedule {} is now {}", schedule.id, schedule.status);
+        Ok(schedule)
+    }
+}
@@ -0,0 +1,545 @@
+use super::{JointAccountService, TransferService};
+use crate::core::{transaction_within, Filter, Storage};
+use crate::domain::{
+    AccountStatus, IdempotencyKey, Schedule, ScheduleRetry, ScheduleRun, ScheduleStatus,
+    TransactionReceipt, SCHEDULER_SCOPE, SCHEDULES_TABLE,
+};
+use crate::error::{AppError, ErrorType};
+use chrono::prelude::*;
+use chrono::Duration;
+use ion_binary_rs::IonValue;
+
+/// How long a claimed attempt is left to the instance that claimed it. An
+/// attempt that is not recorded by then, e.g. because the instance stopped,
+/// is due again.
+const CLAIM_SECONDS: i64 = 300;
+
+/// Background job that makes the transfers of due schedules.
+pub struct Scheduler {
+    processor: Storage,
+    transfer_service: TransferService,
+    joint_service: JointAccountService,
+    retry: ScheduleRetry,
+}
+
+impl Scheduler {
+    pub fn new(
+        processor: Storage,
+        transfer_service: TransferService,
+        joint_service: JointAccountService,
+        retry: ScheduleRetry,
+    ) -> Scheduler {
+        Scheduler {
+            processor,
+            transfer_service,
+            joint_service,
+            retry,
+        }
+    }
+
+    /// Runs due schedules every `poll` for as long as the runtime is up.
+    pub async fn run(self, poll: std::time::Duration) {
+        let mut interval = actix_web::rt::time::interval(poll);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.run_due(Utc::now()).await {
+                error!("Unable to run due schedules: {}", e.message());
+            }
+        }
+    }
+
+    /// Runs every active schedule due by `now` once and returns how many
+    /// this instance ran. Each attempt is claimed first, so schedules another
+    /// instance is running are skipped. A schedule that cannot be run is
+    /// logged and left for a later poll.
+    pub async fn run_due(&self, now: DateTime<Utc>) -> Result<usize, AppError> {
+        let filter = Filter::all().eq(
+            "status",
+            IonValue::String(ScheduleStatus::ACTIVE.to_string()),
+        );
+        let docs = self.processor.query(SCHEDULES_TABLE, &filter).await?;
+        let due: Vec<Schedule> = Schedule::from_documents(docs)?
+            .into_iter()
+            .filter(|schedule| schedule.is_due(now))
+            .collect();
+        let mut ran = 0;
+        for schedule in &due {
+            let result = match self.claim(schedule, now).await {
+                Ok(Some(claimed)) => {
+                    ran += 1;
+                    self.run_schedule(&claimed, now).await
+                }
+                Ok(None) => continue,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                error!("Unable to run schedule {}: {}", schedule.id, e.message());
+            }
+        }
+        Ok(ran)
+    }
+
+    /// Claims the attempt `seen` was due for, in a ledger transaction, so
+    /// that one instance makes it. Returns the claimed schedule, or None when
+    /// the attempt is no longer due, e.g. because another instance claimed
+    /// or recorded it.
+    async fn claim(
+        &self,
+        seen: &Schedule,
+        now: DateTime<Utc>,
+    ) -> Result<Option<Schedule>, AppError> {
+        transaction_within(self.processor.as_ref(), |tx| {
+            let seen = seen.clone();
+            Box::pin(async move {
+                let filter = Filter::all().eq("id", IonValue::String(seen.id.clone()));
+                let docs = tx.find(SCHEDULES_TABLE, &filter).await?;
+                let mut schedule = match Schedule::from_documents(docs)?.pop() {
+                    Some(schedule)
+                        if schedule.is_due(now)
+                            && schedule.due_at == seen.due_at
+                            && schedule.failed_attempts == seen.failed_attempts =>
+                    {
+                        schedule
+                    }
+                    _ => return Ok(None),
+                };
+                schedule.claim(now + Duration::seconds(CLAIM_SECONDS));
+                tx.update(SCHEDULES_TABLE, &filter, schedule.state_params())
+                    .await?;
+                Ok(Some(schedule))
+            })
+        })
+        .await
+    }
+
+    async fn run_schedule(&self, schedule: &Schedule, now: DateTime<Utc>) -> Result<(), AppError> {
+        let outcome = self
+            .transfer(schedule)
+            .await
+            .map(|receipt| receipt.transaction_id);
+        let run = ScheduleRun::new(schedule, &outcome);
+        let abandoned = match &outcome {
+            Ok(_) => false,
+            Err(_) => {
+                self.has_closed_account(schedule).await? || self.needs_approval(schedule).await?
+            }
+        };
+        let retry = self.retry;
+        let ran = schedule.clone();
+        let recorded = transaction_within(self.processor.as_ref(), |tx| {
+            let ran = ran.clone();
+            let run = run.clone();
+            Box::pin(async move {
+                let filter = Filter::all().eq("id", IonValue::String(ran.id.clone()));
+                let docs = tx.find(SCHEDULES_TABLE, &filter).await?;
+                let mut schedule = match Schedule::from_documents(docs)?.pop() {
+                    Some(schedule) => schedule,
+                    None => return Err(AppError::from_type(ErrorType::ScheduleNotFound(ran.id))),
+                };
+                // Another instance took the attempt over after the claim ran
+                // out and recorded it first; the idempotency key kept the
+                // money from moving twice.
+                if schedule.due_at != ran.due_at || schedule.failed_attempts != ran.failed_attempts
+                {
+                    return Ok(None);
+                }
+                match &run.error {
+                    None => schedule.succeeded(now),
+                    Some(error) if abandoned => schedule.abandon(error.clone(), now),
+                    Some(error) => {
+                        schedule.failed(error.clone(), &retry, now);
+                    }
+                }
+                tx.update(SCHEDULES_TABLE, &filter, schedule.state_params())
+                    .await?;
+                tx.insert(&run).await?;
+                Ok(Some(schedule))
+            })
+        })
+        .await?;
+        if let Some(schedule) = recorded {
+            match &run.error {
+                None => info!(
+                    "Ran schedule {} due {} ({})",
+                    schedule.id,
+                    run.due_at,
+                    run.transaction_id.as_deref().unwrap_or_default()
+                ),
+                Some(error) => warn!(
+                    "Schedule {} due {} failed on attempt {}: {}; it is now {} with next run at {:?}",
+                    schedule.id,
+                    run.due_at,
+                    run.attempt,
+                    error,
+                    schedule.status,
+                    schedule.next_run_at
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes the transfer for the schedule's current occurrence with the same
+    /// checks as a transfer request. A retry of an occurrence that was paid
+    /// but not recorded gets the original receipt back. Transfers from an
+    /// account that has come to need the approval of all its owners are not
+    /// made.
+    async fn transfer(&self, schedule: &Schedule) -> Result<TransactionReceipt, AppError> {
+        let fingerprint = format!(
+            "SCHEDULE|{}|{}|{}|{}",
+            schedule.amount.normalized(),
+            schedule.currency,
+            schedule.sender_account_number,
+            schedule.recipient_account_number
+        );
+        let idempotency_key = IdempotencyKey::new(
+            SCHEDULER_SCOPE.to_string(),
+            schedule.idempotency_key(),
+            fingerprint,
+        )?;
+        if let Some(receipt) = self.processor.replay(idempotency_key.clone()).await? {
+            return Ok(receipt);
+        }
+        if self.needs_approval(schedule).await? {
+            let msg = format!(
+                "Transfers from account {} need the approval of all its owners and cannot be scheduled",
+                schedule.sender_account_number
+            );
+            return Err(AppError::new(Some(msg), ErrorType::PayloadError));
+        }
+        let sender = self
+            .processor
+            .find_account(schedule.sender_account_number.clone())
+            .await?;
+        let recipient = self
+            .processor
+            .find_account(schedule.recipient_account_number.clone())
+            .await?;
+        sender.ensure_can_send()?;
+        recipient.ensure_can_receive()?;
+        self.transfer_service
+            .transfer(
+                &sender,
+                &recipient,
+                schedule.amount.clone(),
+                None,
+                Some(idempotency_key),
+            )
+            .await
+    }
+
+    /// The signing rule is checked on every run, since owners may have been
+    /// added or the rule changed since the schedule was created. A missing
+    /// sender is left for the transfer to report.
+    async fn needs_approval(&self, schedule: &Schedule) -> Result<bool, AppError> {
+        match self
+            .joint_service
+            .needs_approval(schedule.sender_account_number.clone())
+            .await
+        {
+            Err(AppError {
+                error_type: ErrorType::AccountNotFound(_),
+                ..
+            }) => Ok(false),
+            result => result,
+        }
+    }
+
+    /// A schedule whose sender or recipient has been closed, or whose sender
+    /// now needs the approval of all its owners, can never run on its own
+    /// again, so it fails without waiting for retries.
+    async fn has_closed_account(&self, schedule: &Schedule) -> Result<bool, AppError> {
+        for account_number in &[
+            &schedule.sender_account_number,
+            &schedule.recipient_account_number,
+        ] {
+            match self
+                .processor
+                .find_account(account_number.to_string())
+                .await
+            {
+                Ok(account) if account.status == AccountStatus::CLOSED => return Ok(true),
+                Ok(_) => (),
+                Err(e) => match e.error_type {
+                    ErrorType::AccountNotFound(_) => return Ok(true),
+                    _ => return Err(e),
+                },
+            }
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{MemoryProcessor, StaticRateProvider};
+    use crate::domain::{
+        Account, AccountClosure, Currency, Frequency, LimitPolicy, NewAccount, NewCustomer,
+        NewSchedule, SigningRule, SystemAccounts, TransactionType,
+    };
+    use crate::service::{AccountService, CustomerService, FxService, ScheduleService};
+    use bigdecimal::BigDecimal;
+    use std::sync::Arc;
+
+    struct Setup {
+        processor: Storage,
+        scheduler: Scheduler,
+        sender: String,
+        recipient: String,
+    }
+
+    /// A sender holding 100 USD, an empty recipient and a scheduler that
+    /// retries a failed run once, straight away.
+    async fn setup() -> Setup {
+        let processor: Storage = Arc::new(MemoryProcessor::new());
+        let system_accounts = SystemAccounts {
+            cash_in: "CASH-IN".to_string(),
+            cash_out: "CASH-OUT".to_string(),
+            suspense: "SUSPENSE".to_string(),
+            fx_position: "FX-POSITION".to_string(),
+        };
+        AccountService::new(processor.clone())
+            .open_system_accounts(&system_accounts)
+            .await
+            .unwrap();
+        let sender = Account::new("Sam James".to_string(), String::new(), Currency::USD);
+        let recipient = Account::new("Ada Obi".to_string(), String::new(), Currency::USD);
+        processor.insert(&sender).await.unwrap();
+        processor.insert(&recipient).await.unwrap();
+        processor
+            .debit_credit(
+                sender.account_number.clone(),
+                system_accounts.cash_in_for(Currency::USD),
+                BigDecimal::from(100),
+                TransactionType::CREDIT,
+                None,
+            )
+            .await
+            .unwrap();
+        let fx_service = FxService::new(
+            processor.clone(),
+            Arc::new(StaticRateProvider::default()),
+            Duration::seconds(60),
+        );
+        let scheduler = Scheduler::new(
+            processor.clone(),
+            TransferService::new(
+                processor.clone(),
+                system_accounts,
+                fx_service,
+                Arc::new(LimitPolicy::default()),
+            ),
+            JointAccountService::new(processor.clone(), Duration::hours(1)),
+            ScheduleRetry {
+                max_attempts: 2,
+                delay: Duration::zero(),
+            },
+        );
+        Setup {
+            processor,
+            scheduler,
+            sender: sender.account_number,
+            recipient: recipient.account_number,
+        }
+    }
+
+    async fn schedule(setup: &Setup, amount: u32, frequency: Frequency) -> Schedule {
+        let new_schedule = NewSchedule {
+            sender_account_number: setup.sender.clone(),
+            recipient_account_number: setup.recipient.clone(),
+            amount: BigDecimal::from(amount),
+            currency: None,
+            frequency,
+            cron: None,
+            start_at: None,
+            end_at: None,
+        };
+        ScheduleService::new(setup.processor.clone())
+            .create_schedule(new_schedule)
+            .await
+            .unwrap()
+    }
+
+    async fn balance(setup: &Setup, account_number: &str) -> BigDecimal {
+        let account = setup
+            .processor
+            .find_account(account_number.to_string())
+            .await
+            .unwrap();
+        account.balance
+    }
+
+    #[actix_rt::test]
+    async fn test_due_schedule_is_paid_once_per_occurrence() {
+        let setup = setup().await;
+        let created = schedule(&setup, 30, Frequency::DAILY).await;
+        let now = Utc::now();
+
+        assert_eq!(setup.scheduler.run_due(now).await.unwrap(), 1);
+        assert_eq!(setup.scheduler.run_due(now).await.unwrap(), 0);
+        assert_eq!(balance(&setup, &setup.sender).await, BigDecimal::from(70));
+        assert_eq!(
+            balance(&setup, &setup.recipient).await,
+            BigDecimal::from(30)
+        );
+
+        let schedule_service = ScheduleService::new(setup.processor.clone());
+        let schedule = schedule_service
+            .find_schedule(created.id.clone())
+            .await
+            .unwrap();
+        assert_eq!(schedule.status, ScheduleStatus::ACTIVE);
+        assert_eq!(
+            schedule.due_at,
+            created.due_at.map(|at| at + Duration::days(1))
+        );
+        let runs = schedule_service.find_runs(created.id).await.unwrap();
+        assert_eq!(runs.len(), 1);
+        assert!(runs[0].transaction_id.is_some());
+    }
+
+    #[actix_rt::test]
+    async fn test_claimed_schedule_is_left_to_its_instance() {
+        let setup = setup().await;
+        let created = schedule(&setup, 30, Frequency::DAILY).await;
+        let now = Utc::now();
+
+        // Another instance claimed the attempt and stopped before running it.
+        let claimed = setup.scheduler.claim(&created, now).await.unwrap();
+        assert!(claimed.is_some());
+        assert!(setup
+            .scheduler
+            .claim(&created, now)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(setup.scheduler.run_due(now).await.unwrap(), 0);
+        assert_eq!(balance(&setup, &setup.sender).await, BigDecimal::from(100));
+
+        // Once the claim runs out the attempt is due again.
+        let later = now + Duration::seconds(CLAIM_SECONDS);
+        assert_eq!(setup.scheduler.run_due(later).await.unwrap(), 1);
+        assert_eq!(setup.scheduler.run_due(later).await.unwrap(), 0);
+        assert_eq!(balance(&setup, &setup.sender).await, BigDecimal::from(70));
+    }
+
+    #[actix_rt::test]
+    async fn test_failed_run_is_retried_before_schedule_fails() {
+        let setup = setup().await;
+        let created = schedule(&setup, 150, Frequency::ONCE).await;
+        let now = Utc::now();
+
+        assert_eq!(setup.scheduler.run_due(now).await.unwrap(), 1);
+        assert_eq!(setup.scheduler.run_due(now).await.unwrap(), 1);
+        assert_eq!(setup.scheduler.run_due(now).await.unwrap(), 0);
+
+        let schedule_service = ScheduleService::new(setup.processor.clone());
+        let schedule = schedule_service
+            .find_schedule(created.id.clone())
+            .await
+            .unwrap();
+        assert_eq!(schedule.status, ScheduleStatus::FAILED);
+        assert_eq!(schedule.next_run_at, None);
+        let runs = schedule_service.find_runs(created.id).await.unwrap();
+        let attempts: Vec<u32> = runs.iter().map(|run| run.attempt).collect();
+        assert_eq!(attempts, vec![2, 1]);
+        assert_eq!(balance(&setup, &setup.sender).await, BigDecimal::from(100));
+    }
+
+    #[actix_rt::test]
+    async fn test_schedule_to_closed_account_fails_at_once() {
+        let setup = setup().await;
+        let created = schedule(&setup, 30, Frequency::WEEKLY).await;
+        let closure = AccountClosure {
+            settlement_account_number: None,
+            reason: "Moved abroad".to_string(),
+        };
+        AccountService::new(setup.processor.clone())
+            .close_account(setup.recipient.clone(), closure)
+            .await
+            .unwrap();
+
+        assert_eq!(setup.scheduler.run_due(Utc::now()).await.unwrap(), 1);
+        let schedule = ScheduleService::new(setup.processor.clone())
+            .find_schedule(created.id)
+            .await
+            .unwrap();
+        assert_eq!(schedule.status, ScheduleStatus::FAILED);
+        assert!(schedule.last_error.is_some());
+        assert_eq!(balance(&setup, &setup.sender).await, BigDecimal::from(100));
+    }
+
+    #[actix_rt::test]
+    async fn test_schedule_stops_once_all_owners_must_approve() {
+        let setup = setup().await;
+        let customer_service = CustomerService::new(setup.processor.clone());
+        let mut customer_ids = Vec::new();
+        for first_name in &["Sam", "Ada"] {
+            let customer = customer_service
+                .create_customer(NewCustomer {
+                    first_name: first_name.to_string(),
+                    last_name: "James".to_string(),
+                    phone: "2347038657970".to_string(),
+                    email: None,
+                    address: None,
+                    date_of_birth: None,
+                })
+                .await
+                .unwrap();
+            customer_ids.push(customer.id);
+        }
+        let account_service = AccountService::new(setup.processor.clone());
+        let (_, joint) = account_service
+            .create_account(NewAccount {
+                customer_id: Some(customer_ids[0].clone()),
+                joint_customer_ids: vec![customer_ids[1].clone()],
+                signing_rule: SigningRule::ANY_ONE,
+                ..NewAccount::default()
+            })
+            .await
+            .unwrap();
+        setup
+            .processor
+            .debit_credit(
+                joint.account_number.clone(),
+                "CASH-IN-USD".to_string(),
+                BigDecimal::from(100),
+                TransactionType::CREDIT,
+                None,
+            )
+            .await
+            .unwrap();
+        let created = ScheduleService::new(setup.processor.clone())
+            .create_schedule(NewSchedule {
+                sender_account_number: joint.account_number.clone(),
+                recipient_account_number: setup.recipient.clone(),
+                amount: BigDecimal::from(30),
+                currency: None,
+                frequency: Frequency::DAILY,
+                cron: None,
+                start_at: None,
+                end_at: None,
+            })
+            .await
+            .unwrap();
+
+        // The rule changes after the schedule was created.
+        account_service
+            .set_signing_rule(joint.account_number.clone(), SigningRule::ALL)
+            .await
+            .unwrap();
+        assert_eq!(setup.scheduler.run_due(Utc::now()).await.unwrap(), 1);
+
+        let schedule = ScheduleService::new(setup.processor.clone())
+            .find_schedule(created.id)
+            .await
+            .unwrap();
+        assert_eq!(schedule.status, ScheduleStatus::FAILED);
+        assert!(schedule.last_error.unwrap().contains("approval"));
+        assert_eq!(
+            balance(&setup, &joint.account_number).await,
+            BigDecimal::from(100)
+        );
+    }
+}
@@ -0,0 +1,527 @@
+use crate::core::{read_within, Filter, LedgerTransaction, Storage};
+use crate::domain::{
+    Account, LedgerEntry, Statement, StatementLine, StatementQuery, Transaction, TransactionPage,
+    TransactionQuery, TransactionType, ACCOUNTS_TABLE, LEDGER_ENTRIES_TABLE, TRANSACTIONS_TABLE,
+};
+use crate::error::{AppError, ErrorType};
+use bigdecimal::BigDecimal;
+use chrono::prelude::*;
+use chrono::Duration;
+use ion_binary_rs::IonValue;
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 100;
+
+pub struct TransactionService {
+    processor: Storage,
+}
+
+impl TransactionService {
+    pub fn new(processor: Storage) -> TransactionService {
+        TransactionService { processor }
+    }
+
+    /// A page of the account's journal, newest first. The query's dates, type,
+    /// cursor and page size are all applied by the storage query.
+    pub async fn find_transactions(
+        &self,
+        account_number: String,
+        query: TransactionQuery,
+    ) -> Result<TransactionPage, AppError> {
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+        let mut filter = Filter::all()
+            .descending(&["created_at", "id"])
+            .limit(limit + 1);
+        if let Some(from) = query.from {
+            filter = filter.gte("created_at", start_of(from));
+        }
+        if let Some(to) = query.to {
+            filter = filter.lt("created_at", start_of(to + Duration::days(1)));
+        }
+        if let Some(transaction_type) = &query.transaction_type {
+            filter = filter.eq(
+                "transaction_type",
+                IonValue::String(transaction_type.to_string()),
+            );
+        }
+        if let Some(cursor) = &query.cursor {
+            let (timestamp, id) = parse_cursor(cursor)?;
+            let created_at: DateTime<FixedOffset> = Utc.timestamp_nanos(timestamp).into();
+            filter = filter.before(
+                ("created_at", IonValue::DateTime(created_at)),
+                ("id", IonValue::String(id.to_string())),
+            );
+        }
+
+        // Money leaves through the sender and arrives at the recipient; each
+        // side is read a page at a time and the two pages are merged.
+        let journal = read_within(self.processor.as_ref(), |tx| {
+            let account_number = account_number.clone();
+            let filter = filter.clone();
+            Box::pin(async move {
+                let mut journal = HashMap::new();
+                for field in &["sender_account_number", "recipient_account_number"] {
+                    let filter = filter
+                        .clone()
+                        .eq(field, IonValue::String(account_number.clone()));
+                    let docs = tx.find(TRANSACTIONS_TABLE, &filter).await?;
+                    for transaction in Transaction::from_documents(docs)? {
+                        journal.insert(transaction.id.clone(), transaction);
+                    }
+                }
+                Ok(journal)
+            })
+        })
+        .await?;
+        let mut transactions: Vec<Transaction> = journal.into_values().collect();
+        transactions
+            .sort_by(|a, b| (b.created_at, b.id.as_str()).cmp(&(a.created_at, a.id.as_str())));
+
+        let next_cursor = if transactions.len() > limit {
+            Some(transactions[limit - 1].cursor())
+        } else {
+            None
+        };
+        transactions.truncate(limit);
+        Ok(TransactionPage {
+            transactions,
+            next_cursor,
+        })
+    }
+
+    /// Statement of the account over the query period, built from its ledger
+    /// entries in that period. The closing balance is the account balance less
+    /// whatever moved after the period, and the opening balance is the closing
+    /// balance less the period's entries, so balances set before the ledger
+    /// recorded every movement are carried in as part of the opening balance.
+    /// The account and its entries are read in one ledger transaction so they
+    /// describe the same moment.
+    pub async fn statement(
+        &self,
+        account_number: String,
+        query: &StatementQuery,
+    ) -> Result<Statement, AppError> {
+        let to = query.to.unwrap_or_else(|| Utc::today().naive_utc());
+        let after = start_of(to + Duration::days(1));
+        let since = query.from.map(start_of);
+        let (account, mut entries, later, journal) = read_within(self.processor.as_ref(), |tx| {
+            let account_number = account_number.clone();
+            let after = after.clone();
+            let since = since.clone();
+            Box::pin(async move {
+                let filter =
+                    Filter::all().eq("account_number", IonValue::String(account_number.clone()));
+                let account = match tx.find(ACCOUNTS_TABLE, &filter).await?.first() {
+                    Some(doc) => Account::try_from(doc)?,
+                    None => {
+                        return Err(AppError::from_type(ErrorType::AccountNotFound(
+                            account_number,
+                        )))
+                    }
+                };
+                let mut period = Filter::all().lt("created_at", after.clone());
+                // A journal entry is written just after its postings, so it is
+                // never earlier than them.
+                let mut journal_filter = Filter::all();
+                if let Some(since) = since {
+                    period = period.gte("created_at", since.clone());
+                    journal_filter = journal_filter.gte("created_at", since);
+                }
+                let entries = find_entries(tx, &account_number, &period).await?;
+                let later_filter = Filter::all().gte("created_at", after);
+                let later = find_entries(tx, &account_number, &later_filter).await?;
+                let journal = find_journal(tx, &account_number, &journal_filter).await?;
+                Ok((account, entries, later, journal))
+            })
+        })
+        .await?;
+        let from = query
+            .from
+            .unwrap_or_else(|| account.created_at.with_timezone(&Utc).date().naive_utc());
+        if from > to {
+            return Err(AppError::new(
+                Some("from cannot be after to".to_string()),
+                ErrorType::PayloadError,
+            ));
+        }
+
+        entries.sort_by(|a, b| {
+            (a.created_at, a.transaction_id.as_str(), a.id.as_str()).cmp(&(
+                b.created_at,
+                b.transaction_id.as_str(),
+                b.id.as_str(),
+            ))
+        });
+        let journal: HashMap<String, Transaction> = journal
+            .into_iter()
+            .map(|transaction| (transaction.id.clone(), transaction))
+            .collect();
+
+        let signed = |entry: &LedgerEntry| {
+            let amount = account.currency.round(&entry.amount);
+            if entry.credit_account_number == account_number {
+                amount
+            } else {
+                -amount
+            }
+        };
+        let zero = account.currency.round(&BigDecimal::from(0));
+        let sum = |entries: &[LedgerEntry]| {
+            entries
+                .iter()
+                .fold(zero.clone(), |sum, entry| sum + signed(entry))
+        };
+        let closing_balance = account.balance.clone() - sum(&later);
+        let opening_balance = closing_balance - sum(&entries);
+
+        let mut balance = opening_balance.clone();
+        let mut total_credits = zero.clone();
+        let mut total_debits = zero.clone();
+        let mut lines = Vec::new();
+        for entry in &entries {
+            let amount = signed(entry);
+            if amount > zero {
+                total_credits += amount.clone();
+            } else {
+                total_debits -= amount.clone();
+            }
+            balance += amount.clone();
+            let (transaction_type, description) = describe(entry, &account_number, &journal);
+            lines.push(StatementLine {
+                transaction_id: entry.transaction_id.clone(),
+                date: entry.created_at,
+                transaction_type,
+                description,
+                amount,
+                balance: balance.clone(),
+            });
+        }
+        Ok(Statement {
+            account_number,
+            name: account.name,
+            currency: account.currency,
+            from,
+            to,
+            opening_balance,
+            total_credits,
+            total_debits,
+            closing_balance: balance,
+            lines,
+            generated_at: Utc::now(),
+        })
+    }
+}
+
+/// Ledger entries matching `filter` on either side of `account_number`, read
+/// through `tx`, in no particular order.
+async fn find_entries(
+    tx: &mut dyn LedgerTransaction,
+    account_number: &str,
+    filter: &Filter,
+) -> Result<Vec<LedgerEntry>, AppError> {
+    let mut entries = HashMap::new();
+    for field in &["debit_account_number", "credit_account_number"] {
+        let filter = filter
+            .clone()
+            .eq(field, IonValue::String(account_number.to_string()));
+        for doc in &tx.find(LEDGER_ENTRIES_TABLE, &filter).await? {
+            let entry: LedgerEntry = doc.try_into()?;
+            entries.insert(entry.id.clone(), entry);
+        }
+    }
+    Ok(entries.into_values().collect())
+}
+
+/// Journal entries matching `filter` that moved money in or out of
+/// `account_number`, read through `tx`, in no particular order.
+async fn find_journal(
+    tx: &mut dyn LedgerTransaction,
+    account_number: &str,
+    filter: &Filter,
+) -> Result<Vec<Transaction>, AppError> {
+    let mut journal = HashMap::new();
+    for field in &["sender_account_number", "recipient_account_number"] {
+        let filter = filter
+            .clone()
+            .eq(field, IonValue::String(account_number.to_string()));
+        let docs = tx.find(TRANSACTIONS_TABLE, &filter).await?;
+        for transaction in Transaction::from_documents(docs)? {
+            journal.insert(transaction.id.clone(), transaction);
+        }
+    }
+    Ok(journal.into_values().collect())
+}
+
+/// Type and description of an entry, from the journal entry it belongs to.
+/// Postings between system accounts have no journal entry and name the
+/// other account instead.
+fn describe(
+    entry: &LedgerEntry,
+    account_number: &str,
+    journal: &HashMap<String, Transaction>,
+) -> (Option<TransactionType>, String) {
+    let transaction = match journal.get(&entry.transaction_id) {
+        Some(transaction) => transaction,
+        None if entry.credit_account_number == account_number => {
+            return (None, format!("From {}", entry.debit_account_number))
+        }
+        None => return (None, format!("To {}", entry.credit_account_number)),
+    };
+    let description = match transaction.transaction_type {
+        TransactionType::CREDIT => "Deposit".to_string(),
+        TransactionType::DEBIT => "Withdrawal".to_string(),
+        TransactionType::TRANSFER
+            if transaction.sender_account_number.as_deref() == Some(account_number) =>
+        {
+            format!("Transfer to {}", transaction.recipient_account_number)
+        }
+        TransactionType::TRANSFER => format!(
+            "Transfer from {}",
+            transaction
+                .sender_account_number
+                .as_deref()
+                .unwrap_or_default()
+        ),
+    };
+    (Some(transaction.transaction_type.clone()), description)
+}
+
+/// Midnight UTC at the start of `day`.
+fn start_of(day: NaiveDate) -> IonValue {
+    IonValue::DateTime(DateTime::<Utc>::from_utc(day.and_hms(0, 0, 0), Utc).into())
+}
+
+fn parse_cursor(cursor: &str) -> Result<(i64, &str), AppError> {
+    let invalid = || {
+        AppError::new(
+            Some("Invalid pagination cursor".to_string()),
+            ErrorType::PayloadError,
+        )
+    };
+    let mut parts = cursor.splitn(2, '.');
+    let timestamp = parts
+        .next()
+        .and_then(|t| t.parse::<i64>().ok())
+        .ok_or_else(invalid)?;
+    let id = parts.next().ok_or_else(invalid)?;
+    Ok((timestamp, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::MemoryProcessor;
+    use crate::domain::{Account, Currency, TransactionType};
+    use bigdecimal::BigDecimal;
+    use std::sync::Arc;
+
+    async fn setup() -> (Storage, String, String) {
+        let processor: Storage = Arc::new(MemoryProcessor::new());
+        let mut numbers = Vec::new();
+        for _ in 0..3 {
+            let account = Account::new(
+                "Sam James".to_string(),
+                "2347038657970".to_string(),
+                Currency::USD,
+            );
+            processor.insert(&account).await.unwrap();
+            numbers.push(account.account_number);
+        }
+        let (first, second, cash) = (numbers[0].clone(), numbers[1].clone(), numbers[2].clone());
+        processor
+            .debit_credit(
+                first.clone(),
+                cash.clone(),
+                BigDecimal::from(100),
+                TransactionType::CREDIT,
+                None,
+            )
+            .await
+            .unwrap();
+        processor
+            .transfer(
+                first.clone(),
+                second.clone(),
+                BigDecimal::from(30),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        processor
+            .debit_credit(
+                first.clone(),
+                cash.clone(),
+                BigDecimal::from(10),
+                TransactionType::DEBIT,
+                None,
+            )
+            .await
+            .unwrap();
+        processor
+            .debit_credit(
+                second.clone(),
+                cash,
+                BigDecimal::from(5),
+                TransactionType::CREDIT,
+                None,
+            )
+            .await
+            .unwrap();
+        (processor, first, second)
+    }
+
+    fn types(page: &TransactionPage) -> Vec<TransactionType> {
+        page.transactions
+            .iter()
+            .map(|t| t.transaction_type.clone())
+            .collect()
+    }
+
+    #[actix_rt::test]
+    async fn test_history_is_newest_first() {
+        let (processor, first, _) = setup().await;
+        let service = TransactionService::new(processor);
+
+        let page = service
+            .find_transactions(first, TransactionQuery::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            types(&page),
+            vec![
+                TransactionType::DEBIT,
+                TransactionType::TRANSFER,
+                TransactionType::CREDIT
+            ]
+        );
+        assert!(page.next_cursor.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_history_pages_with_cursor() {
+        let (processor, first, _) = setup().await;
+        let service = TransactionService::new(processor);
+
+        let query = TransactionQuery {
+            limit: Some(2),
+            ..TransactionQuery::default()
+        };
+        let page = service
+            .find_transactions(first.clone(), query)
+            .await
+            .unwrap();
+        assert_eq!(page.transactions.len(), 2);
+
+        let query = TransactionQuery {
+            limit: Some(2),
+            cursor: page.next_cursor,
+            ..TransactionQuery::default()
+        };
+        let page = service.find_transactions(first, query).await.unwrap();
+        assert_eq!(types(&page), vec![TransactionType::CREDIT]);
+        assert!(page.next_cursor.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_history_filters_by_type() {
+        let (processor, _, second) = setup().await;
+        let service = TransactionService::new(processor);
+
+        let query = TransactionQuery {
+            transaction_type: Some(TransactionType::TRANSFER),
+            ..TransactionQuery::default()
+        };
+        let page = service.find_transactions(second, query).await.unwrap();
+        assert_eq!(types(&page), vec![TransactionType::TRANSFER]);
+    }
+
+    #[actix_rt::test]
+    async fn test_invalid_cursor_is_rejected() {
+        let (processor, first, _) = setup().await;
+        let service = TransactionService::new(processor);
+
+        let query = TransactionQuery {
+            cursor: Some("not-a-cursor".to_string()),
+            ..TransactionQuery::default()
+        };
+        let err = service.find_transactions(first, query).await.unwrap_err();
+        assert!(matches!(err.error_type, ErrorType::PayloadError));
+    }
+
+    #[actix_rt::test]
+    async fn test_statement_reconciles_with_balance() {
+        let (processor, first, second) = setup().await;
+        let service = TransactionService::new(processor.clone());
+
+        let statement = service
+            .statement(first.clone(), &StatementQuery::default())
+            .await
+            .unwrap();
+        let balances: Vec<BigDecimal> = statement
+            .lines
+            .iter()
+            .map(|line| line.balance.clone())
+            .collect();
+        assert_eq!(
+            balances,
+            vec![
+                BigDecimal::from(100),
+                BigDecimal::from(70),
+                BigDecimal::from(60)
+            ]
+        );
+        assert_eq!(statement.opening_balance, BigDecimal::from(0));
+        assert_eq!(statement.total_credits, BigDecimal::from(100));
+        assert_eq!(statement.total_debits, BigDecimal::from(40));
+        let account = processor.find_account(first.clone()).await.unwrap();
+        assert_eq!(statement.closing_balance, account.balance);
+        assert_eq!(
+            statement.lines[1].description,
+            format!("Transfer to {}", second)
+        );
+
+        // A period that ended yesterday is before any of it.
+        let yesterday = Utc::today().naive_utc().pred();
+        let query = StatementQuery {
+            to: Some(yesterday),
+            from: Some(yesterday),
+            ..StatementQuery::default()
+        };
+        let statement = service.statement(first, &query).await.unwrap();
+        assert!(statement.lines.is_empty());
+        assert_eq!(statement.closing_balance, BigDecimal::from(0));
+        assert_eq!(statement.to_csv().unwrap().lines().count(), 3);
+        assert!(statement.to_text().contains("Closing balance"));
+    }
+
+    #[actix_rt::test]
+    async fn test_balance_from_before_the_ledger_opens_the_statement() {
+        let (processor, first, _) = setup().await;
+        // The balance was set before the ledger recorded every movement.
+        let mut tx = processor.begin().await.unwrap();
+        let filter = Filter::all().eq("account_number", IonValue::String(first.clone()));
+        tx.update(
+            ACCOUNTS_TABLE,
+            &filter,
+            vec![("balance", IonValue::Decimal(BigDecimal::from(1000)))],
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+
+        let statement = TransactionService::new(processor)
+            .statement(first, &StatementQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(statement.opening_balance, BigDecimal::from(940));
+        assert_eq!(statement.lines.len(), 3);
+        assert_eq!(statement.closing_balance, BigDecimal::from(1000));
+    }
+}
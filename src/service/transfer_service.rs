@@ -1,68 +1,167 @@
-use crate::core::QldbProcessor;
-use crate::domain::TransactionType;
+use super::FxService;
+use crate::core::Storage;
+use crate::domain::{
+    Account, FxConversion, IdempotencyKey, LimitPolicy, Posting, SystemAccounts,
+    TransactionReceipt, TransactionType,
+};
 use crate::error::{AppError, ErrorType};
 use bigdecimal::BigDecimal;
+use std::sync::Arc;
 
 pub struct TransferService {
-    processor: QldbProcessor,
+    processor: Storage,
+    system_accounts: SystemAccounts,
+    fx_service: FxService,
+    limits: Arc<LimitPolicy>,
 }
 
 impl TransferService {
-    pub fn new(processor: QldbProcessor) -> TransferService {
-        TransferService { processor }
+    pub fn new(
+        processor: Storage,
+        system_accounts: SystemAccounts,
+        fx_service: FxService,
+        limits: Arc<LimitPolicy>,
+    ) -> TransferService {
+        TransferService {
+            processor,
+            system_accounts,
+            fx_service,
+            limits,
+        }
+    }
+
+    pub fn credit_posting(&self, account: &Account, amount: BigDecimal) -> Posting {
+        Posting::DebitCredit {
+            account_number: account.account_number.clone(),
+            contra_account_number: self.system_accounts.cash_in_for(account.currency),
+            amount,
+            transaction_type: TransactionType::CREDIT,
+        }
+    }
+
+    pub fn debit_posting(&self, account: &Account, amount: BigDecimal) -> Posting {
+        Posting::DebitCredit {
+            account_number: account.account_number.clone(),
+            contra_account_number: self.system_accounts.cash_out_for(account.currency),
+            amount,
+            transaction_type: TransactionType::DEBIT,
+        }
+    }
+
+    pub async fn transfer_posting(
+        &self,
+        sender: &Account,
+        recipient: &Account,
+        amount: BigDecimal,
+        fx_quote_id: Option<String>,
+    ) -> Result<Posting, AppError> {
+        let conversion = self.conversion(sender, recipient, fx_quote_id).await?;
+        Ok(Posting::Transfer {
+            sender_account_number: sender.account_number.clone(),
+            recipient_account_number: recipient.account_number.clone(),
+            amount,
+            conversion,
+        })
     }
 
     pub async fn transfer(
         &self,
-        sender_account_number: String,
-        recipient_account_number: String,
+        sender: &Account,
+        recipient: &Account,
         amount: BigDecimal,
-    ) -> Result<String, AppError> {
-        let message = self
-            .processor
-            .transfer(
-                sender_account_number,
-                recipient_account_number,
-                amount.clone(),
-            )
+        fx_quote_id: Option<String>,
+        idempotency_key: Option<IdempotencyKey>,
+    ) -> Result<TransactionReceipt, AppError> {
+        let posting = self
+            .transfer_posting(sender, recipient, amount, fx_quote_id)
             .await?;
-        if message == "INSUFFICIENT_BALANCE" {
-            Err(AppError::from_type(ErrorType::InsufficientBalance))
-        } else {
-            Ok(message)
-        }
+        self.post(posting, idempotency_key).await
     }
 
-    pub async fn credit(
+    /// Posts one transaction, checked against the sending account's limits
+    /// in the ledger transaction that makes it.
+    pub async fn post(
         &self,
-        account_number: String,
-        amount: BigDecimal,
-    ) -> Result<String, AppError> {
-        let message = self
+        posting: Posting,
+        idempotency_key: Option<IdempotencyKey>,
+    ) -> Result<TransactionReceipt, AppError> {
+        let receipt = self
             .processor
-            .debit_credit(
-                account_number.clone(),
-                amount.clone(),
-                TransactionType::CREDIT,
-            )
+            .post(posting.clone(), idempotency_key, self.limits.clone())
             .await?;
-        info!("Successfully credited ${} to {}", amount, account_number);
-        Ok(message)
+        log_posting(&posting, &receipt);
+        Ok(receipt)
     }
 
-    pub async fn debit(
+    /// Posts all of the items or, if any of them fails, none.
+    pub async fn post_all(
         &self,
-        account_number: String,
-        amount: BigDecimal,
-    ) -> Result<String, AppError> {
-        let message = self
+        postings: Vec<(Posting, Option<IdempotencyKey>)>,
+    ) -> Result<Vec<TransactionReceipt>, AppError> {
+        let receipts = self
             .processor
-            .debit_credit(account_number, amount.clone(), TransactionType::DEBIT)
+            .post_all(postings.clone(), self.limits.clone())
             .await?;
-        if message == "INSUFFICIENT_BALANCE" {
-            Err(AppError::from_type(ErrorType::InsufficientBalance))
-        } else {
-            Ok(message)
+        for ((posting, _), receipt) in postings.iter().zip(&receipts) {
+            log_posting(posting, receipt);
         }
+        Ok(receipts)
+    }
+
+    /// Transfers between accounts of different currencies are converted at
+    /// the quoted rate, or the current rate when no quote is given.
+    async fn conversion(
+        &self,
+        sender: &Account,
+        recipient: &Account,
+        fx_quote_id: Option<String>,
+    ) -> Result<Option<FxConversion>, AppError> {
+        if sender.currency == recipient.currency {
+            return match fx_quote_id {
+                Some(_) => Err(AppError::new(
+                    Some("fx_quote_id is only accepted between different currencies".to_string()),
+                    ErrorType::PayloadError,
+                )),
+                None => Ok(None),
+            };
+        }
+        let rate = self
+            .fx_service
+            .rate(sender.currency, recipient.currency, fx_quote_id)
+            .await?;
+        Ok(Some(FxConversion {
+            rate,
+            sender_position: self.system_accounts.fx_position_for(sender.currency),
+            recipient_position: self.system_accounts.fx_position_for(recipient.currency),
+        }))
+    }
+}
+
+fn log_posting(posting: &Posting, receipt: &TransactionReceipt) {
+    match posting {
+        Posting::DebitCredit {
+            account_number,
+            transaction_type: TransactionType::CREDIT,
+            ..
+        } => info!(
+            "Credited {} {} to {} ({})",
+            receipt.amount, receipt.currency, account_number, receipt.transaction_id
+        ),
+        Posting::DebitCredit { account_number, .. } => info!(
+            "Debited {} {} from {} ({})",
+            receipt.amount, receipt.currency, account_number, receipt.transaction_id
+        ),
+        Posting::Transfer {
+            sender_account_number,
+            recipient_account_number,
+            ..
+        } => info!(
+            "Transferred {} {} from {} to {} ({})",
+            receipt.amount,
+            receipt.currency,
+            sender_account_number,
+            recipient_account_number,
+            receipt.transaction_id
+        ),
     }
 }
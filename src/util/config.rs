@@ -1,11 +1,119 @@
 use ::config::ConfigError;
 use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    #[default]
+    Qldb,
+    Memory,
+    Sqlite,
+}
+
 #[derive(Deserialize, Clone)]
 pub struct Config {
     pub server_port: u16,
-    pub ledger_name: String,
-    pub session_pool_size: u16,
+    /// Only needed by the qldb storage backend.
+    pub ledger_name: Option<String>,
+    pub session_pool_size: Option<u16>,
+    #[serde(default)]
+    pub storage_backend: StorageBackend,
+    #[serde(default = "default_database_path")]
+    pub database_path: String,
+    #[serde(default = "default_cash_in_account")]
+    pub cash_in_account: String,
+    #[serde(default = "default_cash_out_account")]
+    pub cash_out_account: String,
+    #[serde(default = "default_suspense_account")]
+    pub suspense_account: String,
+    #[serde(default = "default_fx_position_account")]
+    pub fx_position_account: String,
+    #[serde(default = "default_fx_rates_path")]
+    pub fx_rates_path: String,
+    #[serde(default = "default_fx_quote_ttl_seconds")]
+    pub fx_quote_ttl_seconds: i64,
+    #[serde(default = "default_hold_ttl_seconds")]
+    pub hold_ttl_seconds: i64,
+    #[serde(default = "default_approval_ttl_seconds")]
+    pub approval_ttl_seconds: i64,
+    #[serde(default = "default_limits_path")]
+    pub limits_path: String,
+    #[serde(default = "default_schedule_poll_seconds")]
+    pub schedule_poll_seconds: u64,
+    #[serde(default = "default_schedule_max_attempts")]
+    pub schedule_max_attempts: u32,
+    #[serde(default = "default_schedule_retry_delay_seconds")]
+    pub schedule_retry_delay_seconds: i64,
+    #[serde(default = "default_auth_enabled")]
+    pub auth_enabled: bool,
+    #[serde(default = "default_jwt_algorithm")]
+    pub jwt_algorithm: String,
+    pub jwt_secret: Option<String>,
+    pub jwt_public_key_path: Option<String>,
+    pub jwt_issuer: Option<String>,
+    pub jwt_audience: Option<String>,
+}
+
+fn default_database_path() -> String {
+    "bank.db".to_string()
+}
+
+fn default_cash_in_account() -> String {
+    "CASH-IN".to_string()
+}
+
+fn default_cash_out_account() -> String {
+    "CASH-OUT".to_string()
+}
+
+fn default_suspense_account() -> String {
+    "SUSPENSE".to_string()
+}
+
+fn default_fx_position_account() -> String {
+    "FX-POSITION".to_string()
+}
+
+fn default_fx_rates_path() -> String {
+    "fx_rates.txt".to_string()
+}
+
+fn default_fx_quote_ttl_seconds() -> i64 {
+    60
 }
+
+fn default_hold_ttl_seconds() -> i64 {
+    7 * 24 * 60 * 60
+}
+
+fn default_approval_ttl_seconds() -> i64 {
+    24 * 60 * 60
+}
+
+fn default_limits_path() -> String {
+    "limits.txt".to_string()
+}
+
+fn default_schedule_poll_seconds() -> u64 {
+    30
+}
+
+fn default_schedule_max_attempts() -> u32 {
+    3
+}
+
+fn default_schedule_retry_delay_seconds() -> i64 {
+    5 * 60
+}
+
+fn default_auth_enabled() -> bool {
+    true
+}
+
+fn default_jwt_algorithm() -> String {
+    "HS256".to_string()
+}
+
 impl Config {
     pub fn from_env() -> Result<Self, ConfigError> {
         let mut cfg = ::config::Config::new();
@@ -0,0 +1,196 @@
+use crate::error::{AppError, ErrorType};
+use chrono::prelude::*;
+use chrono::Duration;
+use serde::{Serialize, Serializer};
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::str::FromStr;
+
+/// How far ahead `next_after` looks before deciding an expression never
+/// matches (e.g. `0 0 30 2 *`).
+const SEARCH_DAYS: i64 = 5 * 366;
+
+/// Five field cron expression: minute, hour, day of month, month and day of
+/// week (0 or 7 is Sunday). Each field is `*`, a value, a range `a-b` or a
+/// comma separated list of those, each optionally stepped with `/n`. Times
+/// are in UTC.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronExpression {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Option<u64> {
+    let mut set = 0u64;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0)?),
+            None => (item, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (start.parse().ok()?, end.parse().ok()?)
+        } else {
+            let start = range.parse().ok()?;
+            // `5/15` runs from 5 to the end of the range
+            (start, if item.contains('/') { max } else { start })
+        };
+        if start < min || end > max || start > end {
+            return None;
+        }
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Some(set)
+}
+
+fn contains(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+impl CronExpression {
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = contains(self.days, date.day());
+        let weekday = contains(self.weekdays, date.weekday().num_days_from_sunday());
+        // As in cron, a day matches either field when both are restricted.
+        if self.any_day || self.any_weekday {
+            day && weekday
+        } else {
+            day || weekday
+        }
+    }
+
+    /// First minute strictly after `after` that the expression matches.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after
+            .naive_utc()
+            .date()
+            .and_hms(after.hour(), after.minute(), 0)
+            + Duration::minutes(1);
+        let end = start + Duration::days(SEARCH_DAYS);
+        let mut time = start;
+        while time <= end {
+            let date = time.date();
+            if !contains(self.months, time.month()) {
+                let (year, month) = match time.month() {
+                    12 => (time.year() + 1, 1),
+                    month => (time.year(), month + 1),
+                };
+                time = NaiveDate::from_ymd(year, month, 1).and_hms(0, 0, 0);
+            } else if !self.matches_day(date) {
+                time = date.succ().and_hms(0, 0, 0);
+            } else if !contains(self.hours, time.hour()) {
+                time = date.and_hms(time.hour(), 0, 0) + Duration::hours(1);
+            } else if !contains(self.minutes, time.minute()) {
+                time += Duration::minutes(1);
+            } else {
+                return Some(DateTime::from_utc(time, Utc));
+            }
+        }
+        None
+    }
+}
+
+impl FromStr for CronExpression {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            let msg = format!("Invalid cron expression: {}", s);
+            AppError::new(Some(msg), ErrorType::PayloadError)
+        };
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(invalid());
+        }
+        let mut weekdays = parse_field(fields[4], 0, 7).ok_or_else(invalid)?;
+        if contains(weekdays, 7) {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(CronExpression {
+            expression: fields.join(" "),
+            minutes: parse_field(fields[0], 0, 59).ok_or_else(invalid)?,
+            hours: parse_field(fields[1], 0, 23).ok_or_else(invalid)?,
+            days: parse_field(fields[2], 1, 31).ok_or_else(invalid)?,
+            months: parse_field(fields[3], 1, 12).ok_or_else(invalid)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+}
+
+impl Display for CronExpression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{}", self.expression)
+    }
+}
+
+impl Serialize for CronExpression {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn next(expression: &str, after: &str) -> Option<DateTime<Utc>> {
+        expression
+            .parse::<CronExpression>()
+            .unwrap()
+            .next_after(at(after))
+    }
+
+    #[test]
+    fn test_next_after() {
+        // 2021-01-29 is a Friday
+        assert_eq!(
+            next("*/15 * * * *", "2021-01-29T10:07:30Z"),
+            Some(at("2021-01-29T10:15:00Z"))
+        );
+        assert_eq!(
+            next("0 9 * * 1-5", "2021-01-29T09:00:00Z"),
+            Some(at("2021-02-01T09:00:00Z"))
+        );
+        assert_eq!(
+            next("30 8 1,15 * *", "2021-12-15T09:00:00Z"),
+            Some(at("2022-01-01T08:30:00Z"))
+        );
+        // Day of month or Sunday when both are restricted
+        assert_eq!(
+            next("0 0 13 * 7", "2021-01-29T00:00:00Z"),
+            Some(at("2021-01-31T00:00:00Z"))
+        );
+        assert_eq!(next("0 0 30 2 *", "2021-01-29T00:00:00Z"), None);
+    }
+
+    #[test]
+    fn test_invalid_expressions_are_rejected() {
+        for expression in &[
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+        ] {
+            assert!(
+                expression.parse::<CronExpression>().is_err(),
+                "{}",
+                expression
+            );
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use super::Config;
+use crate::domain::Role;
+use crate::error::{AppError, ErrorType};
+use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+
+/// Claims read from a bearer token. `exp` is required and checked by the
+/// verifier, as are `iss` and `aud` when they are configured. `accounts`
+/// lists the account numbers a customer owns.
+#[derive(Debug, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub role: Role,
+    #[serde(default)]
+    pub accounts: Vec<String>,
+}
+
+/// Checks HS256 tokens against a shared secret or RS256 tokens against a
+/// public key.
+pub struct JwtVerifier {
+    key: DecodingKey<'static>,
+    validation: Validation,
+}
+
+impl JwtVerifier {
+    pub fn new(
+        algorithm: Algorithm,
+        key: DecodingKey<'static>,
+        issuer: Option<String>,
+        audience: Option<String>,
+    ) -> JwtVerifier {
+        let mut validation = Validation::new(algorithm);
+        validation.iss = issuer;
+        if let Some(audience) = audience {
+            validation.set_audience(&[audience]);
+        }
+        JwtVerifier { key, validation }
+    }
+
+    /// The configured verifier, or `None` when no secret or public key is
+    /// set and bearer tokens are not accepted.
+    pub fn from_config(config: &Config) -> Result<Option<JwtVerifier>, AppError> {
+        let invalid = |msg: String| AppError::from_type(ErrorType::Custom(msg));
+        let key = match config.jwt_algorithm.as_str() {
+            "HS256" => match &config.jwt_secret {
+                Some(secret) => DecodingKey::from_secret(secret.as_bytes()).into_static(),
+                None => return Ok(None),
+            },
+            "RS256" => match &config.jwt_public_key_path {
+                Some(path) => {
+                    let pem = std::fs::read(path).map_err(|e| {
+                        invalid(format!("Unable to read JWT public key {}: {}", path, e))
+                    })?;
+                    DecodingKey::from_rsa_pem(&pem)
+                        .map_err(|e| invalid(format!("Invalid JWT public key {}: {}", path, e)))?
+                        .into_static()
+                }
+                None => return Ok(None),
+            },
+            algorithm => {
+                return Err(invalid(format!(
+                    "Unsupported JWT algorithm {}, expected HS256 or RS256",
+                    algorithm
+                )))
+            }
+        };
+        let algorithm = config.jwt_algorithm.parse().map_err(|_| {
+            invalid(format!(
+                "Unsupported JWT algorithm {}",
+                config.jwt_algorithm
+            ))
+        })?;
+        Ok(Some(JwtVerifier::new(
+            algorithm,
+            key,
+            config.jwt_issuer.clone(),
+            config.jwt_audience.clone(),
+        )))
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Claims, AppError> {
+        decode::<Claims>(token, &self.key, &self.validation)
+            .map(|data| data.claims)
+            .map_err(|e| {
+                debug!("Rejected bearer token: {}", e);
+                AppError::new(
+                    Some("Invalid or expired bearer token".to_string()),
+                    ErrorType::Unauthorized,
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct TestClaims<'a> {
+        sub: &'a str,
+        role: &'a str,
+        exp: i64,
+        iss: &'a str,
+        aud: &'a str,
+    }
+
+    fn token(secret: &str, iss: &str, expires_in: Duration) -> String {
+        let claims = TestClaims {
+            sub: "ops@bank",
+            role: "auditor",
+            exp: (Utc::now() + expires_in).timestamp(),
+            iss,
+            aud: "bank-service",
+        };
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_hs256_tokens_are_verified() {
+        let verifier = JwtVerifier::new(
+            Algorithm::HS256,
+            DecodingKey::from_secret(b"secret").into_static(),
+            Some("https://auth.bank".to_string()),
+            Some("bank-service".to_string()),
+        );
+        let hour = Duration::hours(1);
+
+        let claims = verifier
+            .verify(&token("secret", "https://auth.bank", hour))
+            .unwrap();
+        assert_eq!(claims.sub, "ops@bank");
+        assert_eq!(claims.role, Role::Auditor);
+        assert!(claims.accounts.is_empty());
+
+        assert!(verifier
+            .verify(&token("other", "https://auth.bank", hour))
+            .is_err());
+        assert!(verifier
+            .verify(&token("secret", "https://evil.example", hour))
+            .is_err());
+        assert!(verifier
+            .verify(&token("secret", "https://auth.bank", -hour))
+            .is_err());
+        assert!(verifier.verify("not.a.token").is_err());
+    }
+}
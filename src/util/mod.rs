@@ -1,5 +1,9 @@
 mod config;
+mod cron;
+mod jwt;
 mod rand_util;
 
-pub use self::config::Config;
-pub use rand_util::generate_numeric;
+pub use self::config::{Config, StorageBackend};
+pub use cron::CronExpression;
+pub use jwt::JwtVerifier;
+pub use rand_util::{generate_alphanumeric, generate_numeric};
@@ -2,6 +2,8 @@ use crate::error::AppError;
 use rand::{distributions::Uniform, Rng};
 
 const NUM_CHAR: &[u8] = "0123456789".as_bytes();
+const ALPHANUM_CHAR: &[u8] =
+    "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz".as_bytes();
 
 fn generate(characters: &[u8], length: u32) -> Result<String, AppError> {
     let rng = rand::thread_rng();
@@ -17,3 +19,7 @@ fn generate(characters: &[u8], length: u32) -> Result<String, AppError> {
 pub fn generate_numeric(length: u32) -> Result<String, AppError> {
     generate(NUM_CHAR, length)
 }
+
+pub fn generate_alphanumeric(length: u32) -> Result<String, AppError> {
+    generate(ALPHANUM_CHAR, length)
+}